@@ -1,66 +1,467 @@
-use std::iter;
+// Every workload runs on each of the trees and on BTreeSet and HashSet, which
+// are the baselines to beat. Besides criterion's own reports, the run leaves
+// timings.csv and memory.csv next to them, with one row per set and size
 
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use criterion::measurement::WallTime;
+use criterion::{black_box, criterion_group, BenchmarkGroup, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use rust_black_trees::btree::BTree;
 use rust_black_trees::tree::Tree;
+use rust_black_trees::{
+    aatree::AATree, scapegoattree::ScapegoatTree, splaytree::SplayTree, treap::Treap,
+};
 use rust_black_trees::{avltree::AVLTree, rbtree::RBTree, unbalancetree::BSTree};
 
-fn bench_rbtree(c: &mut Criterion) {
-    let mut group = c.benchmark_group("bench_rbtree");
+/// counts the bytes allocated and not yet freed, so footprint can tell how
+/// much a set holds on to
+struct Counting;
 
-    let mut rbtree = RBTree::new();
-    for tree_size in [10000, 40000, 70000, 100000, 130000].iter() {
-        //        group.throughput(Throughput::Bytes(*tree_size));
-        group.bench_with_input(
-            BenchmarkId::from_parameter(tree_size),
-            tree_size,
-            |b, &tree_size| b.iter(|| insert_n_elements_and_search_lowest(&mut rbtree, tree_size)),
-        );
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LIVE.fetch_add(new_size, Relaxed);
+        LIVE.fetch_sub(layout.size(), Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// what the workloads need from a set, so the trees and the baselines all
+/// run the same code
+trait Set {
+    const NAME: &'static str;
+    fn new() -> Self;
+    fn insert(&mut self, v: u64);
+    fn remove(&mut self, v: u64);
+    /// sums the values in lo..hi, so the scan can't be optimised away
+    fn scan(&self, lo: u64, hi: u64) -> u64;
+}
+
+macro_rules! tree_set {
+    ($($t:ident: $name:expr),*) => {$(
+        impl Set for $t<u64> {
+            const NAME: &'static str = $name;
+
+            fn new() -> Self {
+                Tree::new()
+            }
+
+            fn insert(&mut self, v: u64) {
+                Tree::insert(self, v)
+            }
+
+            fn remove(&mut self, v: u64) {
+                Tree::delete(self, v);
+            }
+
+            fn scan(&self, lo: u64, hi: u64) -> u64 {
+                self.range(lo..hi).sum()
+            }
+        }
+    )*};
+}
+
+tree_set!(
+    RBTree: "rb",
+    AVLTree: "avl",
+    BSTree: "bst",
+    SplayTree: "splay",
+    Treap: "treap",
+    AATree: "aa",
+    ScapegoatTree: "sg"
+);
+
+// two fanouts, to see how much wider nodes buy
+macro_rules! btree_set {
+    ($($b:literal: $name:expr),*) => {$(
+        impl Set for BTree<u64, $b> {
+            const NAME: &'static str = $name;
+
+            fn new() -> Self {
+                BTree::new()
+            }
+
+            fn insert(&mut self, v: u64) {
+                BTree::insert(self, v)
+            }
+
+            fn remove(&mut self, v: u64) {
+                BTree::delete(self, v);
+            }
+
+            fn scan(&self, lo: u64, hi: u64) -> u64 {
+                self.range(lo..hi).sum()
+            }
+        }
+    )*};
+}
+
+btree_set!(16: "btree16", 64: "btree64");
+
+impl Set for BTreeSet<u64> {
+    const NAME: &'static str = "btreeset";
+
+    fn new() -> Self {
+        BTreeSet::new()
+    }
+
+    fn insert(&mut self, v: u64) {
+        BTreeSet::insert(self, v);
+    }
+
+    fn remove(&mut self, v: u64) {
+        BTreeSet::remove(self, &v);
+    }
+
+    fn scan(&self, lo: u64, hi: u64) -> u64 {
+        self.range(lo..hi).sum()
+    }
+}
+
+impl Set for HashSet<u64> {
+    const NAME: &'static str = "hashset";
+
+    fn new() -> Self {
+        HashSet::new()
+    }
+
+    fn insert(&mut self, v: u64) {
+        HashSet::insert(self, v);
+    }
+
+    fn remove(&mut self, v: u64) {
+        HashSet::remove(self, &v);
+    }
+
+    // without an order every value has to be looked at
+    fn scan(&self, lo: u64, hi: u64) -> u64 {
+        self.iter().filter(|v| (lo..hi).contains(*v)).sum()
+    }
+}
+
+/// runs $bench once for every set, trees first and then the baselines
+macro_rules! for_each_set {
+    ($bench:ident($($arg:expr),*)) => {
+        $bench::<RBTree<u64>>($($arg),*);
+        $bench::<AVLTree<u64>>($($arg),*);
+        $bench::<BSTree<u64>>($($arg),*);
+        $bench::<SplayTree<u64>>($($arg),*);
+        $bench::<Treap<u64>>($($arg),*);
+        $bench::<AATree<u64>>($($arg),*);
+        $bench::<ScapegoatTree<u64>>($($arg),*);
+        $bench::<BTree<u64, 16>>($($arg),*);
+        $bench::<BTree<u64, 64>>($($arg),*);
+        $bench::<BTreeSet<u64>>($($arg),*);
+        $bench::<HashSet<u64>>($($arg),*);
+    };
+}
+
+/// one benchmark's share of timings.csv
+struct Timing {
+    group: &'static str,
+    set: &'static str,
+    size: usize,
+    /// how many operations a single iteration does
+    ops: u64,
+    iters: u64,
+    total: Duration,
+}
+
+/// the bytes a set of size values held on to, for memory.csv
+struct Footprint {
+    set: &'static str,
+    size: usize,
+    bytes: usize,
+}
+
+static TIMINGS: Mutex<Vec<Timing>> = Mutex::new(Vec::new());
+static FOOTPRINTS: Mutex<Vec<Footprint>> = Mutex::new(Vec::new());
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+// a sorted insert takes the unbalanced tree quadratic time, which rules out
+// the biggest size
+const WORST_SIZES: [usize; 2] = [1_000, 10_000];
+
+fn configure(group: &mut BenchmarkGroup<'_, WallTime>) {
+    group
+        .sample_size(10)
+        .warm_up_time(Duration::from_secs(1))
+        .measurement_time(Duration::from_secs(3));
+}
+
+/**
+ * Benchmarks routine on S, timing only the routine itself. Every iteration
+ * gets a fresh input from setup, so no iteration sees the work of the one
+ * before, and neither setup nor dropping what the routine returns is
+ * counted. The time taken goes towards timings.csv as well
+ */
+fn timed<S, I, O>(
+    group: &mut BenchmarkGroup<'_, WallTime>,
+    name: &'static str,
+    size: usize,
+    ops: u64,
+    mut setup: impl FnMut() -> I,
+    mut routine: impl FnMut(I) -> O,
+) where
+    S: Set,
+{
+    group.throughput(Throughput::Elements(ops));
+    group.bench_function(BenchmarkId::new(S::NAME, size), |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::default();
+            for _ in 0..iters {
+                let input = setup();
+                let start = Instant::now();
+                let out = black_box(routine(input));
+                total += start.elapsed();
+                drop(out);
+            }
+            TIMINGS.lock().unwrap().push(Timing {
+                group: name,
+                set: S::NAME,
+                size,
+                ops,
+                iters,
+                total,
+            });
+            total
+        })
+    });
+}
+
+fn build<S: Set>(values: &[u64]) -> S {
+    let mut set = S::new();
+    for v in values {
+        set.insert(*v);
+    }
+    set
+}
+
+fn random_values(n: usize, seed: u64) -> Vec<u64> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n).map(|_| rng.gen()).collect()
+}
+
+fn insert<S: Set>(group: &mut BenchmarkGroup<'_, WallTime>, values: &[u64]) {
+    timed::<S, _, _>(
+        group,
+        "random_insert",
+        values.len(),
+        values.len() as u64,
+        || (),
+        |_| build::<S>(values),
+    );
+
+    let before = LIVE.load(Relaxed);
+    let set = build::<S>(values);
+    let bytes = LIVE.load(Relaxed).saturating_sub(before);
+    drop(set);
+    FOOTPRINTS.lock().unwrap().push(Footprint {
+        set: S::NAME,
+        size: values.len(),
+        bytes,
+    });
+}
+
+/// builds sets out of random values, and measures how much memory each
+/// kind of set takes per value
+fn random_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("random_insert");
+    configure(&mut group);
+    for &n in SIZES.iter() {
+        let values = random_values(n, 1);
+        for_each_set!(insert(&mut group, &values));
     }
     group.finish();
 }
 
-fn bench_avltree(c: &mut Criterion) {
-    let mut group = c.benchmark_group("bench_avltree");
+fn churn<S: Set>(group: &mut BenchmarkGroup<'_, WallTime>, old: &[u64], new: &[u64]) {
+    let ops = (old.len() + new.len()) as u64;
+    timed::<S, _, _>(
+        group,
+        "interleaved",
+        old.len(),
+        ops,
+        || build::<S>(old),
+        |mut set| {
+            for (o, n) in old.iter().zip(new) {
+                set.insert(*n);
+                set.remove(*o);
+            }
+            set
+        },
+    );
+}
 
-    let mut avltree = AVLTree::new();
-    for tree_size in [10000, 40000, 70000, 100000, 130000].iter() {
-        //        group.throughput(Throughput::Bytes(*tree_size));
-        group.bench_with_input(
-            BenchmarkId::from_parameter(tree_size),
-            tree_size,
-            |b, &tree_size| b.iter(|| insert_n_elements_and_search_lowest(&mut avltree, tree_size)),
-        );
+/// alternates inserting a new value with deleting the oldest one, so the
+/// set stays the same size while every value in it is replaced
+fn interleaved(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interleaved");
+    configure(&mut group);
+    for &n in SIZES.iter() {
+        let old = random_values(n, 2);
+        let new = random_values(n, 3);
+        for_each_set!(churn(&mut group, &old, &new));
     }
     group.finish();
 }
 
-fn bench_bstree(c: &mut Criterion) {
-    let mut group = c.benchmark_group("bench_bstree");
+fn scans<S: Set>(group: &mut BenchmarkGroup<'_, WallTime>, values: &[u64], windows: &[(u64, u64)]) {
+    let set = build::<S>(values);
+    // each window holds a hundredth of the values
+    let ops = windows.len() as u64 * (values.len() / 100) as u64;
+    timed::<S, _, _>(
+        group,
+        "range_scan",
+        values.len(),
+        ops,
+        || (),
+        |_| {
+            windows
+                .iter()
+                .map(|&(lo, hi)| set.scan(lo, hi))
+                .sum::<u64>()
+        },
+    );
+}
 
-    let mut bstree = BSTree::new();
-    for tree_size in [10000, 40000, 70000, 100000, 130000].iter() {
-        //        group.throughput(Throughput::Bytes(*tree_size));
-        group.bench_with_input(
-            BenchmarkId::from_parameter(tree_size),
-            tree_size,
-            |b, &tree_size| b.iter(|| insert_n_elements_and_search_lowest(&mut bstree, tree_size)),
-        );
+/// sums the values in 100 windows that each cover a hundredth of the set.
+/// The values are 0..n in random order, so every window is full
+fn range_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("range_scan");
+    configure(&mut group);
+    let mut rng = StdRng::seed_from_u64(4);
+    for &n in SIZES.iter() {
+        let mut values: Vec<u64> = (0..n as u64).collect();
+        values.shuffle(&mut rng);
+        let width = n as u64 / 100;
+        let windows: Vec<(u64, u64)> = (0..100)
+            .map(|_| rng.gen_range(0, n as u64 - width))
+            .map(|lo| (lo, lo + width))
+            .collect();
+        for_each_set!(scans(&mut group, &values, &windows));
     }
     group.finish();
 }
-criterion_group!(benches, bench_rbtree, bench_avltree, bench_bstree);
-criterion_main!(benches);
 
-fn insert_n_elements_and_search_lowest<T>(tree: &mut T, num_times: usize)
-where
-    T: Tree<usize>,
-{
-    for i in 0..num_times {
-        tree.insert(i);
+fn ordered<S: Set>(group: &mut BenchmarkGroup<'_, WallTime>, name: &'static str, values: &[u64]) {
+    timed::<S, _, _>(
+        group,
+        name,
+        values.len(),
+        values.len() as u64,
+        || (),
+        |_| build::<S>(values),
+    );
+}
+
+/// the values 0..n in some order
+type Order = fn(u64) -> Vec<u64>;
+
+/// inserts in the orders that hurt unbalanced trees the most: ascending,
+/// descending, and zigzagging in from both ends
+fn worst_case(c: &mut Criterion) {
+    let orders: [(&'static str, Order); 3] = [
+        ("worst_case_ascending", |n| (0..n).collect()),
+        ("worst_case_descending", |n| (0..n).rev().collect()),
+        ("worst_case_zigzag", |n| {
+            (0..n)
+                .map(|i| if i % 2 == 0 { i / 2 } else { n - 1 - i / 2 })
+                .collect()
+        }),
+    ];
+    for &(name, order) in orders.iter() {
+        let mut group = c.benchmark_group(name);
+        configure(&mut group);
+        for &n in WORST_SIZES.iter() {
+            let values = order(n as u64);
+            for_each_set!(ordered(&mut group, name, &values));
+        }
+        group.finish();
+    }
+}
+
+/// where criterion writes its reports, which is where the summaries go too
+fn output_dir() -> PathBuf {
+    match std::env::var_os("CARGO_TARGET_DIR") {
+        Some(dir) => PathBuf::from(dir).join("criterion"),
+        None => PathBuf::from("target/criterion"),
+    }
+}
+
+/**
+ * Writes timings.csv, with the mean time an iteration of each benchmark took
+ * over every iteration that was timed, warm up included, and memory.csv. A
+ * benchmark that a filter skipped has no row
+ */
+fn write_summaries() {
+    let mut totals: BTreeMap<(&str, &str, usize), (u64, u64, Duration)> = BTreeMap::new();
+    for t in TIMINGS.lock().unwrap().iter() {
+        let entry =
+            totals
+                .entry((t.group, t.set, t.size))
+                .or_insert((t.ops, 0, Duration::default()));
+        entry.1 += t.iters;
+        entry.2 += t.total;
+    }
+    let mut timings = String::from("group,set,size,iterations,mean_ns,ns_per_op\n");
+    for ((group, set, size), (ops, iters, total)) in totals {
+        let mean = total.as_nanos() as f64 / iters as f64;
+        timings += &format!(
+            "{},{},{},{},{:.0},{:.2}\n",
+            group,
+            set,
+            size,
+            iters,
+            mean,
+            mean / ops as f64
+        );
     }
 
-    for i in 0..num_times / 10 {
-        tree.contains(&i);
+    let mut memory = String::from("set,size,bytes,bytes_per_element\n");
+    for f in FOOTPRINTS.lock().unwrap().iter() {
+        let per = f.bytes as f64 / f.size as f64;
+        memory += &format!("{},{},{},{:.1}\n", f.set, f.size, f.bytes, per);
     }
+
+    let dir = output_dir();
+    let written = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(dir.join("timings.csv"), timings))
+        .and_then(|_| fs::write(dir.join("memory.csv"), memory));
+    match written {
+        Ok(()) => println!("Summaries written to {}", dir.display()),
+        Err(e) => eprintln!("Could not write summaries to {}: {}", dir.display(), e),
+    }
+}
+
+criterion_group!(benches, random_insert, interleaved, range_scan, worst_case);
+
+// criterion_main, with the summaries written once every group has run
+fn main() {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    write_summaries();
 }
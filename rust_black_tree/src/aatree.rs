@@ -0,0 +1,525 @@
+use std::cmp::min;
+use std::fmt::Debug;
+
+use super::comparator::{Comparator, Natural};
+use super::tree::BaseTree;
+use super::tree::Tree;
+use super::tree::{
+    compact, in_order, sorted_arena, swap_values, validate_links, IntoIter, Iter,
+};
+use super::tree::InvariantError;
+use super::snapshot::{
+    read_snapshot, write_snapshot, SnapshotError, SnapshotKind, SnapshotNode, SnapshotValue,
+};
+
+use super::node::Node;
+use super::node::*;
+
+/// a nice convenient macro which allows a user to initialize a tree with
+/// a number of elements
+/// usage: aa!{1, 2, 3, 4, 5, 6, 7, 8, 9, 0};
+#[macro_export]
+macro_rules! aa {
+    ( $( $x:expr ),* ) => {
+        {
+            let mut temp_tree = AATree::new();
+            $(
+                temp_tree.insert($x);
+            )*
+            temp_tree
+        }
+    };
+}
+
+#[derive(Debug)]
+pub struct AANode<T> {
+    pub value: Option<T>,
+    pub ptr: usize,
+    pub parent: Option<usize>,
+    pub lchild: Option<usize>,
+    pub rchild: Option<usize>,
+    /// leaves are on level 1, and a missing child counts as level 0
+    pub level: usize,
+    /// number of nodes in the subtree rooted here, including this one
+    pub size: usize,
+}
+
+impl<T> AANode<T> {
+    fn new(val: T, selfptr: usize) -> Self {
+        Self {
+            value: Some(val),
+            ptr: selfptr,
+            parent: None,
+            lchild: None,
+            rchild: None,
+            level: 1,
+            size: 1,
+        }
+    }
+}
+
+impl<T> Node<T> for AANode<T> {
+    fn to_self_string(&self) -> String
+    where
+        T: Debug,
+    {
+        format!("[P:{:?} L:{} V:{:?}]", self.parent, self.level, self.get_value())
+    }
+
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug,
+    {
+        let s = format!("{:?}", self.get_value());
+        let l = s.len();
+        (s, l)
+    }
+
+    // a right child on its parent's level is what a red node is to a red
+    // black tree, so it is drawn the same way
+    fn export_style(&self, data: &[Self]) -> NodeStyle
+    where
+        T: Debug,
+    {
+        let horizontal = matches!(self.parent, Some(p) if data[p].level == self.level);
+        NodeStyle {
+            label: format!("{:?}", self.get_value()),
+            fill: if horizontal { "red" } else { "black" },
+            font: "white",
+            attributes: vec![("level", self.level.to_string())],
+        }
+    }
+
+    fn get_value(&self) -> &T {
+        self.value.as_ref().expect("freed node")
+    }
+    fn get_value_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("freed node")
+    }
+    fn into_value(self) -> T {
+        self.value.expect("freed node")
+    }
+    fn take_value(&mut self) -> T {
+        self.value.take().expect("freed node")
+    }
+    fn get_child(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Left => self.lchild,
+            Side::Right => self.rchild,
+        }
+    }
+
+    fn set_child(&mut self, child: usize, side: Side) {
+        self.set_child_opt(Some(child), side)
+    }
+
+    fn set_child_opt(&mut self, c: Option<usize>, side: Side) {
+        match side {
+            Side::Left => self.lchild = c,
+            Side::Right => self.rchild = c,
+        };
+    }
+    fn set_parent(&mut self, p: Option<usize>) {
+        self.parent = p;
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn location(&self) -> usize {
+        self.ptr
+    }
+
+    fn set_location(&mut self, ptr: usize) {
+        self.ptr = ptr;
+    }
+
+    fn subtree_size(&self) -> usize {
+        self.size
+    }
+
+    fn set_subtree_size(&mut self, size: usize) {
+        self.size = size;
+    }
+}
+
+/**
+ * Arena based memory tree structure. An AA tree is a red black tree where
+ * only right children may be red, tracked as a level per node instead of a
+ * color. With half the cases gone, both insert and delete come down to the
+ * same two rotations, skew and split, applied on the way back up
+*/
+#[derive(Debug)]
+pub struct AATree<T, C = Natural> {
+    root: Option<usize>,
+    size: usize,
+    data: Vec<AANode<T>>,
+    free: Vec<usize>,
+    cmp: C,
+}
+
+/**
+ * Links a sorted arena into a balanced AA tree. Unlike link_balanced, the
+ * middle is rounded down so the right half is the one holding any extra node,
+ * since a left child must always sit a full level lower. Each node's level
+ * is the length of the shortest path down from it, so a right child is at
+ * most one level lower, and level with its parent only when the right half
+ * is perfect, which keeps its own right child lower again
+ */
+fn link_levels<T>(data: &mut [AANode<T>], lo: usize, hi: usize) -> Option<usize> {
+    if lo >= hi {
+        return None;
+    }
+    let mid = lo + (hi - lo - 1) / 2;
+    let left = link_levels(data, lo, mid);
+    let right = link_levels(data, mid + 1, hi);
+    for c in left.iter().chain(right.iter()) {
+        data[*c].parent = Some(mid);
+    }
+    data[mid].lchild = left;
+    data[mid].rchild = right;
+    data[mid].size = hi - lo;
+    data[mid].level = left.map_or(0, |l| data[l].level) + 1;
+    Some(mid)
+}
+
+impl<T> SnapshotNode<T> for AANode<T> {
+    const KIND: SnapshotKind = SnapshotKind::AA;
+
+    fn blank(val: T, ptr: usize) -> Self {
+        AANode::new(val, ptr)
+    }
+
+    fn write_extra(&self, out: &mut Vec<u8>) {
+        self.level.write(out);
+    }
+
+    fn read_extra(&mut self, input: &mut &[u8]) -> Result<(), SnapshotError> {
+        self.level = usize::read(input)?;
+        Ok(())
+    }
+}
+
+impl<T, C: Comparator<T>> Tree<T> for AATree<T, C> {
+    fn with_comparator(cmp: C) -> Self {
+        Self {
+            root: None,
+            data: Vec::new(),
+            size: 0,
+            free: Vec::new(),
+            cmp,
+        }
+    }
+
+    /**
+     * Besides the shared checks, every node has to sit one level above its
+     * left child, on the level of its right child or one above it, and
+     * strictly above its right grandchild
+     */
+    fn validate(&self) -> Result<(), InvariantError> {
+        validate_links(self)?;
+        for n in in_order(self) {
+            let node = self.get(n);
+            let right = node.rchild.map(|r| self.get(r));
+            let rlevel = self.level(node.rchild);
+            let fine = self.level(node.lchild) + 1 == node.level
+                && rlevel <= node.level
+                && rlevel + 1 >= node.level
+                && self.level(right.and_then(|r| r.rchild)) < node.level;
+            if !fine {
+                return Err(InvariantError::LevelWrong {
+                    node: n,
+                    level: node.level,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Self {
+        let mut data = sorted_arena(iter, &cmp, AANode::new);
+        let len = data.len();
+        let root = link_levels(&mut data, 0, len);
+        Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp,
+        }
+    }
+}
+
+impl<T, C: Comparator<T>> BaseTree<T> for AATree<T, C> {
+    type MNode = AANode<T>;
+    type Cmp = C;
+    fn get(&self, val: usize) -> &Self::MNode {
+        &self.data[val]
+    }
+
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode {
+        &mut self.data[val]
+    }
+
+    fn get_data(&self) -> &[Self::MNode] {
+        &self.data
+    }
+
+    fn get_root(&self) -> Option<usize> {
+        self.root
+    }
+
+    fn set_root(&mut self, new_root: Option<usize>) {
+        self.root = new_root
+    }
+
+    fn crement_size(&mut self, amount: isize) {
+        self.size = (self.size as isize + amount) as usize;
+    }
+
+    fn rebalance_ins(&mut self, n: usize) {
+        let mut next = self.get(n).parent;
+        while let Some(p) = next {
+            let top = self.skew(p);
+            let top = self.split(top);
+            next = self.get(top).parent;
+        }
+    }
+
+    // removing n can leave its ancestors too high above their children. Each
+    // one on the way up is lowered where needed, and the horizontal links
+    // that creates are fixed with up to three skews and two splits
+    fn rebalance_del(&mut self, n: usize, _child: usize) {
+        let parent = self.get(n).parent;
+        let child = self.get(n).lchild.or(self.get(n).rchild);
+        self.replace_node(n, child);
+        let mut next = parent;
+        while let Some(p) = next {
+            self.decrease_level(p);
+            let top = self.skew(p);
+            if let Some(r) = self.get(top).rchild {
+                let r = self.skew(r);
+                if let Some(rr) = self.get(r).rchild {
+                    self.skew(rr);
+                }
+            }
+            let top = self.split(top);
+            if let Some(r) = self.get(top).rchild {
+                self.split(r);
+            }
+            next = self.get(top).parent;
+        }
+    }
+
+    fn delete_replace(&mut self, n: usize) -> usize {
+        match (self.get(n).lchild, self.get(n).rchild) {
+            (Some(_lc), Some(rc)) => {
+                let successor = self.get(rc).find_min(&self.data);
+                swap_values(&mut self.data, n, successor);
+                successor
+            }
+            _ => n,
+        }
+    }
+
+    fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>) {
+        let parent = self.get(to_delete).parent;
+        if let Some(p) = parent {
+            let side = self.get(to_delete).side(&self.data);
+            self.get_mut(p).set_child_opt(to_attach, side);
+        } else {
+            self.root = to_attach;
+        }
+        if let Some(c) = to_attach {
+            self.get_mut(c).parent = parent;
+        }
+    }
+
+    fn get_size(&self) -> usize {
+        self.size
+    }
+
+    fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    fn create_node(&mut self, val: T) -> usize {
+        if let Some(n) = self.free.pop() {
+            self.data[n] = AANode::new(val, n);
+            n
+        } else {
+            let loc = self.data.len();
+            self.data.push(AANode::new(val, loc));
+            loc
+        }
+    }
+
+    fn delete_node(&mut self, index: usize) -> T {
+        self.free.push(index);
+        self.data[index].take_value()
+    }
+}
+
+impl<T: PartialOrd> AATree<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_by(iter, Natural)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        let (data, root) = read_snapshot(bytes)?;
+        let tree = Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp: Natural,
+        };
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd> Default for AATree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, C: Comparator<T>> AATree<T, C> {
+    /// a binary snapshot keeping the exact shape and levels of the tree
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        write_snapshot(&self.data, self.root)
+    }
+
+    fn level(&self, n: Option<usize>) -> usize {
+        n.map_or(0, |n| self.get(n).level)
+    }
+
+    // a left child on the level of its parent is turned into a right
+    // parent. Returns whichever node now tops the subtree
+    fn skew(&mut self, n: usize) -> usize {
+        match self.get(n).lchild {
+            Some(l) if self.get(l).level == self.get(n).level => {
+                self.rotate(Side::Right, l);
+                l
+            }
+            _ => n,
+        }
+    }
+
+    // two right children in a row on one level: the middle one is rotated up
+    // and raised a level. Returns whichever node now tops the subtree
+    fn split(&mut self, n: usize) -> usize {
+        if let Some(r) = self.get(n).rchild {
+            if self.level(self.get(r).rchild) == self.get(n).level {
+                self.rotate(Side::Left, r);
+                self.get_mut(r).level += 1;
+                return r;
+            }
+        }
+        n
+    }
+
+    fn decrease_level(&mut self, n: usize) {
+        let node = self.get(n);
+        let should = min(self.level(node.lchild), self.level(node.rchild)) + 1;
+        if should < node.level {
+            self.get_mut(n).level = should;
+            if let Some(r) = self.get(n).rchild {
+                if self.get(r).level > should {
+                    self.get_mut(r).level = should;
+                }
+            }
+        }
+    }
+
+    /// moves every live node to the front of the arena and drops the freed
+    /// slots
+    pub fn shrink_to_fit(&mut self) {
+        compact(&mut self.data, &mut self.root);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+}
+
+impl<T, C: Comparator<T>> IntoIterator for AATree<T, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let order = in_order(&self);
+        IntoIter::new(self.data, order)
+    }
+}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a AATree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, AATree<T, C>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert() {
+        let mut tree = AATree::new();
+        for x in 0..7 {
+            tree.insert(x);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert_eq!(tree.to_string(), "([P:None L:3 V:3] ([P:Some(3) L:2 V:1] ([P:Some(1) L:1 V:0] () ()) ([P:Some(1) L:1 V:2] () ())) ([P:Some(3) L:2 V:5] ([P:Some(5) L:1 V:4] () ()) ([P:Some(5) L:1 V:6] () ())))");
+    }
+
+    #[test]
+    fn delete() {
+        let mut tree = AATree::new();
+        for x in 0..300 {
+            tree.insert((x * 37) % 300);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.get_height() <= 2 * 9);
+        for x in (0..300).filter(|x| x % 4 != 1) {
+            assert!(tree.delete(x));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), (1..300).step_by(4).collect::<Vec<_>>());
+        while tree.pop_first().is_some() {
+            assert_eq!(tree.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        for n in 0..70 {
+            let tree = AATree::from_sorted_iter(0..n);
+            assert_eq!(tree.validate(), Ok(()), "{} values", n);
+        }
+
+        let mut tree = AATree::from_sorted_iter(0..10);
+        let r = tree.root.unwrap();
+        tree.data[r].level += 1;
+        assert_eq!(
+            tree.validate(),
+            Err(InvariantError::LevelWrong {
+                node: r,
+                level: tree.data[r].level
+            })
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::cmp::Ordering;
+use std::ops::{Add, Bound, RangeBounds};
+
+use super::node::{Node, Side};
+use super::tree::{BaseTree, InvariantError};
+
+/**
+ * A summary each node keeps of the values in its subtree, like their sum.
+ * combine builds it from a node's value and the summaries of its children,
+ * and the balanced trees call it again for every node whose subtree changes,
+ * including the ones a rotation moves.
+ *
+ * A rotation keeps the same values in the same order but groups them
+ * differently, so combine has to be associative, the way sums, minimums and
+ * counts are, or the summary would depend on the shape of the tree
+ */
+pub trait Augment<T> {
+    type Summary: PartialEq;
+
+    fn combine(
+        val: &T,
+        left: Option<&Self::Summary>,
+        right: Option<&Self::Summary>,
+    ) -> Self::Summary;
+}
+
+/// keeps nothing, for trees that don't need a summary
+impl<T> Augment<T> for () {
+    type Summary = ();
+
+    fn combine(_val: &T, _left: Option<&()>, _right: Option<&()>) {}
+}
+
+/// The sum of the values in each subtree
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sum;
+
+impl<T: Clone + PartialEq + Add<Output = T>> Augment<T> for Sum {
+    type Summary = T;
+
+    fn combine(val: &T, left: Option<&T>, right: Option<&T>) -> T {
+        let mut sum = val.clone();
+        if let Some(l) = left {
+            sum = l.clone() + sum;
+        }
+        if let Some(r) = right {
+            sum = sum + r.clone();
+        }
+        sum
+    }
+}
+
+/// A node that carries a summary for its tree's Augment
+pub trait Summarized<S> {
+    fn summary(&self) -> &S;
+    fn set_summary(&mut self, summary: S);
+}
+
+/// the summary n should have, going by its value and its children
+pub(crate) fn combine_at<T, A, N>(data: &[N], n: usize) -> A::Summary
+where
+    A: Augment<T>,
+    N: Node<T> + Summarized<A::Summary>,
+{
+    let node = &data[n];
+    let child = |side| node.get_child(side).map(|c| data[c].summary());
+    A::combine(node.get_value(), child(Side::Left), child(Side::Right))
+}
+
+/// checks that every node's summary matches what its children add up to
+pub(crate) fn validate_summaries<T, A, N>(
+    data: &[N],
+    root: Option<usize>,
+) -> Result<(), InvariantError>
+where
+    A: Augment<T>,
+    N: Node<T> + Summarized<A::Summary>,
+{
+    let mut stack: Vec<usize> = root.into_iter().collect();
+    while let Some(n) = stack.pop() {
+        if *data[n].summary() != combine_at::<T, A, N>(data, n) {
+            return Err(InvariantError::SummaryWrong { node: n });
+        }
+        stack.extend(data[n].get_child(Side::Left));
+        stack.extend(data[n].get_child(Side::Right));
+    }
+    Ok(())
+}
+
+/**
+ * Folds the values inside of range. The search walks down to the highest
+ * node inside the range, then down each of its sides to the two ends. Every
+ * subtree hanging off of those paths on the inside lies wholly in the range,
+ * so its stored summary stands in for it, and only O(log n) nodes get
+ * combined
+ */
+pub(crate) fn fold_range<T, A, B, R>(tree: &B, range: R) -> Option<A::Summary>
+where
+    A: Augment<T>,
+    B: BaseTree<T>,
+    B::MNode: Summarized<A::Summary>,
+    R: RangeBounds<T>,
+{
+    let data = tree.get_data();
+    let above_start = |v: &T| match range.start_bound() {
+        Bound::Included(s) => tree.compare(v, s) != Ordering::Less,
+        Bound::Excluded(s) => tree.compare(v, s) == Ordering::Greater,
+        Bound::Unbounded => true,
+    };
+    let below_end = |v: &T| match range.end_bound() {
+        Bound::Included(e) => tree.compare(v, e) != Ordering::Greater,
+        Bound::Excluded(e) => tree.compare(v, e) == Ordering::Less,
+        Bound::Unbounded => true,
+    };
+
+    let mut next = tree.get_root();
+    let split = loop {
+        let node = &data[next?];
+        if !above_start(node.get_value()) {
+            next = node.get_child(Side::Right);
+        } else if !below_end(node.get_value()) {
+            next = node.get_child(Side::Left);
+        } else {
+            break node;
+        }
+    };
+
+    // everything in the subtree at n is below the end of the range
+    fn from<T, A: Augment<T>, N: Node<T> + Summarized<A::Summary>>(
+        data: &[N],
+        n: Option<usize>,
+        inside: &dyn Fn(&T) -> bool,
+    ) -> Option<A::Summary> {
+        let node = &data[n?];
+        if inside(node.get_value()) {
+            let left = from::<T, A, N>(data, node.get_child(Side::Left), inside);
+            let right = node.get_child(Side::Right).map(|c| data[c].summary());
+            Some(A::combine(node.get_value(), left.as_ref(), right))
+        } else {
+            from::<T, A, N>(data, node.get_child(Side::Right), inside)
+        }
+    }
+
+    // everything in the subtree at n is above the start of the range
+    fn to<T, A: Augment<T>, N: Node<T> + Summarized<A::Summary>>(
+        data: &[N],
+        n: Option<usize>,
+        inside: &dyn Fn(&T) -> bool,
+    ) -> Option<A::Summary> {
+        let node = &data[n?];
+        if inside(node.get_value()) {
+            let left = node.get_child(Side::Left).map(|c| data[c].summary());
+            let right = to::<T, A, N>(data, node.get_child(Side::Right), inside);
+            Some(A::combine(node.get_value(), left, right.as_ref()))
+        } else {
+            to::<T, A, N>(data, node.get_child(Side::Left), inside)
+        }
+    }
+
+    let left = from::<T, A, B::MNode>(data, split.get_child(Side::Left), &above_start);
+    let right = to::<T, A, B::MNode>(data, split.get_child(Side::Right), &below_end);
+    Some(A::combine(split.get_value(), left.as_ref(), right.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::avltree::AVLTree;
+    use super::super::comparator::Natural;
+    use super::super::rbtree::RBTree;
+    use super::super::tree::{BaseTree, Tree};
+    use super::*;
+
+    /// the smallest and largest values below each node, to check that the
+    /// halves of a fold are put together in order
+    struct Span;
+
+    impl Augment<i32> for Span {
+        type Summary = (i32, i32);
+
+        fn combine(val: &i32, left: Option<&(i32, i32)>, right: Option<&(i32, i32)>) -> (i32, i32) {
+            (left.map_or(*val, |l| l.0), right.map_or(*val, |r| r.1))
+        }
+    }
+
+    fn brute(values: &[i64], lo: i64, hi: i64) -> Option<i64> {
+        let inside: Vec<i64> = values
+            .iter()
+            .cloned()
+            .filter(|v| lo <= *v && *v < hi)
+            .collect();
+        if inside.is_empty() {
+            None
+        } else {
+            Some(inside.iter().sum())
+        }
+    }
+
+    #[test]
+    fn sums() {
+        let mut rb: RBTree<i64, Natural, Sum> = RBTree::default();
+        let mut avl: AVLTree<i64, Natural, Sum> = AVLTree::default();
+        let mut values = Vec::new();
+        let mut x: i64 = 7;
+        for _ in 0..300 {
+            x = x * 31 % 1009;
+            rb.insert(x);
+            avl.insert(x);
+            if !values.contains(&x) {
+                values.push(x);
+            }
+        }
+        for v in values.clone().iter().step_by(3) {
+            assert!(rb.delete(*v));
+            assert!(avl.delete(*v));
+            values.retain(|x| x != v);
+        }
+        assert_eq!(rb.validate(), Ok(()));
+        assert_eq!(avl.validate(), Ok(()));
+        assert_eq!(rb.summary(), Some(&values.iter().sum()));
+        assert_eq!(avl.summary(), rb.summary());
+
+        for (lo, hi) in [
+            (0, 1009),
+            (100, 200),
+            (500, 501),
+            (-5, 3),
+            (1000, 2000),
+            (300, 300),
+        ] {
+            assert_eq!(rb.fold_range(lo..hi), brute(&values, lo, hi));
+            assert_eq!(avl.fold_range(lo..hi), brute(&values, lo, hi));
+        }
+        assert_eq!(rb.fold_range(..), rb.summary().cloned());
+        assert_eq!(avl.fold_range(..=100), brute(&values, 0, 101));
+    }
+
+    #[test]
+    fn keeps_order() {
+        let tree: RBTree<i32, Natural, Span> = RBTree::from_sorted_iter_by(0..50, Natural);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.summary(), Some(&(0, 49)));
+        assert_eq!(tree.fold_range(10..20), Some((10, 19)));
+        assert_eq!(tree.fold_range(45..), Some((45, 49)));
+        assert_eq!(tree.fold_range(60..), None);
+
+        let mut avl: AVLTree<i32, Natural, Span> = AVLTree::default();
+        for x in (0..50).rev() {
+            avl.insert(x);
+        }
+        avl.delete(0);
+        assert_eq!(avl.fold_range(..=10), Some((1, 10)));
+        assert_eq!(avl.validate(), Ok(()));
+    }
+
+    #[test]
+    fn stale_summary() {
+        let mut tree: RBTree<i64, Natural, Sum> = RBTree::from_sorted_iter_by(1..10, Natural);
+        let root = tree.get_root().unwrap();
+        tree.get_mut(root).set_summary(0);
+        assert_eq!(
+            tree.validate(),
+            Err(InvariantError::SummaryWrong { node: root })
+        );
+    }
+}
@@ -1,15 +1,26 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+use super::augment::{combine_at, fold_range, validate_summaries, Augment, Summarized};
+use super::comparator::{Comparator, Natural};
+use super::cursor::Cursors;
+use super::join::{self, link, Join, Subtree};
 use super::node::{endpaint, paint};
 use super::tree::BaseTree;
 use super::tree::Tree;
+use super::tree::{
+    compact, in_order, link_balanced, sorted_arena, swap_values, validate_links, IntoIter, Iter,
+};
+use super::tree::InvariantError;
+use super::tree::{Event, Observer};
+use super::snapshot::{
+    read_snapshot, write_snapshot, SnapshotError, SnapshotKind, SnapshotNode, SnapshotValue,
+};
 
 use super::node::Node;
 use super::node::*;
 
-const TREE_END: usize = 0xFFFFFFFF;
-
 /// a nice convenient macro which allows a user to initialize a tree with
 /// a number of elements
 /// usage: redblack!{1, 2, 3, 4, 5, 6, 7, 8, 9, 0};
@@ -27,41 +38,67 @@ macro_rules! avl {
 }
 
 #[derive(Debug)]
-pub struct AVLNode<T> {
-    pub value: T,
+pub struct AVLNode<T, S = ()> {
+    pub value: Option<T>,
     pub ptr: usize,
     pub parent: Option<usize>,
     pub lchild: Option<usize>,
     pub rchild: Option<usize>,
-    data: Rc<RefCell<Vec<AVLNode<T>>>>,
     // For AVL nodes...
     pub height: usize,
     pub balance_factor: isize,
+    /// number of nodes in the subtree rooted here, including this one
+    pub size: usize,
+    /// what the tree's Augment made of the values in this subtree
+    pub summary: S,
 }
 
 impl<T> AVLNode<T> {
-    fn new(val: T, selfptr: usize, data: Rc<RefCell<Vec<AVLNode<T>>>>) -> Self {
+    fn new(val: T, selfptr: usize) -> Self {
+        Self::with_summary(val, selfptr, ())
+    }
+}
+
+impl<T, S> AVLNode<T, S> {
+    fn with_summary(val: T, selfptr: usize, summary: S) -> Self {
         Self {
-            value: val,
+            value: Some(val),
             ptr: selfptr,
             parent: None,
             lchild: None,
             rchild: None,
-            data: data,
             height: 1,
             balance_factor: 0,
+            size: 1,
+            summary,
         }
     }
 }
 
-impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for AVLNode<T> {
-    fn to_self_string(&self) -> String {
+impl<T, S> Summarized<S> for AVLNode<T, S> {
+    fn summary(&self) -> &S {
+        &self.summary
+    }
+
+    fn set_summary(&mut self, summary: S) {
+        self.summary = summary;
+    }
+}
+
+impl<T, S> Node<T> for AVLNode<T, S> {
+    fn to_self_string(&self) -> String
+    where
+        T: Debug,
+    {
         format!(
             "[V:{:?} H:{:?} BF:{:?}]",
-            self.value, self.height, self.balance_factor
+            self.get_value(), self.height, self.balance_factor
         )
     }
-    fn to_self_string_display(&self) -> (String, usize) {
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug,
+    {
         const GRN: usize = 2;
         const YEL: usize = 3;
         const BLU: usize = 4;
@@ -79,38 +116,47 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for AVLNode<T> {
             format!(
                 "{}{:?}{}",
                 paint(FG + BLK, BG + col),
-                self.value,
+                self.get_value(),
                 endpaint()
             ),
-            format!("{:?}", self.value).len(),
+            format!("{:?}", self.get_value()).len(),
         )
     }
-    fn get_value(&self) -> &T {
-        &self.value
+
+    fn export_style(&self, _data: &[Self]) -> NodeStyle
+    where
+        T: Debug,
+    {
+        NodeStyle {
+            label: format!("{:?}", self.get_value()),
+            fill: match self.balance_factor {
+                -1 => "lightblue",
+                1 => "khaki",
+                0 => "palegreen",
+                _ => "white",
+            },
+            font: "black",
+            attributes: vec![
+                ("bf", self.balance_factor.to_string()),
+                ("height", self.height.to_string()),
+            ],
+        }
     }
 
-    fn is(&self, val: &T) -> bool {
-        &self.value == val
+    fn get_balance_factor(&self) -> Option<isize> {
+        Some(self.balance_factor)
     }
-    fn greater(&self, val: &T) -> bool {
-        &self.value > val
+    fn get_value(&self) -> &T {
+        self.value.as_ref().expect("freed node")
     }
-    fn lesser(&self, val: &T) -> bool {
-        &self.value < val
+    fn get_value_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("freed node")
     }
-
-    /**
-     * In order to return a reference to a value of a vector contained within a
-     * refcell, a raw pointer is used. The unsafe code could be avoided by
-     * replacing each call to self.get(n) with &self.data.borrow()[n] and each call
-     * to self.get_mut(n) with &mut self.data.borrow()[n]
-     */
-    fn get(&self, ptr: usize) -> &AVLNode<T> {
-        unsafe { &(*self.data.as_ptr())[ptr] }
+    fn into_value(self) -> T {
+        self.value.expect("freed node")
     }
-
-    fn get_mut(&self, ptr: usize) -> &mut AVLNode<T> {
-        unsafe { &mut (*self.data.as_ptr())[ptr] }
+    fn take_value(&mut self) -> T {
+        self.value.take().expect("freed node")
     }
 
     fn get_child(&self, side: Side) -> Option<usize> {
@@ -129,9 +175,6 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for AVLNode<T> {
             Side::Left => self.lchild = c,
             Side::Right => self.rchild = c,
         };
-        if let Some(child) = c {
-            self.get_mut(child).parent = Some(self.location());
-        }
     }
     fn set_parent(&mut self, p: Option<usize>) {
         self.parent = p;
@@ -144,57 +187,154 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for AVLNode<T> {
     fn location(&self) -> usize {
         self.ptr
     }
+
+    fn set_location(&mut self, ptr: usize) {
+        self.ptr = ptr;
+    }
+
+    fn subtree_size(&self) -> usize {
+        self.size
+    }
+
+    fn set_subtree_size(&mut self, size: usize) {
+        self.size = size;
+    }
 }
 
 /**
- * Arena based memory tree structure
+ * Arena based memory tree structure, with the summary A makes of each
+ * subtree kept in its top node
 */
 #[derive(Debug)]
-pub struct AVLTree<T> {
+pub struct AVLTree<T, C = Natural, A: Augment<T> = ()> {
     root: Option<usize>,
     size: usize,
-    data: Rc<RefCell<Vec<AVLNode<T>>>>,
+    data: Vec<AVLNode<T, A::Summary>>,
     free: Vec<usize>,
+    cmp: C,
+    augment: PhantomData<A>,
+    observer: Observer<Self>,
 }
 
-impl<T> Tree<T> for AVLTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
-    fn new() -> Self {
+impl<T> SnapshotNode<T> for AVLNode<T> {
+    const KIND: SnapshotKind = SnapshotKind::AVL;
+
+    fn blank(val: T, ptr: usize) -> Self {
+        AVLNode::new(val, ptr)
+    }
+
+    fn write_extra(&self, out: &mut Vec<u8>) {
+        (self.balance_factor as i64).write(out);
+        self.height.write(out);
+    }
+
+    fn read_extra(&mut self, input: &mut &[u8]) -> Result<(), SnapshotError> {
+        self.balance_factor = isize::read(input)?;
+        self.height = usize::read(input)?;
+        Ok(())
+    }
+}
+
+impl<T, C: Comparator<T>, A: Augment<T>> Tree<T> for AVLTree<T, C, A> {
+    fn with_comparator(cmp: C) -> Self {
         Self {
             root: None,
-            data: Rc::new(RefCell::new(Vec::new())),
+            data: Vec::new(),
             size: 0,
             free: Vec::new(),
+            cmp,
+            augment: PhantomData,
+            observer: Observer::default(),
         }
     }
+
+    fn validate(&self) -> Result<(), InvariantError> {
+        validate_links(self)?;
+        self.root.map_or(Ok(0), |r| self.checked_height(r))?;
+        validate_summaries::<T, A, _>(&self.data, self.root)
+    }
+
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Self {
+        let mut data = sorted_arena(iter, &cmp, |val, n| {
+            let summary = A::combine(&val, None, None);
+            AVLNode::with_summary(val, n, summary)
+        });
+        let root = link_balanced(&mut data, &mut |data: &mut [AVLNode<T, A::Summary>], n, _depth| {
+            let height = |c: Option<usize>| c.map(|c| data[c].height).unwrap_or(0);
+            let (lh, rh) = (height(data[n].lchild), height(data[n].rchild));
+            data[n].height = std::cmp::max(lh, rh) + 1;
+            data[n].balance_factor = rh as isize - lh as isize;
+            data[n].summary = combine_at::<T, A, _>(data, n);
+        });
+        Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp,
+            augment: PhantomData,
+            observer: Observer::default(),
+        }
+    }
+
+    // split, join and the set operations go through Join instead of
+    // rebuilding both trees the way the defaults do
+    fn split(self, val: &T) -> (Self, Self)
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::split(self, val)
+    }
+
+    fn join(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::join(self, other)
+    }
+
+    fn union(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::union(self, other)
+    }
+
+    fn intersection(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::intersection(self, other)
+    }
+
+    fn difference(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::difference(self, other)
+    }
 }
 
-impl<T> BaseTree<T> for AVLTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
-    type MNode = AVLNode<T>;
-    /**
-     * In order to return a reference to a value of a vector contained within a refcell, a raw
-     * pointer is used. The unsafe code could be avoided by replacing each call to self.get(n) with
-     * &self.data.borrow()[n] and each call to self.get_mut(n) with &mut self.data.borrow()[n]. This
-     * allows us to do the same thing with less keystrokes. It does make the program not
-     * thread-safe, but a this data structure is a pretty terrible choice for a multi-threaded data
-     * structure anyways, since re-balancing can require that most of the tree be locked to one
-     * thread during an insertion or deletion
-     */
+impl<T, C: Comparator<T>, A: Augment<T>> Cursors<T> for AVLTree<T, C, A> {}
+
+impl<T, C: Comparator<T>, A: Augment<T>> BaseTree<T> for AVLTree<T, C, A> {
+    type MNode = AVLNode<T, A::Summary>;
+    type Cmp = C;
     fn get(&self, val: usize) -> &Self::MNode {
-        unsafe { &(*self.data.as_ptr())[val] }
+        &self.data[val]
+    }
+
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode {
+        &mut self.data[val]
     }
 
-    fn get_mut(&self, val: usize) -> &mut Self::MNode {
-        unsafe { &mut (*self.data.as_ptr())[val] }
+    fn get_data(&self) -> &[Self::MNode] {
+        &self.data
     }
 
     fn get_root(&self) -> Option<usize> {
@@ -209,8 +349,8 @@ where
         self.size = (self.size as isize + amount) as usize;
     }
 
-    fn attach_child(&self, p: usize, c: usize, side: Side) {
-        self.get_mut(p).set_child(c, side)
+    fn update_summary(&mut self, n: usize) {
+        self.data[n].summary = combine_at::<T, A, _>(&self.data, n);
     }
 
     fn rebalance_ins(&mut self, n: usize) {
@@ -218,106 +358,192 @@ where
     }
 
     fn rebalance_del(&mut self, n: usize, _child: usize) {
-        if self.get_mut(n).ptr == TREE_END {
-            self.slow_delete();
-        } else {
-            self.del_retrace(n);
-            if let Some(r) = self.root {
-                self.traverse_to_fix(r);
-            }
-        }
+        let parent = self.get(n).parent;
+        let child = self.get(n).lchild.or(self.get(n).rchild);
+        self.replace_node(n, child);
+        self.del_retrace(parent);
     }
 
     fn delete_replace(&mut self, n: usize) -> usize {
-        self.get_mut(n).ptr = TREE_END;
-        n
+        // a node with two children trades values with its successor, which
+        // has at most one child, and the successor gets removed instead
+        match (self.get(n).lchild, self.get(n).rchild) {
+            (Some(_lc), Some(rc)) => {
+                let successor = self.get(rc).find_min(&self.data);
+                swap_values(&mut self.data, n, successor);
+                successor
+            }
+            _ => n,
+        }
     }
 
-    fn replace_node(&mut self, _to_delete: usize, _to_attach: Option<usize>) {
+    fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>) {
+        let parent = self.get(to_delete).parent;
+        if let Some(p) = parent {
+            let side = self.get(to_delete).side(&self.data);
+            self.get_mut(p).set_child_opt(to_attach, side);
+        } else {
+            self.root = to_attach;
+        }
+        if let Some(c) = to_attach {
+            self.get_mut(c).parent = parent;
+        }
     }
 
     fn get_size(&self) -> usize {
-        return self.size;
+        self.size
+    }
+
+    fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    fn observer(&mut self) -> Option<&mut Observer<Self>> {
+        Some(&mut self.observer)
     }
 
     fn create_node(&mut self, val: T) -> usize {
-        let loc = self.data.borrow().len();
-        self.data
-            .borrow_mut()
-            .push(AVLNode::new(val, loc, self.data.clone()));
-        loc
+        let summary = A::combine(&val, None, None);
+        if let Some(n) = self.free.pop() {
+            self.data[n] = AVLNode::with_summary(val, n, summary);
+            n
+        } else {
+            let loc = self.data.len();
+            self.data.push(AVLNode::with_summary(val, loc, summary));
+            loc
+        }
     }
 
-    fn delete_node(&mut self, index: usize) {
+    fn delete_node(&mut self, index: usize) -> T {
         self.free.push(index);
+        self.data[index].take_value()
     }
 }
 
-impl<T> AVLTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
-    fn del_retrace(&mut self, n: usize) {
-        loop {
-            let x = self.get(n).parent;
-            if !x.is_some() {
-                return;
-            }
-            let x: usize = x.expect("Deletion retrace get z parent");
-            //println!("n v:{:?}", self.get(n).value);
-            //println!("{}", self.to_pretty_string());
-            if self.get(n).is_child(Side::Left) {
-                if self.is_heavy_on_side(Side::Right, x) {
-                    // Sibling of N (higher by 2)
-                    if let Some(z) = self.get(n).get_sibling() {
-                        if self.is_heavy_on_side(Side::Left, z) {
-                            self.avl_rotate(Side::Right, z);
-                            self.avl_rotate(Side::Left, x);
-                        } else {
-                            self.avl_rotate(Side::Left, x);
-                        }
-                    } else {
-                        //println!("THIS IS SKETCHY");
-                        //self.del_retrace(x);
-                        self.avl_rotate(Side::Left, x);
-                    }
-                } else {
-                    if self.calc_bal_fac(x) == 0 {
-                        self.set_balance_factor(x, 1);
-                        break;
-                    }
-                    self.set_balance_factor(n, 0);
-                    //N = X; //
-                    self.del_retrace(x);
+impl<T: PartialOrd> AVLTree<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_by(iter, Natural)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        let (data, root) = read_snapshot(bytes)?;
+        let tree = Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp: Natural,
+            augment: PhantomData,
+            observer: Observer::default(),
+        };
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd, A: Augment<T>> Default for AVLTree<T, Natural, A> {
+    fn default() -> Self {
+        Self::with_comparator(Natural)
+    }
+}
+
+impl<T, C: Comparator<T>> AVLTree<T, C> {
+    /// Tree::with_comparator for a tree without a summary, so that the
+    /// comparator alone settles the type
+    pub fn with_comparator(cmp: C) -> Self {
+        Tree::with_comparator(cmp)
+    }
+
+    /// a binary snapshot keeping the exact shape of the tree along with the
+    /// stored heights and balance factors, even ones that have gone wrong
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        write_snapshot(&self.data, self.root)
+    }
+}
+
+impl<T, C: Comparator<T>, A: Augment<T>> AVLTree<T, C, A> {
+    /// calls f after every insert, delete, rotation and balance factor
+    /// change, with the tree as it is at that point
+    pub fn set_observer<F>(&mut self, f: F)
+    where
+        F: FnMut(&Event, &Self) + Send + Sync + 'static,
+    {
+        self.observer = Observer::new(f);
+    }
+
+    /// stops reporting events
+    pub fn clear_observer(&mut self) {
+        self.observer = Observer::default();
+    }
+
+    /// the summary of the whole tree
+    pub fn summary(&self) -> Option<&A::Summary> {
+        self.root.map(|r| &self.data[r].summary)
+    }
+
+    /// the summary of the values inside of range, in O(log n)
+    pub fn fold_range<R: RangeBounds<T>>(&self, range: R) -> Option<A::Summary> {
+        fold_range::<T, A, _, _>(self, range)
+    }
+
+    // the real height of the subtree at n, found while checking that every
+    // balance factor below agrees with it
+    fn checked_height(&self, n: usize) -> Result<usize, InvariantError> {
+        let node = self.get(n);
+        let lh = node.lchild.map_or(Ok(0), |c| self.checked_height(c))?;
+        let rh = node.rchild.map_or(Ok(0), |c| self.checked_height(c))?;
+        let actual = rh as isize - lh as isize;
+        if node.balance_factor != actual || actual.abs() > 1 {
+            return Err(InvariantError::BalanceFactorWrong {
+                node: n,
+                stored: node.balance_factor,
+                actual,
+            });
+        }
+        Ok(std::cmp::max(lh, rh) + 1)
+    }
+
+    /// compacts the arena so it holds only the nodes still in the tree
+    pub fn shrink_to_fit(&mut self) {
+        compact(&mut self.data, &mut self.root);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+
+    // walks up from the parent of a removed node, fixing heights and
+    // rotating any node that has become two levels out of balance
+    fn del_retrace(&mut self, start: Option<usize>) {
+        let mut next = start;
+        while let Some(x) = next {
+            self.fix_bf(x);
+            let top = if self.get_balance_factor(x) > 1 {
+                let z = self.get(x).get_child(Side::Right).expect("del retrace z");
+                if self.is_heavy_on_side(Side::Left, z) {
+                    self.avl_rotate(Side::Right, z);
                 }
-            } else {
-                if self.is_heavy_on_side(Side::Left, x) {
-                    // Sibling of N (higher by 2)
-                    if let Some(z) = self.get(n).get_sibling() {
-                        if self.is_heavy_on_side(Side::Right, z) {
-                            self.avl_rotate(Side::Left, z);
-                            self.avl_rotate(Side::Right, x);
-                        } else {
-                            self.avl_rotate(Side::Right, x);
-                        }
-                    } else {
-                        //println!("THIS IS SKETCHY");
-                        //self.del_retrace(x);
-                        self.avl_rotate(Side::Right, x);
-                    }
-                } else {
-                    if self.calc_bal_fac(x) == 0 {
-                        self.set_balance_factor(x, -1);
-                        break; // Leave the loop
-                    }
-                    self.set_balance_factor(n, 0);
-                    //N = X;
-                    self.del_retrace(x);
+                self.avl_rotate(Side::Left, x);
+                self.get(x).parent.expect("rotated x parent")
+            } else if self.get_balance_factor(x) < -1 {
+                let z = self.get(x).get_child(Side::Left).expect("del retrace z");
+                if self.is_heavy_on_side(Side::Right, z) {
+                    self.avl_rotate(Side::Left, z);
                 }
-            }
-            break;
+                self.avl_rotate(Side::Right, x);
+                self.get(x).parent.expect("rotated x parent")
+            } else {
+                x
+            };
+            next = self.get(top).parent;
         }
     }
 
@@ -326,14 +552,14 @@ where
         //println!("X= {:?}", self.get(x).value);
         // get the parent of current node
         let x = self.get(z).parent;
-        if !x.is_some() {
+        if x.is_none() {
             // current node z is the root of the tree
             // nothing to do, return?
             return;
         }
         let x: usize = x.expect("Retrace get z parent");
 
-        if self.get(z).is_child(Side::Right) {
+        if self.get(z).is_child(Side::Right, &self.data) {
             // The right subtree increases
             if self.is_heavy_on_side(Side::Right, x) {
                 if self.is_heavy_on_side(Side::Left, z) {
@@ -348,10 +574,10 @@ where
                 }
             } else {
                 if self.is_heavy_on_side(Side::Left, x) {
-                    self.set_balance_factor(x, 0);
+                    self.fix_bf(x);
                     return;
                 }
-                self.set_balance_factor(x, 1);
+                self.fix_bf(x);
                 //Z = X; // Height(Z) increases by 1
                 //z = x;
                 self.retrace(x);
@@ -367,10 +593,10 @@ where
                 }
             } else {
                 if self.is_heavy_on_side(Side::Right, x) {
-                    self.set_balance_factor(x, 0);
+                    self.fix_bf(x);
                     return; // Leave the loop
                 }
-                self.set_balance_factor(x, -1);
+                self.fix_bf(x);
                 //Z = X; // Height(Z) increases by 1
                 //z = x;
                 self.retrace(x);
@@ -378,7 +604,6 @@ where
             }
         }
         //self.retrace(x);
-        return;
         // Unless loop is left via break, the height of the total tree increases by 1.
     }
 
@@ -391,18 +616,9 @@ where
         //            self.to_pretty_string());
         if let Some(z) = self.get(n).get_child(!side) {
             self.rotate(side, z);
-            //self.traverse_to_fix(self.root.unwrap());
-            self.traverse_to_fix(z);
-        //            match self.calc_bal_fac(z) {
-        //                0 => {
-        //                    self.set_balance_factor(n, 1);
-        //                    self.set_balance_factor(z, -1);
-        //                }
-        //                _ => {
-        //                    self.set_balance_factor(n, 0);
-        //                    self.set_balance_factor(z, 0);
-        //                }
-        //            }
+            // only n and z changed subtrees, and n is now below z
+            self.fix_bf(n);
+            self.fix_bf(z);
         } else {
             //panic!("avl rotate unwrap");
             println!("tried to rotate on None");
@@ -414,19 +630,10 @@ where
     }
 
     fn set_balance_factor(&mut self, n: usize, bf: isize) {
-        self.get_mut(n).balance_factor = bf;
-    }
-
-    fn calc_bal_fac(&self, n: usize) -> isize {
-        let rc = self.get(n).get_child(Side::Right);
-        let lc = self.get(n).get_child(Side::Left);
-        let safe_get_bf = |x| match x {
-            Some(y) => self.get_balance_factor(y),
-            None => 0,
-        };
-        let bf_rc = safe_get_bf(rc);
-        let bf_lc = safe_get_bf(lc);
-        bf_rc - bf_lc
+        if self.get(n).balance_factor != bf {
+            self.get_mut(n).balance_factor = bf;
+            self.emit(Event::BalanceFactorChanged(n, bf));
+        }
     }
 
     fn is_heavy_on_side(&self, side: Side, n: usize) -> bool {
@@ -437,21 +644,6 @@ where
         }
     }
 
-    fn slow_delete(&mut self) {
-        let mut t = AVLTree::new();
-        let mut v = self.data.borrow_mut().pop();
-        while v.is_some() {
-            let n = v.unwrap();
-            if n.ptr != TREE_END {
-                t.insert(n.value);
-            }
-            v = self.data.borrow_mut().pop();
-        }
-
-        *self = t;
-        self.size += 1;
-    }
-
     fn fix_bf(&mut self, n: usize) {
         let rc = self.get(n).get_child(Side::Right);
         let lc = self.get(n).get_child(Side::Left);
@@ -475,20 +667,84 @@ where
         };
         self.get_mut(n).height = std::cmp::max(lch, rch) + 1;
         self.set_balance_factor(n, rch as isize - lch as isize);
+        // every node retrace or a rotation touches comes through here
+        self.update_summary(n);
     }
+}
 
-    fn traverse_to_fix(&mut self, n: usize) {
-        /*if !self.get(n).is_some() {
-            return;
-        }*/
-        if let Some(c) = self.get(n).get_child(Side::Left) {
-            self.traverse_to_fix(c);
-        }
+impl<T, C: Comparator<T>, A: Augment<T>> Join<T> for AVLTree<T, C, A> {
+    // the height, which every node keeps
+    fn rank_of(&self, root: Option<usize>) -> usize {
+        root.map_or(0, |r| self.get(r).height)
+    }
 
-        if let Some(c) = self.get(n).get_child(Side::Right) {
-            self.traverse_to_fix(c);
+    fn child_rank(&self, n: usize, _rank: usize, side: Side) -> usize {
+        self.rank_of(self.get(n).get_child(side))
+    }
+
+    fn join_at(&mut self, left: Subtree, mid: usize, right: Subtree) -> Subtree {
+        if left.rank <= right.rank + 1 && right.rank <= left.rank + 1 {
+            link(self, left.root, mid, right.root);
+            self.fix_bf(mid);
+            return Subtree {
+                root: Some(mid),
+                rank: self.get(mid).height,
+            };
+        }
+        // walk down the inner edge of the taller tree to the first subtree
+        // that is at most one level taller than the shorter tree
+        let (tall, short, side) = if left.rank > right.rank {
+            (left, right, Side::Right)
+        } else {
+            (right, left, Side::Left)
+        };
+        let mut parent = tall.root.expect("a taller tree has a root");
+        let mut next = self.get(parent).get_child(side);
+        while self.rank_of(next) > short.rank + 1 {
+            parent = next.expect("a height above 0 has a node");
+            next = self.get(parent).get_child(side);
+        }
+        if let Some(c) = next {
+            self.data[c].parent = None;
+        }
+        match side {
+            Side::Right => link(self, next, mid, short.root),
+            Side::Left => link(self, short.root, mid, next),
+        }
+        self.fix_bf(mid);
+        let added = self.data[mid].size - next.map_or(0, |c| self.data[c].size);
+        self.attach_child(parent, mid, side);
+        self.add_size_to_path(parent, added as isize);
+        // mid stands one level taller than what it replaced, which can
+        // leave the nodes above it out of balance the way a delete can
+        self.del_retrace(Some(parent));
+        let mut top = mid;
+        while let Some(p) = self.get(top).parent {
+            top = p;
+        }
+        Subtree {
+            root: Some(top),
+            rank: self.get(top).height,
         }
-        self.fix_bf(n);
+    }
+}
+
+impl<T, C: Comparator<T>, A: Augment<T>> IntoIterator for AVLTree<T, C, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let order = in_order(&self);
+        IntoIter::new(self.data, order)
+    }
+}
+
+impl<'a, T, C: Comparator<T>, A: Augment<T>> IntoIterator for &'a AVLTree<T, C, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, AVLTree<T, C, A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
@@ -507,8 +763,8 @@ mod tests {
         tree.insert(1);
         let root = tree.root.expect("tree root");
         assert_eq!(tree.get_balance_factor(root), 0);
-        assert!(tree.is_heavy_on_side(Side::Right, root) == false);
-        assert!(tree.is_heavy_on_side(Side::Left, root) == false);
+        assert!(!tree.is_heavy_on_side(Side::Right, root));
+        assert!(!tree.is_heavy_on_side(Side::Left, root));
     }
 
     #[test]
@@ -574,7 +830,7 @@ mod tests {
         tree.insert(4);
         tree.insert(6);
 
-        for i in vec![1, 3, 5, 7] {
+        for i in [1, 3, 5, 7] {
             println!("Adding and removing leaf v={}", i);
             tree.insert(i);
             tree.delete(i);
@@ -584,4 +840,168 @@ mod tests {
             );
         }
     }
+
+    // checks the stored height and balance factor of every node against the
+    // actual subtree and that no node is out of balance
+    fn check_balance(tree: &AVLTree<i32>, n: Option<usize>) -> usize {
+        match n {
+            None => 0,
+            Some(n) => {
+                let l = check_balance(tree, tree.get(n).lchild);
+                let r = check_balance(tree, tree.get(n).rchild);
+                let bf = r as isize - l as isize;
+                assert_eq!(tree.get_balance_factor(n), bf);
+                assert!(bf.abs() <= 1);
+                assert_eq!(tree.get(n).height, 1 + l.max(r));
+                assert_eq!(tree.get(n).size, tree.get(n).get_size(&tree.data));
+                1 + l.max(r)
+            }
+        }
+    }
+
+    #[test]
+    fn avl_del_many() {
+        let mut tree = AVLTree::<i32>::new();
+        for x in 0..100 {
+            tree.insert((x * 37) % 100);
+        }
+        for x in (0..100).filter(|x| x % 3 != 0) {
+            tree.delete(x);
+            check_balance(&tree, tree.root);
+        }
+        let expected: Vec<i32> = (0..100).filter(|x| x % 3 == 0).collect();
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), expected);
+        assert_eq!(tree.rank(&51), 17);
+        assert_eq!(tree.select(17), Some(&51));
+        assert_eq!(tree.count_range(10..=20), 3);
+
+        for x in (0..100).filter(|x| x % 3 != 0) {
+            tree.insert(x);
+        }
+        assert_eq!(tree.data.len(), 100);
+        check_balance(&tree, tree.root);
+
+        for x in 50..100 {
+            tree.delete(x);
+        }
+        tree.shrink_to_fit();
+        assert_eq!(tree.data.len(), 50);
+        check_balance(&tree, tree.root);
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn avl_from_sorted_iter() {
+        for len in 0..40 {
+            let tree = AVLTree::from_sorted_iter(0..len);
+            check_balance(&tree, tree.root);
+            assert_eq!(tree.iter().count(), len as usize);
+        }
+        let mut tree = AVLTree::from_sorted_iter((0..50).map(|x| x * 2));
+        tree.insert(51);
+        tree.delete(0);
+        check_balance(&tree, tree.root);
+
+        let (low, high) = tree.split(&51);
+        check_balance(&low, low.root);
+        assert_eq!(low.get_size(), 25);
+        assert_eq!(high.select(0), Some(&51));
+        let other = AVLTree::from_sorted_iter(0..10);
+        let tree = low.join(high).union(other);
+        check_balance(&tree, tree.root);
+        assert_eq!(tree.get_size(), 56);
+    }
+
+    #[test]
+    fn avl_validate() {
+        let mut tree = AVLTree::new();
+        for x in 0..30 {
+            tree.insert(x);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        let r = tree.root.unwrap();
+        tree.data[r].balance_factor = 1;
+        assert_eq!(
+            tree.validate(),
+            Err(InvariantError::BalanceFactorWrong {
+                node: r,
+                stored: 1,
+                actual: 0
+            })
+        );
+
+        let mut tree = AVLTree::new();
+        for x in 0..3 {
+            tree.insert(x);
+        }
+        // hang 0 under 2 by hand, leaving the tree a straight line
+        tree.data[1].lchild = None;
+        tree.data[1].size = 2;
+        tree.data[2].lchild = Some(0);
+        tree.data[0].parent = Some(2);
+        assert!(matches!(
+            tree.validate(),
+            Err(InvariantError::OrderViolation { .. })
+        ));
+    }
+
+    #[test]
+    fn avl_iter() {
+        let mut tree = AVLTree::<i32>::new();
+        for x in vec![5, 3, 8, 1, 4, 7, 9, 2, 6] {
+            tree.insert(x);
+        }
+        let v: Vec<i32> = tree.iter().cloned().collect();
+        assert_eq!(v, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let v: Vec<i32> = tree.iter().rev().cloned().collect();
+        assert_eq!(v, vec![9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        let v: Vec<&i32> = tree.range(3..7).collect();
+        assert_eq!(v, vec![&3, &4, &5, &6]);
+        let v: Vec<&i32> = tree.range(7..).rev().collect();
+        assert_eq!(v, vec![&9, &8, &7]);
+
+        tree.delete(5);
+        let v: Vec<i32> = tree.into_iter().collect();
+        assert_eq!(v, vec![1, 2, 3, 4, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn observer() {
+        use std::sync::{Arc, Mutex};
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tree = AVLTree::new();
+        let seen = log.clone();
+        tree.set_observer(move |event, _tree: &AVLTree<i32>| {
+            seen.lock().unwrap().push(*event);
+        });
+        tree.insert(3);
+        tree.insert(2);
+        tree.insert(1);
+        let events = log.lock().unwrap().clone();
+        assert_eq!(events[0], Event::Inserted(0));
+        assert!(events.contains(&Event::BalanceFactorChanged(0, -1)));
+        assert!(events.contains(&Event::Rotated(Side::Right, 1)));
+        // 3 went back to being balanced once it was rotated down, while 1
+        // never changed from the 0 it started with
+        assert!(events.contains(&Event::BalanceFactorChanged(0, 0)));
+        assert!(events.iter().all(|e| *e != Event::BalanceFactorChanged(2, 0)));
+    }
+
+    #[test]
+    fn send_to_thread() {
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<AVLTree<i32>>();
+
+        let mut tree = AVLTree::new();
+        for x in 0..50 {
+            tree.insert(x);
+        }
+        let handle = std::thread::spawn(move || {
+            tree.delete(25);
+            tree
+        });
+        let tree = handle.join().unwrap();
+        assert!(!tree.contains(&25));
+        assert_eq!(tree.iter().count(), 49);
+    }
 }
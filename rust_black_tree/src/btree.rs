@@ -0,0 +1,1485 @@
+use std::cmp::{max, Ordering};
+use std::fmt::{self, Debug};
+use std::mem::{self, MaybeUninit};
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::{ptr, slice};
+
+use super::comparator::{Comparator, Natural};
+use super::snapshot::{read_header, write_header, SnapshotError, SnapshotKind, SnapshotValue};
+use super::tree::InvariantError;
+use super::treeexport::{dot_escape, xml_escape};
+use super::treestats::TreeStats;
+
+/// a nice convenient macro which allows a user to initialize a tree with
+/// a number of elements
+/// usage: btree!{1, 2, 3, 4, 5, 6, 7, 8, 9, 0};
+#[macro_export]
+macro_rules! btree {
+    ( $( $x:expr ),* ) => {
+        {
+            let mut temp_tree: BTree<_> = BTree::new();
+            $(
+                temp_tree.insert($x);
+            )*
+            temp_tree
+        }
+    };
+}
+
+/**
+ * The keys of a node, stored inside the node itself rather than behind a
+ * pointer of their own. There is room for B of them, one more than a node
+ * keeps, since a node takes the key that overflows it before it splits. The
+ * first len slots hold keys and the rest are uninitialized, which is the one
+ * invariant every unsafe block here relies on
+ */
+pub struct Keys<T, const B: usize> {
+    len: usize,
+    slots: [MaybeUninit<T>; B],
+}
+
+impl<T, const B: usize> Keys<T, B> {
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            slots: [(); B].map(|_| MaybeUninit::uninit()),
+        }
+    }
+
+    fn ptr(&mut self) -> *mut T {
+        self.slots.as_mut_ptr() as *mut T
+    }
+
+    pub fn push(&mut self, val: T) {
+        assert!(self.len < B, "a node holds at most {} keys", B);
+        self.slots[self.len] = MaybeUninit::new(val);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // SAFETY: the slot was the last initialized one, and is outside of
+        // len now, so it won't be read or dropped again
+        Some(unsafe { ptr::read(self.slots[self.len].as_ptr()) })
+    }
+
+    /// puts val at index i, moving the keys from i on up by one
+    pub fn insert(&mut self, i: usize, val: T) {
+        assert!(i <= self.len, "insert at {} past {} keys", i, self.len);
+        assert!(self.len < B, "a node holds at most {} keys", B);
+        let len = self.len;
+        let p = self.ptr();
+        // SAFETY: slots i..len are initialized and len < B, so moving them
+        // to i + 1..len + 1 stays inside the array and leaves i free to write
+        unsafe {
+            ptr::copy(p.add(i), p.add(i + 1), len - i);
+            ptr::write(p.add(i), val);
+        }
+        self.len += 1;
+    }
+
+    /// takes out the key at i, moving the ones after it down by one
+    pub fn remove(&mut self, i: usize) -> T {
+        assert!(i < self.len, "remove at {} past {} keys", i, self.len);
+        let len = self.len;
+        let p = self.ptr();
+        // SAFETY: slot i is initialized and read out once, and slots
+        // i + 1..len move down over it, leaving len - 1 initialized
+        let val = unsafe {
+            let val = ptr::read(p.add(i));
+            ptr::copy(p.add(i + 1), p.add(i), len - i - 1);
+            val
+        };
+        self.len -= 1;
+        val
+    }
+
+    /// moves the keys from at on into a new Keys
+    pub fn split_off(&mut self, at: usize) -> Self {
+        assert!(at <= self.len, "split at {} past {} keys", at, self.len);
+        let mut other = Self::new();
+        let count = self.len - at;
+        // SAFETY: slots at..len are initialized, and after the copy they
+        // belong to other alone, since self.len stops short of them
+        unsafe {
+            ptr::copy_nonoverlapping(self.ptr().add(at), other.ptr(), count);
+        }
+        self.len = at;
+        other.len = count;
+        other
+    }
+
+    /// moves every key of other onto the end of these
+    pub fn append(&mut self, other: &mut Self) {
+        assert!(self.len + other.len <= B, "a node holds at most {} keys", B);
+        let len = self.len;
+        // SAFETY: there is room for other's keys after ours, and other ends
+        // up empty, so each key is owned once
+        unsafe {
+            ptr::copy_nonoverlapping(other.ptr(), self.ptr().add(len), other.len);
+        }
+        self.len += other.len;
+        other.len = 0;
+    }
+
+    pub fn clear(&mut self) {
+        let len = self.len;
+        self.len = 0;
+        // SAFETY: the first len slots were initialized, and len is 0 before
+        // they are dropped, so a panicking drop can't drop one twice
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.ptr(), len));
+        }
+    }
+
+    pub fn into_vec(mut self) -> Vec<T> {
+        let mut out = Vec::with_capacity(self.len);
+        while let Some(val) = self.pop() {
+            out.push(val);
+        }
+        out.reverse();
+        out
+    }
+}
+
+impl<T, const B: usize> Deref for Keys<T, B> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: the first len slots are initialized
+        unsafe { slice::from_raw_parts(self.slots.as_ptr() as *const T, self.len) }
+    }
+}
+
+impl<T, const B: usize> DerefMut for Keys<T, B> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        let len = self.len;
+        // SAFETY: the first len slots are initialized
+        unsafe { slice::from_raw_parts_mut(self.ptr(), len) }
+    }
+}
+
+impl<T, const B: usize> Drop for Keys<T, B> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, const B: usize> Default for Keys<T, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Debug, const B: usize> Debug for Keys<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/**
+ * A node of a BTree. Its keys sit side by side inside the node, so a search
+ * through the node reads memory in order rather than following an index for
+ * every comparison. A leaf has no children, and any other node has one more
+ * child than it has keys, with everything under children[i] ordered before
+ * keys[i]
+ */
+#[derive(Debug)]
+pub struct BNode<T, const B: usize> {
+    pub keys: Keys<T, B>,
+    pub children: Vec<usize>,
+    /// number of keys in the subtree rooted here, including this node's own
+    pub size: usize,
+}
+
+impl<T, const B: usize> BNode<T, B> {
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+/// a walk down the tree: each entry is a node and the index taken in it.
+/// The last entry indexes a key and the ones above it the child followed
+type Path = Vec<(usize, usize)>;
+
+/**
+ * Arena based B-tree. Every node holds up to B - 1 keys and, unless it is a
+ * leaf, one child more than that, so a search reads a few short runs of
+ * contiguous keys instead of chasing one node per level of a binary tree.
+ * Every leaf is at the same depth. A node that fills up splits around its
+ * middle key, which moves up into the parent, and a node left with fewer
+ * than (B - 1) / 2 keys borrows one from a sibling or merges with it. B has
+ * to be at least 3.
+ *
+ * Tree is built on BaseTree handing out binary nodes by index, for the
+ * rotations, cursors, joins and in place updates of the other trees, and a
+ * node holding many keys has no such index for a single value. So BTree
+ * keeps the query, insert and delete methods of Tree as methods of its own,
+ * and shares them with the other trees through OrderedSet
+ */
+#[derive(Debug)]
+pub struct BTree<T, const B: usize = 16, C = Natural> {
+    root: Option<usize>,
+    size: usize,
+    data: Vec<BNode<T, B>>,
+    free: Vec<usize>,
+    cmp: C,
+}
+
+impl<T: PartialOrd, const B: usize> BTree<T, B> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    /// the fanout the snapshot was taken with has to match B
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        // reads a node and everything under it, returning its index
+        fn read_node<T: SnapshotValue, const B: usize>(
+            input: &mut &[u8],
+            data: &mut Vec<BNode<T, B>>,
+            count: &mut usize,
+            depth: usize,
+        ) -> Result<usize, SnapshotError> {
+            // no B-tree holding anything that fits in memory is this deep
+            if *count == 0 || depth > 64 {
+                return Err(SnapshotError::BadShape);
+            }
+            *count -= 1;
+            let inner = match u8::read(input)? {
+                0 => false,
+                1 => true,
+                _ => return Err(SnapshotError::BadShape),
+            };
+            let len = usize::read(input)?;
+            if len == 0 || len >= B {
+                return Err(SnapshotError::BadShape);
+            }
+            let mut keys = Keys::new();
+            for _ in 0..len {
+                keys.push(T::read(input)?);
+            }
+            let n = data.len();
+            data.push(BNode {
+                keys,
+                children: Vec::new(),
+                size: len,
+            });
+            if inner {
+                let mut children = Vec::with_capacity(B + 1);
+                for _ in 0..=len {
+                    let c = read_node(input, data, count, depth + 1)?;
+                    data[n].size += data[c].size;
+                    children.push(c);
+                }
+                data[n].children = children;
+            }
+            Ok(n)
+        }
+
+        let (mut count, mut input) = read_header(bytes, SnapshotKind::BTree)?;
+        if usize::read(&mut input)? != B {
+            return Err(SnapshotError::BadShape);
+        }
+        let mut tree = Self::new();
+        if count > 0 {
+            let root = read_node(&mut input, &mut tree.data, &mut count, 0)?;
+            tree.root = Some(root);
+            tree.size = tree.data[root].size;
+        }
+        if count > 0 {
+            return Err(SnapshotError::BadShape);
+        }
+        if !input.is_empty() {
+            return Err(SnapshotError::TrailingData);
+        }
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd, const B: usize> Default for BTree<T, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const B: usize, C: Comparator<T>> BTree<T, B, C> {
+    /// the most keys a node can hold
+    const MAX_KEYS: usize = B - 1;
+    /// the fewest keys a node other than the root can hold
+    const MIN_KEYS: usize = (B - 1) / 2;
+    const FANOUT_CHECK: () = assert!(B >= 3, "a B-tree node needs room for 3 children");
+
+    /// an empty tree that orders its values with cmp
+    /// usage: BTree::<i32, 8, _>::with_comparator(|a: &i32, b: &i32| b.cmp(a))
+    pub fn with_comparator(cmp: C) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FANOUT_CHECK;
+        Self {
+            root: None,
+            size: 0,
+            data: Vec::new(),
+            free: Vec::new(),
+            cmp,
+        }
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    /// the index of val among the keys of n, or of the child it would be
+    /// found under
+    fn search(&self, n: usize, val: &T) -> Result<usize, usize> {
+        self.data[n]
+            .keys
+            .binary_search_by(|k| self.cmp.compare(k, val))
+    }
+
+    fn create_node(&mut self, keys: Keys<T, B>, children: Vec<usize>) -> usize {
+        let node = BNode {
+            keys,
+            children,
+            size: 0,
+        };
+        let n = if let Some(n) = self.free.pop() {
+            self.data[n] = node;
+            n
+        } else {
+            self.data.push(node);
+            self.data.len() - 1
+        };
+        self.resize(n);
+        n
+    }
+
+    fn delete_node(&mut self, n: usize) {
+        self.data[n].keys.clear();
+        self.data[n].children = Vec::new();
+        self.free.push(n);
+    }
+
+    // works out the size of n again from its keys and children
+    fn resize(&mut self, n: usize) {
+        let below: usize = self.data[n]
+            .children
+            .iter()
+            .map(|&c| self.data[c].size)
+            .sum();
+        self.data[n].size = self.data[n].keys.len() + below;
+    }
+
+    pub fn contains(&self, val: &T) -> bool {
+        let mut n = self.root;
+        while let Some(i) = n {
+            match self.search(i, val) {
+                Ok(_) => return true,
+                Err(c) => n = self.data[i].children.get(c).copied(),
+            }
+        }
+        false
+    }
+
+    /// adds val to the tree, or does nothing if an equal value is in it
+    pub fn insert(&mut self, val: T) {
+        let mut n = match self.root {
+            Some(r) => r,
+            None => {
+                let mut keys = Keys::new();
+                keys.push(val);
+                let r = self.create_node(keys, Vec::new());
+                self.root = Some(r);
+                self.size = 1;
+                return;
+            }
+        };
+        let mut path = Path::new();
+        loop {
+            match self.search(n, &val) {
+                Ok(_) => return,
+                Err(i) if self.data[n].is_leaf() => {
+                    self.data[n].keys.insert(i, val);
+                    break;
+                }
+                Err(i) => {
+                    path.push((n, i));
+                    n = self.data[n].children[i];
+                }
+            }
+        }
+        self.size += 1;
+        self.data[n].size += 1;
+        for &(p, _) in &path {
+            self.data[p].size += 1;
+        }
+
+        // the middle key of a node that overflowed moves up into its parent,
+        // which can overflow in turn
+        while self.data[n].keys.len() > Self::MAX_KEYS {
+            let (middle, right) = self.split(n);
+            match path.pop() {
+                Some((p, i)) => {
+                    self.data[p].keys.insert(i, middle);
+                    self.data[p].children.insert(i + 1, right);
+                    n = p;
+                }
+                None => {
+                    let mut keys = Keys::new();
+                    keys.push(middle);
+                    let mut children = Vec::with_capacity(B + 1);
+                    children.extend_from_slice(&[n, right]);
+                    self.root = Some(self.create_node(keys, children));
+                    break;
+                }
+            }
+        }
+    }
+
+    /// moves the keys of n after its middle one, and the children between
+    /// them, into a new node. Returns the middle key and the new node
+    fn split(&mut self, n: usize) -> (T, usize) {
+        let mid = B / 2;
+        let node = &mut self.data[n];
+        let keys = node.keys.split_off(mid + 1);
+        let middle = node.keys.pop().expect("split a node without a middle key");
+        let mut children = Vec::new();
+        if !node.is_leaf() {
+            children.reserve_exact(B + 1);
+            children.extend(node.children.drain(mid + 1..));
+        }
+        let right = self.create_node(keys, children);
+        self.resize(n);
+        (middle, right)
+    }
+
+    /// removes val from the tree, returning false if it wasn't there
+    pub fn delete(&mut self, val: T) -> bool {
+        self.take(&val).is_some()
+    }
+
+    /// removes the value equal to val and hands it back
+    pub fn take(&mut self, val: &T) -> Option<T> {
+        let mut n = self.root?;
+        let mut path = Path::new();
+        let i = loop {
+            match self.search(n, val) {
+                Ok(i) => break i,
+                Err(i) => {
+                    let c = *self.data[n].children.get(i)?;
+                    path.push((n, i));
+                    n = c;
+                }
+            }
+        };
+        if self.data[n].is_leaf() {
+            let val = self.data[n].keys.remove(i);
+            self.after_removal(n, path);
+            return Some(val);
+        }
+
+        // a key above the leaves trades places with the largest key before
+        // it, which is always in a leaf
+        path.push((n, i));
+        let mut leaf = self.data[n].children[i];
+        while !self.data[leaf].is_leaf() {
+            let last = self.data[leaf].children.len() - 1;
+            path.push((leaf, last));
+            leaf = self.data[leaf].children[last];
+        }
+        let before = self.data[leaf].keys.pop().expect("empty leaf");
+        let val = mem::replace(&mut self.data[n].keys[i], before);
+        self.after_removal(leaf, path);
+        Some(val)
+    }
+
+    /// removes and returns the smallest value
+    pub fn pop_first(&mut self) -> Option<T> {
+        let mut n = self.root?;
+        let mut path = Path::new();
+        while !self.data[n].is_leaf() {
+            path.push((n, 0));
+            n = self.data[n].children[0];
+        }
+        let val = self.data[n].keys.remove(0);
+        self.after_removal(n, path);
+        Some(val)
+    }
+
+    /// removes and returns the largest value
+    pub fn pop_last(&mut self) -> Option<T> {
+        let mut n = self.root?;
+        let mut path = Path::new();
+        while !self.data[n].is_leaf() {
+            let last = self.data[n].children.len() - 1;
+            path.push((n, last));
+            n = self.data[n].children[last];
+        }
+        let val = self.data[n].keys.pop();
+        self.after_removal(n, path);
+        val
+    }
+
+    /**
+     * Counts the key taken out of the leaf n off of every size on the way
+     * down to it, then tops up any node left with too few keys. A node
+     * borrows a key through the parent from a sibling that can spare one,
+     * and otherwise merges with a sibling and the key between them, which
+     * takes a key from the parent and can leave it short in turn. A root
+     * left with no keys gives way to its only child
+     */
+    fn after_removal(&mut self, mut n: usize, mut path: Path) {
+        self.size -= 1;
+        self.data[n].size -= 1;
+        for &(p, _) in &path {
+            self.data[p].size -= 1;
+        }
+
+        while let Some((p, i)) = path.pop() {
+            if self.data[n].keys.len() >= Self::MIN_KEYS {
+                return;
+            }
+            let siblings = &self.data[p].children;
+            let left = if i > 0 { Some(siblings[i - 1]) } else { None };
+            let right = siblings.get(i + 1).copied();
+            let spare = |s: &usize| self.data[*s].keys.len() > Self::MIN_KEYS;
+            if left.filter(spare).is_some() {
+                self.rotate_right(p, i - 1);
+                return;
+            } else if right.filter(spare).is_some() {
+                self.rotate_left(p, i);
+                return;
+            } else if left.is_some() {
+                self.merge(p, i - 1);
+            } else {
+                self.merge(p, i);
+            }
+            n = p;
+        }
+
+        if self.data[n].keys.is_empty() {
+            self.root = self.data[n].children.first().copied();
+            self.delete_node(n);
+        }
+    }
+
+    /// moves the last key of the child before keys[k] of p up into its
+    /// place, and the key that was there down into the child after it
+    fn rotate_right(&mut self, p: usize, k: usize) {
+        let (l, r) = (self.data[p].children[k], self.data[p].children[k + 1]);
+        let up = self.data[l]
+            .keys
+            .pop()
+            .expect("borrowed from an empty node");
+        let down = mem::replace(&mut self.data[p].keys[k], up);
+        self.data[r].keys.insert(0, down);
+        if let Some(c) = self.data[l].children.pop() {
+            self.data[r].children.insert(0, c);
+        }
+        self.resize(l);
+        self.resize(r);
+    }
+
+    /// the mirror image of rotate_right
+    fn rotate_left(&mut self, p: usize, k: usize) {
+        let (l, r) = (self.data[p].children[k], self.data[p].children[k + 1]);
+        let up = self.data[r].keys.remove(0);
+        let down = mem::replace(&mut self.data[p].keys[k], up);
+        self.data[l].keys.push(down);
+        if !self.data[r].is_leaf() {
+            let c = self.data[r].children.remove(0);
+            self.data[l].children.push(c);
+        }
+        self.resize(l);
+        self.resize(r);
+    }
+
+    /// folds keys[k] of p and the child after it into the child before it
+    fn merge(&mut self, p: usize, k: usize) {
+        let (l, r) = (self.data[p].children[k], self.data[p].children[k + 1]);
+        let between = self.data[p].keys.remove(k);
+        self.data[p].children.remove(k + 1);
+        let mut keys = mem::take(&mut self.data[r].keys);
+        let children = mem::take(&mut self.data[r].children);
+        let left = &mut self.data[l];
+        left.keys.push(between);
+        left.keys.append(&mut keys);
+        left.children.extend(children);
+        self.resize(l);
+        self.delete_node(r);
+    }
+
+    /// the path to the smallest key inside of the bound. Above the last
+    /// entry, the key at the index of the child followed comes next in order
+    fn lower_path(&self, bound: Bound<&T>) -> Path {
+        let below = |k: &T| match bound {
+            Bound::Included(v) => self.cmp.compare(k, v) == Ordering::Less,
+            Bound::Excluded(v) => self.cmp.compare(k, v) != Ordering::Greater,
+            Bound::Unbounded => false,
+        };
+        let mut path = Path::new();
+        let mut n = self.root;
+        while let Some(i) = n {
+            let k = self.data[i].keys.partition_point(below);
+            path.push((i, k));
+            n = self.data[i].children.get(k).copied();
+        }
+        while let Some(&(i, k)) = path.last() {
+            if k < self.data[i].keys.len() {
+                break;
+            }
+            path.pop();
+        }
+        path
+    }
+
+    /// the path to the largest key inside of the bound. Above the last
+    /// entry, the key before the child followed comes next going backwards
+    fn upper_path(&self, bound: Bound<&T>) -> Path {
+        let inside = |k: &T| match bound {
+            Bound::Included(v) => self.cmp.compare(k, v) != Ordering::Greater,
+            Bound::Excluded(v) => self.cmp.compare(k, v) == Ordering::Less,
+            Bound::Unbounded => true,
+        };
+        let mut path = Path::new();
+        let mut n = self.root;
+        while let Some(i) = n {
+            let k = self.data[i].keys.partition_point(inside);
+            path.push((i, k));
+            n = self.data[i].children.get(k).copied();
+        }
+        while let Some(&(_, k)) = path.last() {
+            if k > 0 {
+                break;
+            }
+            path.pop();
+        }
+        if let Some(last) = path.last_mut() {
+            last.1 -= 1;
+        }
+        path
+    }
+
+    fn key_at(&self, path: &[(usize, usize)]) -> Option<&T> {
+        path.last().map(|&(n, k)| &self.data[n].keys[k])
+    }
+
+    /// moves a path made by lower_path on to the next key in order, leaving
+    /// it empty after the last one
+    fn step_forward(&self, path: &mut Path) {
+        let (n, k) = match path.pop() {
+            Some(top) => top,
+            None => return,
+        };
+        if let Some(&c) = self.data[n].children.get(k + 1) {
+            path.push((n, k + 1));
+            let mut c = c;
+            loop {
+                path.push((c, 0));
+                match self.data[c].children.first() {
+                    Some(&next) => c = next,
+                    None => break,
+                }
+            }
+        } else if k + 1 < self.data[n].keys.len() {
+            path.push((n, k + 1));
+        } else {
+            while let Some(&(a, i)) = path.last() {
+                if i < self.data[a].keys.len() {
+                    break;
+                }
+                path.pop();
+            }
+        }
+    }
+
+    /// moves a path made by upper_path back to the key before
+    fn step_back(&self, path: &mut Path) {
+        let (n, k) = match path.pop() {
+            Some(top) => top,
+            None => return,
+        };
+        if let Some(&c) = self.data[n].children.get(k) {
+            path.push((n, k));
+            let mut c = c;
+            loop {
+                let node = &self.data[c];
+                if node.is_leaf() {
+                    path.push((c, node.keys.len() - 1));
+                    break;
+                }
+                let last = node.children.len() - 1;
+                path.push((c, last));
+                c = node.children[last];
+            }
+        } else if k > 0 {
+            path.push((n, k - 1));
+        } else {
+            while let Some(&(_, i)) = path.last() {
+                if i > 0 {
+                    break;
+                }
+                path.pop();
+            }
+            if let Some(last) = path.last_mut() {
+                last.1 -= 1;
+            }
+        }
+    }
+
+    /// iterate over the values of the tree in sorted order
+    pub fn iter(&self) -> Iter<'_, T, B, C> {
+        self.range(..)
+    }
+
+    /// iterate in sorted order over the values of the tree that fall inside
+    /// of the given range
+    /// usage: tree.range(3..10), tree.range(..=7)
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Iter<'_, T, B, C> {
+        let front = self.lower_path(range.start_bound());
+        let back = self.upper_path(range.end_bound());
+        let done = match (self.key_at(&front), self.key_at(&back)) {
+            (Some(f), Some(b)) => self.cmp.compare(f, b) == Ordering::Greater,
+            _ => true,
+        };
+        Iter {
+            tree: self,
+            front,
+            back,
+            done,
+        }
+    }
+
+    /// the smallest value in the tree
+    pub fn first(&self) -> Option<&T> {
+        self.key_at(&self.lower_path(Bound::Unbounded))
+    }
+
+    /// the largest value in the tree
+    pub fn last(&self) -> Option<&T> {
+        self.key_at(&self.upper_path(Bound::Unbounded))
+    }
+
+    /// the largest value no greater than val
+    pub fn floor(&self, val: &T) -> Option<&T> {
+        self.key_at(&self.upper_path(Bound::Included(val)))
+    }
+
+    /// the smallest value no less than val
+    pub fn ceiling(&self, val: &T) -> Option<&T> {
+        self.key_at(&self.lower_path(Bound::Included(val)))
+    }
+
+    /// the largest value strictly below val, whether or not val is in the tree
+    pub fn predecessor(&self, val: &T) -> Option<&T> {
+        self.key_at(&self.upper_path(Bound::Excluded(val)))
+    }
+
+    /// the smallest value strictly above val, whether or not val is in the tree
+    pub fn successor(&self, val: &T) -> Option<&T> {
+        self.key_at(&self.lower_path(Bound::Excluded(val)))
+    }
+
+    /// the number of values in the tree that are smaller than val
+    pub fn rank(&self, val: &T) -> usize {
+        self.count_below(val, false)
+    }
+
+    /// counts the values smaller than val, or no greater than it when
+    /// inclusive is set
+    pub fn count_below(&self, val: &T, inclusive: bool) -> usize {
+        let mut n = self.root;
+        let mut count = 0;
+        while let Some(i) = n {
+            let node = &self.data[i];
+            let k = node
+                .keys
+                .partition_point(|key| match self.cmp.compare(key, val) {
+                    Ordering::Less => true,
+                    Ordering::Equal => inclusive,
+                    Ordering::Greater => false,
+                });
+            count += k + node
+                .children
+                .iter()
+                .take(k)
+                .map(|&c| self.data[c].size)
+                .sum::<usize>();
+            n = node.children.get(k).copied();
+        }
+        count
+    }
+
+    /// the k-th smallest value in the tree, counting from 0, so that
+    /// select(rank(v)) finds v again
+    pub fn select(&self, k: usize) -> Option<&T> {
+        let mut n = self.root?;
+        let mut k = k;
+        'down: loop {
+            let node = &self.data[n];
+            if node.is_leaf() {
+                return node.keys.get(k);
+            }
+            for (i, &c) in node.children.iter().enumerate() {
+                let below = self.data[c].size;
+                if k < below {
+                    n = c;
+                    continue 'down;
+                }
+                if k == below {
+                    return node.keys.get(i);
+                }
+                k -= below + 1;
+            }
+            return None;
+        }
+    }
+
+    /// the number of values inside of the range, without visiting them
+    /// usage: tree.count_range(10..20)
+    pub fn count_range<R: RangeBounds<T>>(&self, range: R) -> usize {
+        let below_start = match range.start_bound() {
+            Bound::Included(v) => self.count_below(v, false),
+            Bound::Excluded(v) => self.count_below(v, true),
+            Bound::Unbounded => 0,
+        };
+        let below_end = match range.end_bound() {
+            Bound::Included(v) => self.count_below(v, true),
+            Bound::Excluded(v) => self.count_below(v, false),
+            Bound::Unbounded => self.size,
+        };
+        below_end.saturating_sub(below_start)
+    }
+
+    /// the number of levels, which is the same down to every leaf
+    pub fn get_height(&self) -> usize {
+        let mut height = 0;
+        let mut n = self.root;
+        while let Some(i) = n {
+            height += 1;
+            n = self.data[i].children.first().copied();
+        }
+        height
+    }
+
+    /// the number of leaf nodes, rather than of values in them
+    pub fn get_leaf_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(n) = stack.pop() {
+            if self.data[n].is_leaf() {
+                count += 1;
+            }
+            stack.extend(&self.data[n].children);
+        }
+        count
+    }
+
+    /// the shape of the tree counted in nodes rather than values, all of
+    /// whose leaves are at the same depth
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats::default();
+        let mut stack: Vec<(usize, usize)> = self.root.map(|r| (r, 0)).into_iter().collect();
+        while let Some((n, depth)) = stack.pop() {
+            let node = &self.data[n];
+            stats.record(depth, node.is_leaf());
+            stack.extend(node.children.iter().map(|&c| (c, depth + 1)));
+        }
+        stats.finish()
+    }
+
+    /// checks the number of keys and children in every node, that every
+    /// leaf is at the same depth, the stored sizes, and that the keys are in
+    /// order
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        let mut leaf_depth = None;
+        let mut stack: Vec<(usize, usize)> = self.root.map(|r| (r, 0)).into_iter().collect();
+        while let Some((n, depth)) = stack.pop() {
+            let node = &self.data[n];
+            let keys = node.keys.len();
+            let min = if Some(n) == self.root {
+                1
+            } else {
+                Self::MIN_KEYS
+            };
+            if keys < min || keys > Self::MAX_KEYS {
+                return Err(InvariantError::KeyCountWrong {
+                    node: n,
+                    keys,
+                    min,
+                    max: Self::MAX_KEYS,
+                });
+            }
+            if node.is_leaf() {
+                match leaf_depth {
+                    Some(expected) if expected != depth => {
+                        return Err(InvariantError::LeafDepthMismatch {
+                            node: n,
+                            depth,
+                            expected,
+                        })
+                    }
+                    _ => leaf_depth = Some(depth),
+                }
+            } else if node.children.len() != keys + 1 {
+                return Err(InvariantError::ChildCountWrong {
+                    node: n,
+                    keys,
+                    children: node.children.len(),
+                });
+            }
+            let actual = keys
+                + node
+                    .children
+                    .iter()
+                    .map(|&c| self.data[c].size)
+                    .sum::<usize>();
+            if node.size != actual {
+                return Err(InvariantError::SizeWrong {
+                    node: n,
+                    stored: node.size,
+                    actual,
+                });
+            }
+            stack.extend(node.children.iter().map(|&c| (c, depth + 1)));
+        }
+        if let Some(r) = self.root {
+            if self.data[r].size != self.size {
+                return Err(InvariantError::SizeWrong {
+                    node: r,
+                    stored: self.size,
+                    actual: self.data[r].size,
+                });
+            }
+        }
+
+        let mut path = self.lower_path(Bound::Unbounded);
+        let mut prev: Option<(usize, &T)> = None;
+        while let Some(&(n, k)) = path.last() {
+            let key = &self.data[n].keys[k];
+            if let Some((p, before)) = prev {
+                if self.cmp.compare(before, key) != Ordering::Less {
+                    return Err(InvariantError::OrderViolation { node: p, next: n });
+                }
+            }
+            prev = Some((n, key));
+            self.step_forward(&mut path);
+        }
+        Ok(())
+    }
+
+    /// a binary snapshot keeping the exact shape of the tree and its fanout
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        let order = self.pre_order();
+        let mut out = write_header(SnapshotKind::BTree, order.len());
+        B.write(&mut out);
+        for &n in &order {
+            let node = &self.data[n];
+            out.push(!node.is_leaf() as u8);
+            node.keys.len().write(&mut out);
+            for key in node.keys.iter() {
+                key.write(&mut out);
+            }
+        }
+        out
+    }
+
+    fn pre_order(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(n) = stack.pop() {
+            order.push(n);
+            stack.extend(self.data[n].children.iter().rev());
+        }
+        order
+    }
+
+    fn label(&self, n: usize) -> String
+    where
+        T: Debug,
+    {
+        let keys: Vec<String> = self.data[n]
+            .keys
+            .iter()
+            .map(|k| format!("{:?}", k))
+            .collect();
+        format!("[{}]", keys.join(" "))
+    }
+
+    /// the tree a level to a line, with the keys of each node in brackets
+    pub fn to_pretty_string(&self) -> String
+    where
+        T: Debug,
+    {
+        let mut level: Vec<usize> = self.root.into_iter().collect();
+        if level.is_empty() {
+            return String::from("(Empty tree)");
+        }
+        let mut lines = Vec::new();
+        while !level.is_empty() {
+            let labels: Vec<String> = level.iter().map(|&n| self.label(n)).collect();
+            lines.push(labels.join(" "));
+            level = level
+                .iter()
+                .flat_map(|&n| self.data[n].children.iter().copied())
+                .collect();
+        }
+        lines.join("\n")
+    }
+
+    /// the tree as a Graphviz graph, with every node a record of its keys
+    /// usage: dot -Tpng tree.dot -o tree.png
+    pub fn to_dot(&self) -> String
+    where
+        T: Debug,
+    {
+        // a record label gives these characters a meaning of their own
+        fn field(key: String) -> String {
+            let mut out = String::new();
+            for c in key.chars() {
+                if "\\\"{}|<> ".contains(c) {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+            out
+        }
+
+        let mut out = String::from("digraph tree {\n    node [shape=record];\n");
+        for n in self.pre_order() {
+            let node = &self.data[n];
+            let fields: Vec<String> = node
+                .keys
+                .iter()
+                .map(|k| field(format!("{:?}", k)))
+                .collect();
+            out += &format!(
+                "    n{} [label=\"{}\", tooltip=\"size={}\"];\n",
+                n,
+                fields.join("|"),
+                dot_escape(&node.size.to_string())
+            );
+            for c in &node.children {
+                out += &format!("    n{} -> n{};\n", n, c);
+            }
+        }
+        out + "}\n"
+    }
+
+    /**
+     * Draws the tree as a standalone SVG document. The leaves are laid out
+     * left to right and every other node sits over the middle of its
+     * children, one row per level
+     */
+    pub fn to_svg(&self) -> String
+    where
+        T: Debug,
+    {
+        const LEVEL: usize = 60;
+        const PAD: usize = 20;
+        const HEIGHT: usize = 24;
+        const GAP: usize = 12;
+        let root = match self.root {
+            Some(r) => r,
+            None => {
+                return String::from(
+                    "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"120\" height=\"40\">\
+                     <text x=\"10\" y=\"25\" font-family=\"monospace\">(Empty tree)</text></svg>\n",
+                )
+            }
+        };
+
+        let labels: Vec<String> = (0..self.data.len()).map(|n| self.label(n)).collect();
+        let width = |n: usize| labels[n].chars().count() * 7 + 10;
+        // the centre of each node, and the left edge of the next leaf
+        fn place<T, const B: usize>(
+            data: &[BNode<T, B>],
+            n: usize,
+            width: &dyn Fn(usize) -> usize,
+            next: &mut usize,
+            x: &mut [usize],
+        ) {
+            let node = &data[n];
+            if node.is_leaf() {
+                x[n] = *next + width(n) / 2;
+                *next += width(n) + GAP;
+            } else {
+                for &c in &node.children {
+                    place(data, c, width, next, x);
+                }
+                x[n] = (x[node.children[0]] + x[node.children[node.children.len() - 1]]) / 2;
+            }
+        }
+        let mut x = vec![0; self.data.len()];
+        let mut next = PAD;
+        place(&self.data, root, &width, &mut next, &mut x);
+
+        let levels = self.get_height();
+        let total_width = max(next - GAP + PAD, 2 * PAD);
+        let total_height = LEVEL * (levels - 1) + HEIGHT + 2 * PAD;
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\" font-family=\"monospace\">\n",
+            w = total_width,
+            h = total_height
+        );
+        let mut level = vec![root];
+        let mut y = PAD;
+        out += "<g stroke=\"#555\" stroke-width=\"1.5\">\n";
+        let mut boxes = String::new();
+        while !level.is_empty() {
+            let mut below = Vec::new();
+            for &n in &level {
+                for &c in &self.data[n].children {
+                    out += &format!(
+                        "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>\n",
+                        x[n],
+                        y + HEIGHT,
+                        x[c],
+                        y + LEVEL
+                    );
+                    below.push(c);
+                }
+                boxes += &format!(
+                    "<g><title>size={}</title>\
+                     <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"lightgrey\" stroke=\"black\"/>\
+                     <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"12\">{}</text></g>\n",
+                    self.data[n].size,
+                    x[n] - width(n) / 2,
+                    y,
+                    width(n),
+                    HEIGHT,
+                    x[n],
+                    y + HEIGHT / 2 + 4,
+                    xml_escape(&labels[n])
+                );
+            }
+            level = below;
+            y += LEVEL;
+        }
+        out += "</g>\n";
+        out + &boxes + "</svg>\n"
+    }
+
+    /// moves every live node to the front of the arena and drops the freed
+    /// slots
+    pub fn shrink_to_fit(&mut self) {
+        if self.free.is_empty() {
+            return;
+        }
+        let order = self.pre_order();
+        let mut new_index = vec![0; self.data.len()];
+        for (i, &n) in order.iter().enumerate() {
+            new_index[n] = i;
+        }
+        let mut slots: Vec<Option<BNode<T, B>>> =
+            mem::take(&mut self.data).into_iter().map(Some).collect();
+        self.data = order
+            .iter()
+            .map(|&n| {
+                let mut node = slots[n].take().expect("node visited twice");
+                for c in node.children.iter_mut() {
+                    *c = new_index[*c];
+                }
+                node
+            })
+            .collect();
+        self.root = self.root.map(|_| 0);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+}
+
+/**
+ * In order iterator over a BTree. Each end keeps the path down to the key
+ * it hands out next, and the two ends walk towards each other until they
+ * land on the same key
+ */
+pub struct Iter<'a, T, const B: usize, C> {
+    tree: &'a BTree<T, B, C>,
+    front: Path,
+    back: Path,
+    done: bool,
+}
+
+impl<'a, T, const B: usize, C: Comparator<T>> Iterator for Iter<'a, T, B, C> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.done {
+            return None;
+        }
+        let &(n, k) = self.front.last()?;
+        self.done = self.front.last() == self.back.last();
+        self.tree.step_forward(&mut self.front);
+        Some(&self.tree.data[n].keys[k])
+    }
+}
+
+impl<'a, T, const B: usize, C: Comparator<T>> DoubleEndedIterator for Iter<'a, T, B, C> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.done {
+            return None;
+        }
+        let &(n, k) = self.back.last()?;
+        self.done = self.front.last() == self.back.last();
+        self.tree.step_back(&mut self.back);
+        Some(&self.tree.data[n].keys[k])
+    }
+}
+
+impl<T, const B: usize, C: Comparator<T>> IntoIterator for BTree<T, B, C> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        fn walk<T, const B: usize>(data: &mut [BNode<T, B>], n: usize, out: &mut Vec<T>) {
+            let keys = mem::take(&mut data[n].keys);
+            let children = mem::take(&mut data[n].children);
+            let mut keys = keys.into_vec().into_iter();
+            for c in children {
+                walk(data, c, out);
+                out.extend(keys.next());
+            }
+            out.extend(keys);
+        }
+
+        let mut values = Vec::with_capacity(self.size);
+        if let Some(r) = self.root {
+            walk(&mut self.data, r, &mut values);
+        }
+        values.into_iter()
+    }
+}
+
+impl<'a, T, const B: usize, C: Comparator<T>> IntoIterator for &'a BTree<T, B, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, B, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    // inserts and deletes a scrambled run of values, checking against a
+    // BTreeSet and validating after every step
+    fn churn<const B: usize>() {
+        let mut tree: BTree<u32, B> = BTree::new();
+        let mut set = BTreeSet::new();
+        let mut x: u32 = 1;
+        for step in 0..2000 {
+            x = x * 7919 % 1009;
+            if step % 3 == 2 {
+                assert_eq!(tree.delete(x), set.remove(&x));
+            } else {
+                tree.insert(x);
+                set.insert(x);
+            }
+            assert_eq!(tree.validate(), Ok(()), "fanout {} step {}", B, step);
+        }
+        assert_eq!(tree.get_size(), set.len());
+        assert!(tree.iter().eq(set.iter()));
+        assert!(tree.iter().rev().eq(set.iter().rev()));
+        while let Some(v) = tree.pop_first() {
+            assert_eq!(Some(v), set.pop_first());
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.get_height(), 0);
+    }
+
+    #[test]
+    fn fanouts() {
+        churn::<3>();
+        churn::<4>();
+        churn::<5>();
+        churn::<16>();
+    }
+
+    #[test]
+    fn queries() {
+        let mut tree: BTree<i32> = BTree::new();
+        for x in (0..200).map(|x| x * 2) {
+            tree.insert(x);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        // ascending inserts leave every split node half full
+        assert_eq!(tree.get_height(), 3);
+        let stats = tree.stats();
+        assert_eq!(stats.height, 3);
+        assert_eq!(stats.leaves, tree.get_leaf_count());
+        assert_eq!(stats.min_leaf_depth, 2);
+        assert_eq!(stats.depth_histogram[0], 1);
+        assert!(tree.contains(&50));
+        assert!(!tree.contains(&51));
+        assert_eq!(tree.first(), Some(&0));
+        assert_eq!(tree.last(), Some(&398));
+        assert_eq!(tree.floor(&51), Some(&50));
+        assert_eq!(tree.ceiling(&51), Some(&52));
+        assert_eq!(tree.predecessor(&50), Some(&48));
+        assert_eq!(tree.successor(&50), Some(&52));
+        assert_eq!(tree.predecessor(&0), None);
+        assert_eq!(tree.successor(&398), None);
+        assert_eq!(tree.rank(&51), 26);
+        assert_eq!(tree.select(26), Some(&52));
+        assert_eq!(tree.select(200), None);
+        assert_eq!(tree.count_range(10..=20), 6);
+
+        let inside: Vec<i32> = tree.range(95..=105).cloned().collect();
+        assert_eq!(inside, vec![96, 98, 100, 102, 104]);
+        let mut both = tree.range(10..20);
+        assert_eq!(both.next(), Some(&10));
+        assert_eq!(both.next_back(), Some(&18));
+        assert_eq!(both.collect::<Vec<_>>(), vec![&12, &14, &16]);
+        assert_eq!(tree.range(51..52).count(), 0);
+        assert_eq!(tree.range(400..).count(), 0);
+
+        assert_eq!(tree.pop_last(), Some(398));
+        assert!(tree.delete(100));
+        assert!(!tree.delete(100));
+        let values: Vec<i32> = tree.into_iter().collect();
+        assert_eq!(values.len(), 198);
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn comparator() {
+        let mut tree: BTree<i32, 4, _> = BTree::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        for x in 0..50 {
+            tree.insert(x);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.first(), Some(&49));
+        assert_eq!(tree.iter().nth(10), Some(&39));
+        assert_eq!(tree.rank(&40), 9);
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut tree: BTree<i64, 5> = BTree::new();
+        for x in 0..300 {
+            tree.insert(x * 37 % 301);
+        }
+        for x in 0..100 {
+            tree.delete(x * 3);
+        }
+        let bytes = tree.to_snapshot();
+        let back: BTree<i64, 5> = BTree::from_snapshot(&bytes).unwrap();
+        assert_eq!(back.to_pretty_string(), tree.to_pretty_string());
+        assert_eq!(back.get_size(), tree.get_size());
+
+        assert_eq!(
+            BTree::<i64, 6>::from_snapshot(&bytes).err(),
+            Some(SnapshotError::BadShape)
+        );
+        assert_eq!(
+            BTree::<i64, 5>::from_snapshot(&bytes[..bytes.len() - 1]).err(),
+            Some(SnapshotError::Truncated)
+        );
+
+        tree.shrink_to_fit();
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.to_snapshot(), bytes);
+    }
+
+    #[test]
+    fn broken() {
+        let mut tree: BTree<i32, 4> = BTree::new();
+        for x in 0..20 {
+            tree.insert(x);
+        }
+        let leaf = tree
+            .pre_order()
+            .into_iter()
+            .find(|&n| tree.data[n].is_leaf())
+            .unwrap();
+        tree.data[leaf].keys.reverse();
+        assert!(matches!(
+            tree.validate(),
+            Err(InvariantError::OrderViolation { .. })
+        ));
+        tree.data[leaf].keys.clear();
+        assert!(matches!(
+            tree.validate(),
+            Err(InvariantError::KeyCountWrong { keys: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn drops_every_key() {
+        use std::rc::Rc;
+        let values: Vec<Rc<u32>> = (0..500).map(Rc::new).collect();
+        let live = || values.iter().filter(|v| Rc::strong_count(v) > 1).count();
+
+        let mut tree: BTree<Rc<u32>, 4> = BTree::new();
+        for x in 0..500 {
+            tree.insert(values[x * 7 % 500].clone());
+        }
+        // an equal value already in the tree is dropped instead of stored
+        tree.insert(values[3].clone());
+        assert_eq!(live(), 500);
+        for x in 0..200 {
+            assert!(tree.delete(values[x * 3 % 500].clone()));
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(live(), tree.get_size());
+        let smallest = **tree.first().unwrap();
+        assert_eq!(tree.pop_first().map(|v| *v), Some(smallest));
+        tree.shrink_to_fit();
+        assert_eq!(live(), tree.get_size());
+
+        let mut back = tree.into_iter();
+        let first = back.next();
+        assert_eq!(live(), 299);
+        drop(back);
+        assert_eq!(live(), 1);
+        drop(first);
+        assert_eq!(live(), 0);
+
+        let mut tree: BTree<Rc<u32>, 3> = BTree::new();
+        for v in &values {
+            tree.insert(v.clone());
+        }
+        drop(tree);
+        assert_eq!(live(), 0);
+    }
+
+    #[test]
+    fn drawings() {
+        let tree = btree! {1, 2, 3, "a \"b\"".len()};
+        assert_eq!(tree.to_pretty_string(), "[1 2 3 5]");
+        let dot = tree.to_dot();
+        assert!(dot.contains("n0 [label=\"1|2|3|5\""));
+        let strings: BTree<String, 3> = {
+            let mut t = BTree::new();
+            for s in ["x", "{y}", "z"] {
+                t.insert(s.to_string());
+            }
+            t
+        };
+        assert!(strings.to_dot().contains("\\\"\\{y\\}\\\""));
+        assert!(strings.to_svg().contains("<rect"));
+        assert!(BTree::<i32>::new().to_svg().contains("(Empty tree)"));
+    }
+}
@@ -0,0 +1,95 @@
+use std::cmp::Ordering;
+
+/**
+ * Decides the order a tree keeps its values in. Every search, insert and
+ * delete goes through it, so two values comparing Equal count as the same
+ * value. Any Fn(&T, &T) -> Ordering is a comparator, so a closure can be
+ * handed straight to with_comparator
+ */
+pub trait Comparator<T> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+impl<T, F> Comparator<T> for F
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+/**
+ * The order given by PartialOrd, used by every tree built without a
+ * comparator. Values that can't be ordered against each other, like a NaN,
+ * would leave the tree in no particular order, so they panic instead
+ */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Natural;
+
+impl<T: PartialOrd> Comparator<T> for Natural {
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.partial_cmp(b)
+            .expect("values that can't be compared, such as NaN, can't go in a tree")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::avltree::AVLTree;
+    use super::super::rbtree::RBTree;
+    use super::super::tree::{BaseTree, Tree};
+    use super::super::unbalancetree::BSTree;
+
+    #[test]
+    fn reversed() {
+        let mut tree = RBTree::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        for x in 0..20 {
+            tree.insert(x);
+        }
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), (0..20).rev().collect::<Vec<_>>());
+        assert_eq!(tree.first(), Some(&19));
+        assert_eq!(tree.successor(&5), Some(&4));
+        assert!(tree.delete(7));
+        assert!(!tree.contains(&7));
+        assert_eq!(tree.validate(), Ok(()));
+
+        let (high, low) = tree.split(&10);
+        assert_eq!(high.iter().cloned().collect::<Vec<_>>(), (11..20).rev().collect::<Vec<_>>());
+        assert_eq!(low.first(), Some(&10));
+        assert_eq!(high.join(low).validate(), Ok(()));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let mut tree = AVLTree::with_comparator(|a: &String, b: &String| {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        });
+        for word in &["Banana", "apple", "Cherry", "APPLE", "banana"] {
+            tree.insert(word.to_string());
+        }
+        assert_eq!(tree.get_size(), 3);
+        assert!(tree.contains(&String::from("CHERRY")));
+        assert_eq!(tree.first().map(String::as_str), Some("apple"));
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn floats() {
+        let mut tree = BSTree::with_comparator(|a: &f64, b: &f64| a.total_cmp(b));
+        for x in &[2.5, f64::NAN, -1.0, 0.0] {
+            tree.insert(*x);
+        }
+        assert_eq!(tree.get_size(), 4);
+        assert!(tree.contains(&f64::NAN));
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "such as NaN")]
+    fn nan_panics() {
+        let mut tree = RBTree::new();
+        tree.insert(1.0);
+        tree.insert(f64::NAN);
+    }
+}
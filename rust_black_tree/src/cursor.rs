@@ -0,0 +1,361 @@
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::ops::Bound;
+
+use super::node::{Node, Side};
+use super::tree::{BaseTree, Tree};
+
+/**
+ * Cursors over the trees that never move a value to another node except
+ * while deleting it. A cursor sits on a node, or on the "ghost" position
+ * past the last value and before the first one, and moves to its neighbours
+ * over the parent/child links, the way the std linked list cursors do
+ */
+pub trait Cursors<T>: Tree<T> + Sized {
+    /// a cursor on the smallest value, or the ghost when the tree is empty
+    fn cursor_front(&self) -> Cursor<'_, T, Self> {
+        Cursor::new(self, self.lower_bound(Bound::Unbounded))
+    }
+
+    /// a cursor on the largest value, or the ghost when the tree is empty
+    fn cursor_back(&self) -> Cursor<'_, T, Self> {
+        Cursor::new(self, self.upper_bound(Bound::Unbounded))
+    }
+
+    /// a cursor on the value equal to val, or the ghost when there is none
+    fn cursor_at(&self, val: &T) -> Cursor<'_, T, Self> {
+        Cursor::new(self, self.find_by(|v| self.compare(v, val)))
+    }
+
+    fn cursor_front_mut(&mut self) -> CursorMut<'_, T, Self> {
+        let current = self.lower_bound(Bound::Unbounded);
+        CursorMut::new(self, current)
+    }
+
+    fn cursor_back_mut(&mut self) -> CursorMut<'_, T, Self> {
+        let current = self.upper_bound(Bound::Unbounded);
+        CursorMut::new(self, current)
+    }
+
+    fn cursor_at_mut(&mut self, val: &T) -> CursorMut<'_, T, Self> {
+        let current = self.find_by(|v| self.compare(v, val));
+        CursorMut::new(self, current)
+    }
+}
+
+fn subtree_size<T, B: BaseTree<T>>(tree: &B, n: Option<usize>) -> usize {
+    n.map(|c| tree.get(c).subtree_size()).unwrap_or(0)
+}
+
+// the number of values before node n, counted on the way up to the root
+fn position<T, B: BaseTree<T>>(tree: &B, n: usize) -> usize {
+    let mut count = subtree_size(tree, tree.get(n).get_child(Side::Left));
+    let mut i = n;
+    while let Some(p) = tree.get(i).get_parent() {
+        let parent = tree.get(p);
+        if parent.get_child(Side::Right) == Some(i) {
+            count += subtree_size(tree, parent.get_child(Side::Left)) + 1;
+        }
+        i = p;
+    }
+    count
+}
+
+// the node holding the k-th smallest value, counting from 0
+fn node_at<T, B: BaseTree<T>>(tree: &B, k: usize) -> Option<usize> {
+    let mut n = tree.get_root();
+    let mut k = k;
+    while let Some(i) = n {
+        let node = tree.get(i);
+        let left = subtree_size(tree, node.get_child(Side::Left));
+        n = match k.cmp(&left) {
+            Ordering::Less => node.get_child(Side::Left),
+            Ordering::Equal => return Some(i),
+            Ordering::Greater => {
+                k -= left + 1;
+                node.get_child(Side::Right)
+            }
+        };
+    }
+    None
+}
+
+fn next_node<T, B: BaseTree<T>>(tree: &B, current: Option<usize>) -> Option<usize> {
+    match current {
+        Some(n) => tree.get(n).get_successor(tree.get_data()),
+        None => tree
+            .get_root()
+            .map(|r| tree.get(r).find_min(tree.get_data())),
+    }
+}
+
+fn prev_node<T, B: BaseTree<T>>(tree: &B, current: Option<usize>) -> Option<usize> {
+    match current {
+        Some(n) => tree.get(n).get_predecessor(tree.get_data()),
+        None => tree
+            .get_root()
+            .map(|r| tree.get(r).find_max(tree.get_data())),
+    }
+}
+
+/// a read only cursor, which can be copied to keep a position around
+pub struct Cursor<'a, T, B: BaseTree<T>> {
+    tree: &'a B,
+    current: Option<usize>,
+    phantom: PhantomData<&'a T>,
+}
+
+impl<'a, T, B: BaseTree<T>> Clone for Cursor<'a, T, B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, B: BaseTree<T>> Copy for Cursor<'a, T, B> {}
+
+impl<'a, T, B: BaseTree<T>> Cursor<'a, T, B> {
+    fn new(tree: &'a B, current: Option<usize>) -> Self {
+        Self {
+            tree,
+            current,
+            phantom: PhantomData,
+        }
+    }
+
+    /// moves on to the next value, going from the last one to the ghost and
+    /// from the ghost to the first one
+    pub fn move_next(&mut self) {
+        self.current = next_node(self.tree, self.current);
+    }
+
+    /// moves back to the previous value, see move_next
+    pub fn move_prev(&mut self) {
+        self.current = prev_node(self.tree, self.current);
+    }
+
+    /// the value under the cursor, or None on the ghost
+    pub fn current(&self) -> Option<&'a T> {
+        self.current.map(|n| self.tree.get(n).get_value())
+    }
+
+    pub fn peek_next(&self) -> Option<&'a T> {
+        next_node(self.tree, self.current).map(|n| self.tree.get(n).get_value())
+    }
+
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        prev_node(self.tree, self.current).map(|n| self.tree.get(n).get_value())
+    }
+
+    /// the number of values before the current one, or None on the ghost
+    pub fn index(&self) -> Option<usize> {
+        self.current.map(|n| position(self.tree, n))
+    }
+}
+
+/**
+ * A cursor that can change the tree under it. Values go in next to the
+ * cursor only where they belong in sorted order, so the tree stays a search
+ * tree, and removing one rebalances the tree the same way delete does
+ */
+pub struct CursorMut<'a, T, B: Tree<T>> {
+    tree: &'a mut B,
+    current: Option<usize>,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T, B: Tree<T>> CursorMut<'a, T, B> {
+    fn new(tree: &'a mut B, current: Option<usize>) -> Self {
+        Self {
+            tree,
+            current,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn move_next(&mut self) {
+        self.current = next_node(self.tree, self.current);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current = prev_node(self.tree, self.current);
+    }
+
+    pub fn current(&self) -> Option<&T> {
+        self.current.map(|n| self.tree.get(n).get_value())
+    }
+
+    pub fn peek_next(&self) -> Option<&T> {
+        next_node(self.tree, self.current).map(|n| self.tree.get(n).get_value())
+    }
+
+    pub fn peek_prev(&self) -> Option<&T> {
+        prev_node(self.tree, self.current).map(|n| self.tree.get(n).get_value())
+    }
+
+    pub fn index(&self) -> Option<usize> {
+        self.current.map(|n| position(self.tree, n))
+    }
+
+    /// a read only cursor at the same position, for as long as this one
+    /// is borrowed
+    pub fn as_cursor(&self) -> Cursor<'_, T, B> {
+        Cursor::new(self.tree, self.current)
+    }
+
+    /**
+     * Removes the current value and moves on to the one after it. Deleting
+     * a node with two children moves its successor's value into it, so the
+     * cursor finds its next position by counting rather than by following
+     * the links it had
+     */
+    pub fn remove_current(&mut self) -> Option<T> {
+        let n = self.current?;
+        let index = position(self.tree, n);
+        let val = self.tree.delete_at(n);
+        self.current = node_at(self.tree, index);
+        Some(val)
+    }
+
+    /// inserts val just before the current value, or after the last one when
+    /// on the ghost. val is handed back when it doesn't sort between the
+    /// values on either side of where it would go
+    pub fn insert_before(&mut self, val: T) -> Result<(), T> {
+        let prev = prev_node(self.tree, self.current);
+        self.insert_between(prev, self.current, val)
+    }
+
+    /// inserts val just after the current value, or before the first one
+    /// when on the ghost, see insert_before
+    pub fn insert_after(&mut self, val: T) -> Result<(), T> {
+        let next = next_node(self.tree, self.current);
+        self.insert_between(self.current, next, val)
+    }
+
+    // an insert never moves a value to another node, so the cursor stays on
+    // the node it was on
+    fn insert_between(
+        &mut self,
+        before: Option<usize>,
+        after: Option<usize>,
+        val: T,
+    ) -> Result<(), T> {
+        // a missing neighbour puts no bound on val
+        let fits = |n: Option<usize>, ord: Ordering| {
+            match n {
+                Some(n) => self.tree.compare(self.tree.get(n).get_value(), &val) == ord,
+                None => true,
+            }
+        };
+        if !fits(before, Ordering::Less) || !fits(after, Ordering::Greater) {
+            return Err(val);
+        }
+        self.tree.insert_and_find(val);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avltree::AVLTree;
+    use crate::rbtree::RBTree;
+    use crate::unbalancetree::BSTree;
+
+    #[test]
+    fn walking() {
+        let mut tree = RBTree::new();
+        for x in 0..10 {
+            tree.insert(x * 10);
+        }
+        let mut cursor = tree.cursor_at(&40);
+        assert_eq!(cursor.current(), Some(&40));
+        assert_eq!(cursor.index(), Some(4));
+        assert_eq!(cursor.peek_prev(), Some(&30));
+        assert_eq!(cursor.peek_next(), Some(&50));
+        let saved = cursor;
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&30));
+        assert_eq!(saved.current(), Some(&40));
+
+        // past either end is the ghost, which leads round to the other end
+        let mut cursor = tree.cursor_back();
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.index(), None);
+        assert_eq!(cursor.peek_next(), Some(&0));
+        assert_eq!(cursor.peek_prev(), Some(&90));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&0));
+        assert_eq!(tree.cursor_at(&45).current(), None);
+        assert_eq!(RBTree::<i32>::new().cursor_front().current(), None);
+    }
+
+    // removes every other value through a cursor and checks the tree is
+    // still valid, whichever way the nodes had to be unlinked
+    fn remove_alternate<B: Cursors<i32>>(mut tree: B) {
+        for x in 0..200 {
+            tree.insert(x * 7 % 200);
+        }
+        let mut cursor = tree.cursor_front_mut();
+        let mut removed = Vec::new();
+        while cursor.current().is_some() {
+            removed.push(cursor.remove_current().unwrap());
+            cursor.move_next();
+        }
+        assert_eq!(removed, (0..100).map(|x| x * 2).collect::<Vec<_>>());
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.get_size(), 100);
+        assert!(tree.iter().cloned().eq((0..100).map(|x| x * 2 + 1)));
+    }
+
+    #[test]
+    fn removing() {
+        remove_alternate(RBTree::new());
+        remove_alternate(AVLTree::new());
+        remove_alternate(BSTree::new());
+
+        let mut tree = AVLTree::new();
+        tree.insert(1);
+        let mut cursor = tree.cursor_back_mut();
+        assert_eq!(cursor.remove_current(), Some(1));
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.remove_current(), None);
+
+        // the value is moved out of the tree, so it needs no Clone
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Unique(i32);
+        let mut tree = RBTree::new();
+        tree.insert(Unique(1));
+        assert_eq!(tree.cursor_front_mut().remove_current(), Some(Unique(1)));
+    }
+
+    #[test]
+    fn inserting() {
+        let mut tree = BSTree::new();
+        for x in &[10, 20, 30] {
+            tree.insert(*x);
+        }
+        let mut cursor = tree.cursor_at_mut(&20);
+        assert_eq!(cursor.insert_before(15), Ok(()));
+        assert_eq!(cursor.insert_after(25), Ok(()));
+        assert_eq!(cursor.current(), Some(&20));
+        assert_eq!(cursor.insert_before(5), Err(5));
+        assert_eq!(cursor.insert_after(20), Err(20));
+        assert_eq!(cursor.as_cursor().index(), Some(2));
+
+        // the ghost sits after the last value and before the first
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.insert_before(40), Ok(()));
+        assert_eq!(cursor.insert_after(0), Ok(()));
+        assert_eq!(cursor.insert_after(50), Err(50));
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(tree.iter().cloned().eq(vec![0, 10, 15, 20, 25, 30, 40]));
+
+        let mut empty = RBTree::new();
+        assert_eq!(empty.cursor_front_mut().insert_after(1), Ok(()));
+        assert_eq!(empty.get_size(), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Bound, Range};
+
+use super::augment::Augment;
+use super::comparator::Natural;
+use super::node::Node;
+use super::rbtree::{ColorNode, RBTree};
+use super::tree::BaseTree;
+use super::tree::Tree;
+
+/// A half open range of values, from lo up to but not including hi. Intervals
+/// are ordered by where they start, then by where they end
+#[derive(Clone, PartialEq)]
+pub struct Interval<T> {
+    pub lo: T,
+    pub hi: T,
+}
+
+impl<T> From<Range<T>> for Interval<T> {
+    fn from(range: Range<T>) -> Self {
+        Self {
+            lo: range.start,
+            hi: range.end,
+        }
+    }
+}
+
+impl<T: PartialOrd> PartialOrd for Interval<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.lo.partial_cmp(&other.lo) {
+            Some(Ordering::Equal) => self.hi.partial_cmp(&other.hi),
+            ord => ord,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Interval<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}..{:?}", self.lo, self.hi)
+    }
+}
+
+/// The largest hi of the intervals in each subtree, which is all an overlap
+/// query needs to know before going into it
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MaxEnd;
+
+impl<T: PartialOrd + Clone> Augment<Interval<T>> for MaxEnd {
+    type Summary = T;
+
+    fn combine(val: &Interval<T>, left: Option<&T>, right: Option<&T>) -> T {
+        let mut max = &val.hi;
+        for m in left.into_iter().chain(right) {
+            if *m > *max {
+                max = m;
+            }
+        }
+        max.clone()
+    }
+}
+
+/**
+ * Red black tree of intervals, for finding every interval that overlaps a
+ * range or holds a point. MaxEnd keeps the largest endpoint in each subtree,
+ * which lets a query skip any subtree that ends before the range it is
+ * looking at, and RBTree refreshes it through rotations and deletes like any
+ * other summary
+ */
+pub type IntervalTree<T> = RBTree<Interval<T>, Natural, MaxEnd>;
+
+impl<T: PartialOrd + Clone> RBTree<Interval<T>, Natural, MaxEnd> {
+    /// adds the interval lo..hi, returning false if it was left out. An
+    /// empty range holds no points, so it is never stored, and intervals are
+    /// a set: two bookings of the same lo..hi are stored once, so callers
+    /// that need both have to tell them apart in T or count them themselves
+    pub fn insert_range(&mut self, range: Range<T>) -> bool {
+        if range.start >= range.end {
+            return false;
+        }
+        let before = self.get_size();
+        Tree::insert(self, Interval::from(range));
+        self.get_size() > before
+    }
+
+    /// removes the interval lo..hi, returning false if it wasn't there
+    pub fn remove_range(&mut self, range: &Range<T>) -> bool {
+        Tree::delete(self, Interval::from(range.clone()))
+    }
+
+    /// every interval sharing at least one point with range, in order
+    pub fn overlapping(&self, range: Range<T>) -> Overlapping<'_, T> {
+        Overlapping::new(self, range.start, Bound::Excluded(range.end))
+    }
+
+    /// every interval holding point, in order
+    pub fn stabbing(&self, point: &T) -> Overlapping<'_, T> {
+        Overlapping::new(self, point.clone(), Bound::Included(point.clone()))
+    }
+}
+
+/**
+ * Walks the tree in order, yielding the intervals that end after start and
+ * begin no later than end. A subtree whose largest endpoint is not past start
+ * is never entered, and the walk stops at the first interval beginning after
+ * end, since everything left to visit begins later still
+ */
+pub struct Overlapping<'a, T> {
+    data: &'a [ColorNode<Interval<T>, T>],
+    stack: Vec<usize>,
+    start: T,
+    end: Bound<T>,
+}
+
+impl<'a, T: PartialOrd + Clone> Overlapping<'a, T> {
+    fn new(tree: &'a IntervalTree<T>, start: T, end: Bound<T>) -> Self {
+        let mut iter = Self {
+            data: tree.get_data(),
+            stack: Vec::new(),
+            start,
+            end,
+        };
+        iter.push_left(tree.get_root());
+        iter
+    }
+
+    fn push_left(&mut self, mut n: Option<usize>) {
+        while let Some(i) = n {
+            if self.data[i].summary <= self.start {
+                break;
+            }
+            self.stack.push(i);
+            n = self.data[i].lchild;
+        }
+    }
+
+    fn past_end(&self, lo: &T) -> bool {
+        match &self.end {
+            Bound::Included(end) => lo > end,
+            Bound::Excluded(end) => lo >= end,
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+impl<'a, T: PartialOrd + Clone> Iterator for Overlapping<'a, T> {
+    type Item = &'a Interval<T>;
+
+    fn next(&mut self) -> Option<&'a Interval<T>> {
+        let data = self.data;
+        while let Some(i) = self.stack.pop() {
+            let node = &data[i];
+            if self.past_end(&node.get_value().lo) {
+                self.stack.clear();
+                return None;
+            }
+            self.push_left(node.rchild);
+            if node.get_value().hi > self.start {
+                return Some(node.get_value());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::InvariantError;
+
+    fn ranges<'a, I: Iterator<Item = &'a Interval<i32>>>(iter: I) -> Vec<Range<i32>> {
+        iter.map(|i| i.lo..i.hi).collect()
+    }
+
+    #[test]
+    fn queries() {
+        let mut tree = IntervalTree::new();
+        for r in &[15..20, 10..30, 17..19, 5..20, 12..15, 30..40] {
+            assert!(tree.insert_range(r.clone()));
+        }
+        assert!(!tree.insert_range(7..7));
+        assert!(!tree.insert_range(12..15));
+        assert_eq!(tree.get_size(), 6);
+        assert_eq!(tree.validate(), Ok(()));
+
+        assert_eq!(ranges(tree.overlapping(14..16)), vec![5..20, 10..30, 12..15, 15..20]);
+        assert_eq!(ranges(tree.overlapping(30..31)), vec![30..40]);
+        assert_eq!(ranges(tree.overlapping(40..50)), vec![]);
+        assert_eq!(ranges(tree.stabbing(&15)), vec![5..20, 10..30, 15..20]);
+        assert_eq!(ranges(tree.stabbing(&30)), vec![30..40]);
+        assert_eq!(ranges(tree.stabbing(&4)), vec![]);
+
+        assert!(tree.remove_range(&(10..30)));
+        assert!(!tree.remove_range(&(10..30)));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(ranges(tree.stabbing(&25)), vec![]);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut tree = IntervalTree::new();
+        let mut all: Vec<Range<i32>> = Vec::new();
+        let mut state: u64 = 7;
+        let mut next = |m: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % m) as i32
+        };
+        for _ in 0..2000 {
+            let lo = next(500);
+            let r = lo..lo + 1 + next(60);
+            if next(3) == 0 {
+                let had = all.contains(&r);
+                all.retain(|x| *x != r);
+                assert_eq!(tree.remove_range(&r), had);
+            } else {
+                let fresh = !all.contains(&r);
+                if fresh {
+                    all.push(r.clone());
+                }
+                assert_eq!(tree.insert_range(r), fresh);
+            }
+            assert_eq!(tree.validate(), Ok(()));
+
+            let q = next(560);
+            let query = q..q + next(20);
+            let mut want: Vec<Range<i32>> = all
+                .iter()
+                .filter(|r| r.start < query.end && query.start < r.end)
+                .cloned()
+                .collect();
+            want.sort_by_key(|r| (r.start, r.end));
+            assert_eq!(ranges(tree.overlapping(query)), want);
+
+            let mut want: Vec<Range<i32>> = all.iter().filter(|r| r.contains(&q)).cloned().collect();
+            want.sort_by_key(|r| (r.start, r.end));
+            assert_eq!(ranges(tree.stabbing(&q)), want);
+        }
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let tree = IntervalTree::from_sorted_iter((0..100).map(|x| Interval::from(x..100)));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.stabbing(&99).count(), 100);
+
+        let mut tree = IntervalTree::from_sorted_iter((0..10).map(|x| Interval::from(x..x + 1)));
+        let r = tree.get_root().unwrap();
+        tree.get_mut(r).summary = 0;
+        assert_eq!(tree.validate(), Err(InvariantError::SummaryWrong { node: r }));
+    }
+}
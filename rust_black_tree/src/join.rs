@@ -0,0 +1,475 @@
+use std::cmp::Ordering;
+
+use super::node::{Node, Side};
+use super::tree::{BaseTree, Tree};
+
+/**
+ * A subtree that has been cut loose from the rest of its arena, along with
+ * its rank: its height, or its black height for a red black tree. Ranks are
+ * passed around instead of worked out again, since finding one can take a
+ * walk down the whole subtree
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subtree {
+    pub root: Option<usize>,
+    pub rank: usize,
+}
+
+impl Subtree {
+    pub const EMPTY: Subtree = Subtree {
+        root: None,
+        rank: 0,
+    };
+}
+
+/**
+ * Trees that can join two of their subtrees around a node in time that only
+ * depends on how far apart their ranks are. Splitting, joining and the set
+ * operations all come down to that one step, as laid out in "Just Join for
+ * Parallel Ordered Sets" by Blelloch, Ferizovic and Sun, so a tree only has
+ * to provide join_at to get all of them in logarithmic time
+ */
+pub trait Join<T>: Tree<T> {
+    /// the rank of the subtree at root, worked out from scratch
+    fn rank_of(&self, root: Option<usize>) -> usize;
+
+    /// the rank of the child of n on side, given the rank of n
+    fn child_rank(&self, n: usize, rank: usize, side: Side) -> usize;
+
+    /// hangs left and right off of mid, where mid is a lone node that sorts
+    /// between them, and rebalances. The joined subtree is left detached
+    fn join_at(&mut self, left: Subtree, mid: usize, right: Subtree) -> Subtree;
+
+    /// called on the root of a finished tree, for trees with a rule about
+    /// their root that subtrees don't have to keep
+    fn settle_root(&mut self, _root: usize) {}
+}
+
+/// makes left and right the children of the lone node mid
+pub(crate) fn link<T, B: BaseTree<T>>(
+    tree: &mut B,
+    left: Option<usize>,
+    mid: usize,
+    right: Option<usize>,
+) {
+    for &(child, side) in &[(left, Side::Left), (right, Side::Right)] {
+        tree.get_mut(mid).set_child_opt(child, side);
+        if let Some(c) = child {
+            tree.get_mut(c).set_parent(Some(mid));
+        }
+    }
+    tree.update_size(mid);
+}
+
+// unhooks the children of the root of t, leaving the root on its own
+fn detach<T, B: Join<T>>(tree: &mut B, t: Subtree) -> (Subtree, usize, Subtree) {
+    let n = t.root.expect("detach an empty subtree");
+    let mut children = [Subtree::EMPTY; 2];
+    for (i, &side) in [Side::Left, Side::Right].iter().enumerate() {
+        if let Some(c) = tree.get(n).get_child(side) {
+            children[i] = Subtree {
+                root: Some(c),
+                rank: tree.child_rank(n, t.rank, side),
+            };
+            tree.get_mut(c).set_parent(None);
+            tree.get_mut(n).set_child_opt(None, side);
+        }
+    }
+    tree.update_size(n);
+    (children[0], n, children[1])
+}
+
+fn size<T, B: Join<T>>(tree: &B, t: Subtree) -> usize {
+    t.root.map_or(0, |r| tree.get(r).subtree_size())
+}
+
+/**
+ * Splits t into the values below the key, the node equal to it if there is
+ * one, and the values above it. cmp orders a node against the key
+ */
+fn split_at<T, B, F>(tree: &mut B, t: Subtree, cmp: &F) -> (Subtree, Option<usize>, Subtree)
+where
+    B: Join<T>,
+    F: Fn(&B, usize) -> Ordering,
+{
+    if t.root.is_none() {
+        return (Subtree::EMPTY, None, Subtree::EMPTY);
+    }
+    let (left, n, right) = detach(tree, t);
+    match cmp(tree, n) {
+        Ordering::Equal => (left, Some(n), right),
+        Ordering::Greater => {
+            let (below, found, above) = split_at(tree, left, cmp);
+            (below, found, tree.join_at(above, n, right))
+        }
+        Ordering::Less => {
+            let (below, found, above) = split_at(tree, right, cmp);
+            (tree.join_at(left, n, below), found, above)
+        }
+    }
+}
+
+// takes the largest node out of t, returning what is left and that node
+fn split_last<T, B: Join<T>>(tree: &mut B, t: Subtree) -> (Subtree, usize) {
+    let (left, n, right) = detach(tree, t);
+    if right.root.is_none() {
+        return (left, n);
+    }
+    let (rest, last) = split_last(tree, right);
+    (tree.join_at(left, n, rest), last)
+}
+
+// join_at without a node to put in the middle
+fn join_two<T, B: Join<T>>(tree: &mut B, left: Subtree, right: Subtree) -> Subtree {
+    if left.root.is_none() {
+        return right;
+    }
+    let (rest, last) = split_last(tree, left);
+    tree.join_at(rest, last, right)
+}
+
+// the nodes of t in sorted order
+fn in_order_from<T, B: Join<T>>(tree: &B, t: Subtree) -> Vec<usize> {
+    let mut order = Vec::with_capacity(size(tree, t));
+    let mut stack = Vec::new();
+    let mut next = t.root;
+    loop {
+        while let Some(n) = next {
+            stack.push(n);
+            next = tree.get(n).get_child(Side::Left);
+        }
+        match stack.pop() {
+            Some(n) => {
+                order.push(n);
+                next = tree.get(n).get_child(Side::Right);
+            }
+            None => return order,
+        }
+    }
+}
+
+// frees every node of t, handing back their values in sorted order
+fn take_values<T, B: Join<T>>(tree: &mut B, t: Subtree) -> Vec<T> {
+    in_order_from(tree, t)
+        .into_iter()
+        .map(|n| tree.delete_node(n))
+        .collect()
+}
+
+// a balanced subtree of the next count values, which have to be ascending
+fn build<T, B: Join<T>>(
+    tree: &mut B,
+    values: &mut impl Iterator<Item = T>,
+    count: usize,
+) -> Subtree {
+    if count == 0 {
+        return Subtree::EMPTY;
+    }
+    let left = build(tree, values, count / 2);
+    let mid = tree.create_node(values.next().expect("fewer values than counted"));
+    let right = build(tree, values, count - count / 2 - 1);
+    tree.join_at(left, mid, right)
+}
+
+// moves every value of other into the arena of tree, as a detached subtree
+fn absorb<T, B>(tree: &mut B, other: B) -> Subtree
+where
+    B: Join<T> + IntoIterator<Item = T>,
+{
+    let count = other.get_size();
+    build(tree, &mut other.into_iter(), count)
+}
+
+fn whole<T, B: Join<T>>(tree: &B) -> Subtree {
+    let root = tree.get_root();
+    Subtree {
+        root,
+        rank: tree.rank_of(root),
+    }
+}
+
+// makes t the whole tree
+fn settle<T, B: Join<T>>(tree: &mut B, t: Subtree) {
+    tree.set_root(t.root);
+    if let Some(r) = t.root {
+        tree.settle_root(r);
+    }
+    let new_size = size(tree, t) as isize;
+    tree.crement_size(new_size - tree.get_size() as isize);
+}
+
+/**
+ * Tree::split in O(log n), apart from moving the smaller half into an arena
+ * of its own, which takes time in proportion to its size
+ */
+pub(crate) fn split<T, B>(mut tree: B, val: &T) -> (B, B)
+where
+    B: Join<T>,
+    B::Cmp: Clone,
+{
+    let top = whole(&tree);
+    let cmp = |t: &B, n: usize| t.compare(t.get(n).get_value(), val);
+    let (below, found, above) = split_at(&mut tree, top, &cmp);
+    let above = match found {
+        Some(f) => tree.join_at(Subtree::EMPTY, f, above),
+        None => above,
+    };
+    let make = |tree: &mut B, t: Subtree| {
+        let values = take_values(tree, t);
+        B::from_sorted_iter_by(values, tree.comparator().clone())
+    };
+    if size(&tree, below) <= size(&tree, above) {
+        let low = make(&mut tree, below);
+        settle(&mut tree, above);
+        (low, tree)
+    } else {
+        let high = make(&mut tree, above);
+        settle(&mut tree, below);
+        (tree, high)
+    }
+}
+
+/**
+ * Tree::join in O(log n), apart from moving the smaller tree into the arena
+ * of the larger one
+ */
+pub(crate) fn join<T, B>(mut left: B, mut right: B) -> B
+where
+    B: Join<T> + IntoIterator<Item = T>,
+{
+    if let (Some(max), Some(min)) = (left.last(), right.first()) {
+        assert!(
+            left.compare(max, min) == Ordering::Less,
+            "join needs every value of the left tree below the right tree"
+        );
+    }
+    if left.get_size() >= right.get_size() {
+        let l = whole(&left);
+        let r = absorb(&mut left, right);
+        let joined = join_two(&mut left, l, r);
+        settle(&mut left, joined);
+        left
+    } else {
+        let r = whole(&right);
+        let l = absorb(&mut right, left);
+        let joined = join_two(&mut right, l, r);
+        settle(&mut right, joined);
+        right
+    }
+}
+
+// a and b are in the same arena. Where a value is in both, the node from a
+// is the one kept, here and in intersection_at
+fn union_at<T, B: Join<T>>(tree: &mut B, a: Subtree, b: Subtree) -> Subtree {
+    if a.root.is_none() {
+        return b;
+    }
+    if b.root.is_none() {
+        return a;
+    }
+    let (b_left, key, b_right) = detach(tree, b);
+    let cmp = |t: &B, n: usize| t.compare(t.get(n).get_value(), t.get(key).get_value());
+    let (a_left, found, a_right) = split_at(tree, a, &cmp);
+    let left = union_at(tree, a_left, b_left);
+    let right = union_at(tree, a_right, b_right);
+    let mid = match found {
+        Some(f) => {
+            tree.delete_node(key);
+            f
+        }
+        None => key,
+    };
+    tree.join_at(left, mid, right)
+}
+
+fn intersection_at<T, B: Join<T>>(tree: &mut B, a: Subtree, b: Subtree) -> Subtree {
+    if a.root.is_none() || b.root.is_none() {
+        take_values(tree, a);
+        take_values(tree, b);
+        return Subtree::EMPTY;
+    }
+    let (b_left, key, b_right) = detach(tree, b);
+    let cmp = |t: &B, n: usize| t.compare(t.get(n).get_value(), t.get(key).get_value());
+    let (a_left, found, a_right) = split_at(tree, a, &cmp);
+    let left = intersection_at(tree, a_left, b_left);
+    let right = intersection_at(tree, a_right, b_right);
+    tree.delete_node(key);
+    match found {
+        Some(f) => tree.join_at(left, f, right),
+        None => join_two(tree, left, right),
+    }
+}
+
+// the values of a that are not in b
+fn difference_at<T, B: Join<T>>(tree: &mut B, a: Subtree, b: Subtree) -> Subtree {
+    if a.root.is_none() || b.root.is_none() {
+        take_values(tree, b);
+        return a;
+    }
+    let (b_left, key, b_right) = detach(tree, b);
+    let cmp = |t: &B, n: usize| t.compare(t.get(n).get_value(), t.get(key).get_value());
+    let (a_left, found, a_right) = split_at(tree, a, &cmp);
+    if let Some(f) = found {
+        tree.delete_node(f);
+    }
+    tree.delete_node(key);
+    let left = difference_at(tree, a_left, b_left);
+    let right = difference_at(tree, a_right, b_right);
+    join_two(tree, left, right)
+}
+
+/**
+ * Runs op on the values of mine and other in the arena of the larger tree,
+ * after moving the smaller one into it. The values of mine are always the
+ * first subtree op gets. Takes O(m log(n / m + 1)) for sizes m <= n
+ */
+fn set_operation<T, B>(mine: B, other: B, op: fn(&mut B, Subtree, Subtree) -> Subtree) -> B
+where
+    B: Join<T> + IntoIterator<Item = T>,
+{
+    let (mut tree, a, b) = if mine.get_size() >= other.get_size() {
+        let mut tree = mine;
+        let a = whole(&tree);
+        let b = absorb(&mut tree, other);
+        (tree, a, b)
+    } else {
+        let mut tree = other;
+        let b = whole(&tree);
+        let a = absorb(&mut tree, mine);
+        (tree, a, b)
+    };
+    let result = op(&mut tree, a, b);
+    settle(&mut tree, result);
+    tree
+}
+
+/// Tree::union, keeping the value from mine where both have one
+pub(crate) fn union<T, B>(mine: B, other: B) -> B
+where
+    B: Join<T> + IntoIterator<Item = T>,
+{
+    set_operation(mine, other, union_at)
+}
+
+/// Tree::intersection, keeping the values from mine
+pub(crate) fn intersection<T, B>(mine: B, other: B) -> B
+where
+    B: Join<T> + IntoIterator<Item = T>,
+{
+    set_operation(mine, other, intersection_at)
+}
+
+/// Tree::difference
+pub(crate) fn difference<T, B>(mine: B, other: B) -> B
+where
+    B: Join<T> + IntoIterator<Item = T>,
+{
+    set_operation(mine, other, difference_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::augment::Sum;
+    use crate::avltree::AVLTree;
+    use crate::comparator::Natural;
+    use crate::rbtree::RBTree;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    // trees built by inserting come in every shape, red roots included
+    fn random_tree<B: Tree<i32, Cmp = Natural>>(
+        rng: &mut StdRng,
+        len: usize,
+    ) -> (B, BTreeSet<i32>) {
+        let mut tree = B::new();
+        let mut set = BTreeSet::new();
+        for _ in 0..len {
+            let x = rng.gen_range(0, 500);
+            tree.insert(x);
+            set.insert(x);
+        }
+        (tree, set)
+    }
+
+    fn assert_holds<B: Tree<i32>>(tree: &B, set: &BTreeSet<i32>) {
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.get_size(), set.len());
+        assert!(tree.iter().eq(set.iter()));
+    }
+
+    fn against_sets<B>(seed: u64)
+    where
+        B: Join<i32, Cmp = Natural> + IntoIterator<Item = i32>,
+    {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..40 {
+            // one side is often tiny, so the smaller tree moves both ways
+            let len = rng.gen_range(0, 300);
+            let other_len = if rng.gen() {
+                rng.gen_range(0, 5)
+            } else {
+                rng.gen_range(0, 300)
+            };
+            let (tree, set) = random_tree::<B>(&mut rng, len);
+            let (other, other_set) = random_tree::<B>(&mut rng, other_len);
+
+            let at = rng.gen_range(-10, 510);
+            let (low, high) = tree.split(&at);
+            let low_set: BTreeSet<i32> = set.range(..at).cloned().collect();
+            let high_set: BTreeSet<i32> = set.range(at..).cloned().collect();
+            assert_holds(&low, &low_set);
+            assert_holds(&high, &high_set);
+            let tree = low.join(high);
+            assert_holds(&tree, &set);
+
+            let (a, b) = tree.split(&at);
+            let (c, d) = other.split(&at);
+            let tree = a.join(b);
+            let other = c.join(d);
+            assert_holds(&other, &other_set);
+
+            let union = tree.union(other);
+            let union_set: BTreeSet<i32> = set.union(&other_set).cloned().collect();
+            assert_holds(&union, &union_set);
+
+            let (tree, set) = random_tree::<B>(&mut rng, len);
+            let (other, other_set) = random_tree::<B>(&mut rng, other_len);
+            let both = tree.intersection(other);
+            assert_holds(&both, &set.intersection(&other_set).cloned().collect());
+
+            let (tree, set) = random_tree::<B>(&mut rng, other_len);
+            let (other, other_set) = random_tree::<B>(&mut rng, len);
+            let diff = tree.difference(other);
+            assert_holds(&diff, &set.difference(&other_set).cloned().collect());
+        }
+    }
+
+    #[test]
+    fn matches_btreeset() {
+        against_sets::<RBTree<i32>>(1);
+        against_sets::<AVLTree<i32>>(2);
+        // the summaries have to follow every rotation and relinking
+        against_sets::<RBTree<i32, Natural, Sum>>(3);
+        against_sets::<AVLTree<i32, Natural, Sum>>(4);
+    }
+
+    #[test]
+    fn keeps_the_larger_arena() {
+        let big = RBTree::from_sorted_iter(0..1000);
+        let small = RBTree::from_sorted_iter(1000..1003);
+        let joined = small.union(big);
+        assert_eq!(joined.get_size(), 1003);
+        // the three values moved into the arena of the larger tree, whose
+        // slots all stay where they were
+        assert_eq!(joined.get_data().len(), 1003);
+        assert_eq!(joined.rank(&500), 500);
+
+        let (low, high) = joined.split(&2);
+        assert_eq!(low.get_data().len(), 2);
+        assert_eq!(high.get_data().len(), 1003);
+        assert_eq!(high.get_size(), 1001);
+        assert_eq!(high.validate(), Ok(()));
+    }
+}
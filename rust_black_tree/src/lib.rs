@@ -1,8 +1,27 @@
+pub mod aatree;
+pub mod augment;
 pub mod avltree;
+pub mod btree;
+pub mod comparator;
+pub mod cursor;
+pub mod intervaltree;
+pub mod join;
+pub mod multiset;
 pub mod node;
+pub mod orderedset;
+pub mod persistentrbtree;
 pub mod prettynodeprinter;
 pub mod rbtree;
+pub mod scapegoattree;
+pub mod snapshot;
+pub mod splaytree;
+pub mod treap;
 pub mod tree;
+pub mod treeexport;
+pub mod treemap;
+#[cfg(feature = "serde")]
+mod treeserde;
+pub mod treestats;
 pub mod unbalancetree;
 
 pub fn test() {
@@ -4,6 +4,13 @@ extern crate term_size;
 extern crate rustyline;
 extern crate isatty;
 
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
 use isatty::{stdin_isatty};
 
 use rustyline::error::ReadlineError;
@@ -11,23 +18,110 @@ use rustyline::Editor;
 
 use nom::{
     branch::alt,
-    character::{is_alphabetic, is_digit},
+    bytes::complete::{tag, take_till1},
+    character::complete::{char, digit1, one_of, space1},
+    character::is_alphabetic,
+    combinator::{map, opt, recognize, verify},
+    multi::separated_nonempty_list,
+    sequence::{pair, preceded, tuple},
     IResult,
 };
-use rust_black_trees::tree::BaseTree;
-use rust_black_trees::tree::Tree;
+use rust_black_trees::snapshot::{snapshot_kind, SnapshotError, SnapshotKind, SnapshotValue};
+use rust_black_trees::tree::{BaseTree, Event, InvariantError, Tree};
+use rust_black_trees::treestats::TreeStats;
+use rust_black_trees::prettynodeprinter::*;
 use rust_black_trees::{avltree::AVLTree, rbtree::RBTree, unbalancetree::BSTree};
+use rust_black_trees::{aatree::AATree, scapegoattree::ScapegoatTree, splaytree::SplayTree, treap::Treap};
+use rust_black_trees::btree::BTree;
 
 #[derive(Debug)]
 enum Cmd {
-    Add(isize),
-    Delete(isize),
+    Add(Vec<Literal>),
+    Delete(Vec<Literal>),
+    Contains(Vec<Literal>),
+    Height,
+    Size,
+    Leaves,
+    Stats,
+    Min,
+    Max,
     Print,
+    Check,
+    Export(ExportFormat, String),
+    Save(String),
+    Load(String, Option<ElementType>),
     Quit,
     Clear,
     Help,
-    New(TreeSelection),
-    NumberError,
+    New(TreeSelection, Option<String>, ElementType),
+    Use(String),
+    List,
+    Undo,
+    Redo,
+    Compare(Vec<Literal>),
+    Trace(bool),
+    TypeError,
+}
+
+/// a value as it was typed, before the tree it goes to says what type it is
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    /// a number as written, which may have a fraction or an exponent
+    Number(String),
+    /// the whole numbers from the first end to the second, taking in the
+    /// second when the range was written with `..=`
+    Range(String, String, bool),
+    Str(String),
+}
+
+fn number(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    recognize(tuple((
+        opt(char('-')),
+        digit1,
+        opt(pair(char('.'), digit1)),
+        opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+    )))(input)
+}
+
+fn span(input: &[u8]) -> IResult<&[u8], Literal> {
+    let (s, (lo, hi)) = pair(number, opt(pair(alt((tag("..="), tag(".."))), number)))(input)?;
+    let text = |t: &[u8]| String::from_utf8_lossy(t).to_string();
+    let literal = match hi {
+        None => Literal::Number(text(lo)),
+        Some((op, hi)) => Literal::Range(text(lo), text(hi), op == b"..="),
+    };
+    Ok((s, literal))
+}
+
+/// a string in double quotes, where a backslash keeps the character after
+/// it, so `"say \"hi\""` holds `say "hi"`
+fn quoted(input: &[u8]) -> IResult<&[u8], String> {
+    let (mut rest, _) = char('"')(input)?;
+    let mut out = Vec::new();
+    loop {
+        match rest {
+            [b'"', after @ ..] => return Ok((after, String::from_utf8_lossy(&out).to_string())),
+            [b'\\', c, after @ ..] | [c, after @ ..] => {
+                out.push(*c);
+                rest = after;
+            }
+            [] => return Err(nom::Err::Error((input, nom::error::ErrorKind::Char))),
+        }
+    }
+}
+
+fn literal(input: &[u8]) -> IResult<&[u8], Literal> {
+    alt((map(quoted, Literal::Str), span))(input)
+}
+
+/// a command name followed by any mix of numbers, ranges and strings, like
+/// `add 3 9 20..30 -5..=-1 2.5` or `add "pear" "fig"`
+fn values<'a, N>(name: N, make: fn(Vec<Literal>) -> Cmd, input: &'a [u8]) -> IResult<&'a [u8], Cmd>
+where
+    N: Fn(&'a [u8]) -> IResult<&'a [u8], &'a [u8]>,
+{
+    let (s, literals) = preceded(pair(name, space1), separated_nonempty_list(space1, literal))(input)?;
+    Ok((s, make(literals)))
 }
 
 fn delete(input: &[u8]) -> IResult<&[u8], Cmd> {
@@ -35,21 +129,7 @@ fn delete(input: &[u8]) -> IResult<&[u8], Cmd> {
         delnameparser,
         alt!(tag!("delete") | tag!("del") | tag!("remove") | tag!("d") | tag!("r"))
     );
-    named!( delparser( &[u8] ) -> (&[u8], &[u8]),
-            separated_pair!(
-                delnameparser,
-                char!(' '),
-                take_while!(is_digit)
-            )
-    );
-    delparser(input).map(|(s, (_a, b))| {
-        let x = std::str::from_utf8(b).unwrap().parse();
-        if let Ok(val) = x {
-            (s, Cmd::Delete(val))
-        } else {
-            (s, Cmd::NumberError)
-        }
-    })
+    values(delnameparser, Cmd::Delete, input)
 }
 
 fn add(input: &[u8]) -> IResult<&[u8], Cmd> {
@@ -57,20 +137,29 @@ fn add(input: &[u8]) -> IResult<&[u8], Cmd> {
         addnameparser,
         alt!(tag!("add") | tag!("insert") | tag!("i") | tag!("a"))
     );
-    named!( addparser( &[u8] ) -> (&[u8], &[u8]),
-            separated_pair!(
-                addnameparser,
-                char!(' '),
-                take_while!(is_digit)
-            )
+    values(addnameparser, Cmd::Add, input)
+}
+
+fn contains(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!(containsnameparser, alt!(tag!("contains") | tag!("has")));
+    values(containsnameparser, Cmd::Contains, input)
+}
+
+fn stat(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!(
+        statparse,
+        alt!(tag!("height") | tag!("stats") | tag!("size") | tag!("leaves") | tag!("min") | tag!("max"))
     );
-    addparser(input).map(|(s, (_a, b))| {
-        let x = std::str::from_utf8(b).unwrap().parse();
-        if let Ok(val) = x {
-            (s, Cmd::Add(val))
-        } else {
-            (s, Cmd::NumberError)
-        }
+    statparse(input).map(|(a, b)| {
+        let cmd = match b {
+            b"height" => Cmd::Height,
+            b"size" => Cmd::Size,
+            b"leaves" => Cmd::Leaves,
+            b"stats" => Cmd::Stats,
+            b"min" => Cmd::Min,
+            _ => Cmd::Max,
+        };
+        (a, cmd)
     })
 }
 
@@ -86,14 +175,117 @@ fn new(input: &[u8]) -> IResult<&[u8], Cmd> {
                 take_while!(is_alphabetic)
             )
     );
-    newparser(input).and_then(|(s, (_a, b))| match std::str::from_utf8(b).unwrap() {
-        "rb" => Ok((s, Cmd::New(TreeSelection::RedBlack))),
-        "avl" => Ok((s, Cmd::New(TreeSelection::AVL))),
-        "bst" => Ok((s, Cmd::New(TreeSelection::BST))),
-        _ => Err(nom::Err::Failure((s, nom::error::ErrorKind::NoneOf))),
+    let (s, (_a, b)) = newparser(input)?;
+    let kind = match b {
+        b"rb" => TreeSelection::RedBlack,
+        b"avl" => TreeSelection::AVL,
+        b"bst" => TreeSelection::BST,
+        b"splay" => TreeSelection::Splay,
+        b"treap" => TreeSelection::Treap,
+        b"aa" => TreeSelection::AA,
+        b"sg" | b"scapegoat" => TreeSelection::Scapegoat,
+        b"btree" => TreeSelection::BTree,
+        _ => return Err(nom::Err::Failure((s, nom::error::ErrorKind::NoneOf))),
+    };
+    let (s, name) = opt(preceded(space1, verify(session_name, |n: &[u8]| n[0] != b'-')))(s)?;
+    let name = name.map(|n| String::from_utf8_lossy(n).to_string());
+    let (s, ty) = opt(preceded(space1, type_flag))(s)?;
+    let cmd = match ty {
+        None => Cmd::New(kind, name, ElementType::I64),
+        Some(Some(ty)) => Cmd::New(kind, name, ty),
+        Some(None) => Cmd::TypeError,
+    };
+    Ok((s, cmd))
+}
+
+/// `--type` and the type of value a tree holds, which is None when the demo
+/// doesn't know the type
+fn type_flag(input: &[u8]) -> IResult<&[u8], Option<ElementType>> {
+    let (s, name) = preceded(pair(tag("--type"), space1), session_name)(input)?;
+    let ty = ElementType::ALL.iter().find(|t| t.name().as_bytes() == name);
+    Ok((s, ty.cloned()))
+}
+
+fn session_name(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    take_till1(|c: u8| c.is_ascii_whitespace())(input)
+}
+
+fn use_session(input: &[u8]) -> IResult<&[u8], Cmd> {
+    let (s, name) = preceded(tag("use "), session_name)(input)?;
+    Ok((s, Cmd::Use(String::from_utf8_lossy(name).to_string())))
+}
+
+fn history(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!(historyparse, alt!(tag!("list") | tag!("ls") | tag!("undo") | tag!("redo")));
+    historyparse(input).map(|(a, b)| {
+        let cmd = match b {
+            b"undo" => Cmd::Undo,
+            b"redo" => Cmd::Redo,
+            _ => Cmd::List,
+        };
+        (a, cmd)
+    })
+}
+
+fn compare(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!(comparenameparser, tag!("compare"));
+    values(comparenameparser, Cmd::Compare, input)
+}
+
+#[derive(Debug)]
+enum ExportFormat {
+    Dot,
+    Svg,
+}
+
+fn export(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!(formatparser, alt!(tag!("dot") | tag!("svg")));
+    named!( exportparser( &[u8] ) -> (&[u8], &[u8]),
+            preceded!(
+                tag!("export "),
+                separated_pair!(
+                    formatparser,
+                    char!(' '),
+                    take_till1!(|c: u8| c.is_ascii_whitespace())
+                )
+            )
+    );
+    exportparser(input).map(|(s, (format, file))| {
+        let format = if format == b"dot" {
+            ExportFormat::Dot
+        } else {
+            ExportFormat::Svg
+        };
+        (s, Cmd::Export(format, String::from_utf8_lossy(file).to_string()))
     })
 }
 
+fn save(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!( saveparser( &[u8] ) -> &[u8],
+            preceded!(tag!("save "), take_till1!(|c: u8| c.is_ascii_whitespace()))
+    );
+    saveparser(input).map(|(s, file)| (s, Cmd::Save(String::from_utf8_lossy(file).to_string())))
+}
+
+fn load(input: &[u8]) -> IResult<&[u8], Cmd> {
+    let (s, file) = preceded(tag("load "), session_name)(input)?;
+    let file = String::from_utf8_lossy(file).to_string();
+    let (s, ty) = opt(preceded(space1, type_flag))(s)?;
+    let cmd = match ty {
+        None => Cmd::Load(file, None),
+        Some(Some(ty)) => Cmd::Load(file, Some(ty)),
+        Some(None) => Cmd::TypeError,
+    };
+    Ok((s, cmd))
+}
+
+fn trace(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!( traceparser( &[u8] ) -> &[u8],
+            preceded!(tag!("trace "), alt!(tag!("on") | tag!("off")))
+    );
+    traceparser(input).map(|(s, b)| (s, Cmd::Trace(b == b"on")))
+}
+
 fn help(input: &[u8]) -> IResult<&[u8], Cmd> {
     named!(quitparse, alt!(tag!("help") | tag!("h")));
     let x = quitparse(input);
@@ -118,163 +310,1012 @@ fn print(input: &[u8]) -> IResult<&[u8], Cmd> {
     x.map(|(a, _b)| (a, Cmd::Print))
 }
 
+fn check(input: &[u8]) -> IResult<&[u8], Cmd> {
+    named!(checkparse, alt!(tag!("check") | tag!("validate")));
+    let x = checkparse(input);
+    x.map(|(a, _b)| (a, Cmd::Check))
+}
+
 fn command(input: &[u8]) -> IResult<&[u8], Cmd> {
     //named!(commandparse, alt!(quit | print | clear) );
     //let x = commandparse(input);
-    let x = alt((quit, print, clear, help, add, delete, new))(input);
-    x
+    // stat comes before help, which would otherwise take the h of height
+    alt((
+        quit, print, check, export, save, load, clear, trace, stat, help, history, use_session, compare,
+        contains, add, delete, new,
+    ))(input)
 }
 
-fn eval(
-    cmd: Cmd,
-    rb: &mut RBTree<isize>,
-    avl: &mut AVLTree<isize>,
-    bs: &mut BSTree<isize>,
-    tree_type: &mut TreeSelection,
-) {
-    match cmd {
-        Cmd::Quit => {
-            std::process::exit(0);
-        }
-        Cmd::Clear => {
-            // print!("\x1B[2J"); // Clear should clear the tree not the screen
-            *rb = RBTree::new();
-            *avl = AVLTree::new();
-            *bs = BSTree::new();
-        }
-        Cmd::Print => match tree_type {
-            TreeSelection::RedBlack => {
-                if let Some(root) = rb.get_root() {
-                    if let Some(s) = rust_black_trees::prettynodeprinter::printprettyrb(
-                        rb.get(root),
-                    ) {
-                        println!("{}", s)
-                    } else {
-                        println!("{}", rb.to_pretty_string())
-                    }
-                } else {
-                    println!("Empty Red Black Tree")
-                }
+/// the types of value a demo tree can hold, picked with `--type`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ElementType {
+    Str,
+    F64,
+    I64,
+    U64,
+}
+
+impl ElementType {
+    const ALL: [ElementType; 4] = [ElementType::Str, ElementType::F64, ElementType::I64, ElementType::U64];
+
+    fn name(self) -> &'static str {
+        match self {
+            ElementType::Str => "str",
+            ElementType::F64 => "f64",
+            ElementType::I64 => "i64",
+            ElementType::U64 => "u64",
+        }
+    }
+
+    /// what compare builds its trees out of, going by how the values look:
+    /// strings if there are any, floats if any number has a fraction or an
+    /// exponent, and otherwise i64
+    fn guess(values: &[Literal]) -> Self {
+        let float = |n: &str| n.contains(['.', 'e', 'E']);
+        if values.iter().any(|v| matches!(v, Literal::Str(_))) {
+            ElementType::Str
+        } else if values.iter().any(|v| matches!(v, Literal::Number(n) if float(n))) {
+            ElementType::F64
+        } else {
+            ElementType::I64
+        }
+    }
+}
+
+/// runs $body with $t standing for the Rust type of an ElementType
+macro_rules! with_element {
+    ($ty:expr, $t:ident => $body:expr) => {
+        match $ty {
+            ElementType::Str => {
+                type $t = String;
+                $body
             }
-            TreeSelection::AVL => {
-                if let Some(root) = avl.get_root() {
-                    if let Some(s) = rust_black_trees::prettynodeprinter::printprettyavl(
-                        avl.get(root),
-                    ) {
-                        println!("{}", s)
-                    } else {
-                        println!("{}", avl.to_pretty_string())
-                    }
-                } else {
-                    println!("Empty AVL Tree")
-                }
+            ElementType::F64 => {
+                type $t = f64;
+                $body
+            }
+            ElementType::I64 => {
+                type $t = i64;
+                $body
+            }
+            ElementType::U64 => {
+                type $t = u64;
+                $body
             }
-            TreeSelection::BST => {
-                if let Some(root) = bs.get_root() {
-                    if let Some(s) = rust_black_trees::prettynodeprinter::printprettybst(
-                        bs.get(root),
-                    ) {
-                        println!("{}", s)
-                    } else {
-                        println!("{}", avl.to_pretty_string())
-                    }
-                } else {
-                    println!("Empty Binary Search Tree")
+        }
+    };
+}
+
+/// a type of value the demo trees can hold
+trait Element: PartialOrd + Clone + fmt::Debug + fmt::Display + SnapshotValue + 'static {
+    const TYPE: ElementType;
+
+    /// adds the values a literal stands for to out, or says why it can't
+    /// be one of these
+    fn extend(literal: &Literal, out: &mut Vec<Self>) -> Result<(), String>;
+}
+
+fn from_literals<T: Element>(literals: &[Literal]) -> Result<Vec<T>, String> {
+    let mut out = Vec::new();
+    for literal in literals {
+        T::extend(literal, &mut out)?;
+    }
+    Ok(out)
+}
+
+fn parse_as<T: Element + FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("{} is not a valid {}", text, T::TYPE.name()))
+}
+
+fn not_a_number(s: &str, ty: ElementType) -> String {
+    format!("{:?} is a string, but this tree holds {}", s, ty.name())
+}
+
+macro_rules! whole_element {
+    ($t:ty, $ty:expr) => {
+        impl Element for $t {
+            const TYPE: ElementType = $ty;
+
+            fn extend(literal: &Literal, out: &mut Vec<Self>) -> Result<(), String> {
+                match literal {
+                    Literal::Number(n) => out.push(parse_as(n)?),
+                    Literal::Range(lo, hi, true) => out.extend(parse_as::<$t>(lo)?..=parse_as(hi)?),
+                    Literal::Range(lo, hi, false) => out.extend(parse_as::<$t>(lo)?..parse_as(hi)?),
+                    Literal::Str(s) => return Err(not_a_number(s, $ty)),
                 }
+                Ok(())
             }
-            TreeSelection::Undefined => eprintln!("Need to create a tree first!"),
-        },
-        Cmd::Add(v) => match tree_type {
-            TreeSelection::RedBlack => rb.insert(v),
-            TreeSelection::AVL => avl.insert(v),
-            TreeSelection::BST => bs.insert(v),
-            TreeSelection::Undefined => eprintln!("Need to create a tree first!"),
-        },
-        Cmd::Delete(v) => match tree_type {
-            TreeSelection::RedBlack => {
-                rb.delete(v);
+        }
+    };
+}
+
+whole_element!(i64, ElementType::I64);
+whole_element!(u64, ElementType::U64);
+
+impl Element for f64 {
+    const TYPE: ElementType = ElementType::F64;
+
+    fn extend(literal: &Literal, out: &mut Vec<Self>) -> Result<(), String> {
+        match literal {
+            Literal::Number(n) => out.push(parse_as(n)?),
+            // a range steps through the whole numbers, as it does for i64
+            Literal::Range(..) => {
+                let whole = from_literals::<i64>(std::slice::from_ref(literal))
+                    .map_err(|_| "Ranges of f64 go between whole numbers, like 1..10".to_string())?;
+                out.extend(whole.into_iter().map(|v| v as f64));
             }
-            TreeSelection::AVL => {
-                avl.delete(v);
+            Literal::Str(s) => return Err(not_a_number(s, ElementType::F64)),
+        }
+        Ok(())
+    }
+}
+
+impl Element for String {
+    const TYPE: ElementType = ElementType::Str;
+
+    fn extend(literal: &Literal, out: &mut Vec<Self>) -> Result<(), String> {
+        match literal {
+            Literal::Str(s) => out.push(s.clone()),
+            _ => return Err("This tree holds str, so values go in quotes, like \"pear\"".to_string()),
+        }
+        Ok(())
+    }
+}
+
+/// a tree of any of the kinds the demo can make
+#[allow(clippy::upper_case_acronyms)]
+enum AnyTree<T> {
+    RedBlack(RBTree<T>),
+    AVL(AVLTree<T>),
+    BST(BSTree<T>),
+    Splay(SplayTree<T>),
+    Treap(Treap<T>),
+    AA(AATree<T>),
+    Scapegoat(ScapegoatTree<T>),
+    BTree(BTree<T>),
+}
+
+/// runs $body with $t bound to whichever tree $tree holds
+macro_rules! with_tree {
+    ($tree:expr, $t:ident => $body:expr) => {
+        match $tree {
+            AnyTree::RedBlack($t) => $body,
+            AnyTree::AVL($t) => $body,
+            AnyTree::BST($t) => $body,
+            AnyTree::Splay($t) => $body,
+            AnyTree::Treap($t) => $body,
+            AnyTree::AA($t) => $body,
+            AnyTree::Scapegoat($t) => $body,
+            AnyTree::BTree($t) => $body,
+        }
+    };
+}
+
+/// what stat and compare report about a tree
+struct Shape {
+    height: usize,
+    leaves: usize,
+    nodes: usize,
+}
+
+impl<T: Element> AnyTree<T> {
+    fn new(kind: TreeSelection) -> Self {
+        match kind {
+            TreeSelection::RedBlack => AnyTree::RedBlack(RBTree::new()),
+            TreeSelection::AVL => AnyTree::AVL(AVLTree::new()),
+            TreeSelection::BST => AnyTree::BST(BSTree::new()),
+            TreeSelection::Splay => AnyTree::Splay(SplayTree::new()),
+            TreeSelection::Treap => AnyTree::Treap(Treap::new()),
+            TreeSelection::AA => AnyTree::AA(AATree::new()),
+            TreeSelection::Scapegoat => AnyTree::Scapegoat(ScapegoatTree::new()),
+            TreeSelection::BTree => AnyTree::BTree(BTree::new()),
+        }
+    }
+
+    // the snapshot says which kind of tree it holds, but not the type of its
+    // values, so T has to be the type it was saved with
+    fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        Ok(match snapshot_kind(bytes)? {
+            SnapshotKind::RedBlack => AnyTree::RedBlack(RBTree::from_snapshot(bytes)?),
+            SnapshotKind::AVL => AnyTree::AVL(AVLTree::from_snapshot(bytes)?),
+            SnapshotKind::Unbalanced => AnyTree::BST(BSTree::from_snapshot(bytes)?),
+            SnapshotKind::Splay => AnyTree::Splay(SplayTree::from_snapshot(bytes)?),
+            SnapshotKind::Treap => AnyTree::Treap(Treap::from_snapshot(bytes)?),
+            SnapshotKind::AA => AnyTree::AA(AATree::from_snapshot(bytes)?),
+            SnapshotKind::Scapegoat => AnyTree::Scapegoat(ScapegoatTree::from_snapshot(bytes)?),
+            SnapshotKind::BTree => AnyTree::BTree(BTree::from_snapshot(bytes)?),
+        })
+    }
+
+    fn kind(&self) -> TreeSelection {
+        match self {
+            AnyTree::RedBlack(_) => TreeSelection::RedBlack,
+            AnyTree::AVL(_) => TreeSelection::AVL,
+            AnyTree::BST(_) => TreeSelection::BST,
+            AnyTree::Splay(_) => TreeSelection::Splay,
+            AnyTree::Treap(_) => TreeSelection::Treap,
+            AnyTree::AA(_) => TreeSelection::AA,
+            AnyTree::Scapegoat(_) => TreeSelection::Scapegoat,
+            AnyTree::BTree(_) => TreeSelection::BTree,
+        }
+    }
+
+    fn shape(&self) -> Shape {
+        with_tree!(self, t => Shape {
+            height: t.get_height(),
+            leaves: t.get_leaf_count(),
+            nodes: t.get_size(),
+        })
+    }
+
+    fn print(&self) {
+        match self {
+            AnyTree::RedBlack(t) => print_tree(t, printprettyrb(t), "Red Black Tree"),
+            AnyTree::AVL(t) => print_tree(t, printprettyavl(t), "AVL Tree"),
+            AnyTree::BST(t) => print_tree(t, printprettybst(t), "Binary Search Tree"),
+            AnyTree::Splay(t) => print_tree(t, printprettysplay(t), "Splay Tree"),
+            AnyTree::Treap(t) => print_tree(t, printprettytreap(t), "Treap"),
+            AnyTree::AA(t) => print_tree(t, printprettyaa(t), "AA Tree"),
+            AnyTree::Scapegoat(t) => print_tree(t, printprettyscapegoat(t), "Scapegoat Tree"),
+            // a B-tree isn't binary, so it has a printer of its own
+            AnyTree::BTree(t) if t.is_empty() => println!("Empty B-Tree"),
+            AnyTree::BTree(t) => println!("{}", t.to_pretty_string()),
+        }
+    }
+
+    /// has the trees that report their steps print each one, or stop
+    fn set_trace(&mut self, on: bool) {
+        match self {
+            AnyTree::RedBlack(t) if on => t.set_observer(|e, t| trace_step(e, t, printprettyrb(t), "Red Black Tree")),
+            AnyTree::AVL(t) if on => t.set_observer(|e, t| trace_step(e, t, printprettyavl(t), "AVL Tree")),
+            AnyTree::BST(t) if on => t.set_observer(|e, t| trace_step(e, t, printprettybst(t), "Binary Search Tree")),
+            AnyTree::RedBlack(t) => t.clear_observer(),
+            AnyTree::AVL(t) => t.clear_observer(),
+            AnyTree::BST(t) => t.clear_observer(),
+            _ => {}
+        }
+    }
+}
+
+/// the values an add or delete really changed, which is what undoing it
+/// has to put back
+enum Change<T> {
+    Added(Vec<T>),
+    Deleted(Vec<T>),
+}
+
+impl<T: Element> Change<T> {
+    fn apply(&self, tree: &mut AnyTree<T>) {
+        with_tree!(tree, t => match self {
+            Change::Added(values) => values.iter().for_each(|v| t.insert(v.clone())),
+            Change::Deleted(values) => values.iter().for_each(|v| {
+                t.delete(v.clone());
+            }),
+        })
+    }
+
+    fn undo(&self, tree: &mut AnyTree<T>) {
+        match self {
+            Change::Added(values) => Change::Deleted(values.clone()).apply(tree),
+            Change::Deleted(values) => Change::Added(values.clone()).apply(tree),
+        }
+    }
+}
+
+/// a named tree, along with the changes made to it that can be undone and
+/// the undone ones that can be redone
+struct Session<T> {
+    tree: AnyTree<T>,
+    undo: Vec<Change<T>>,
+    redo: Vec<Change<T>>,
+}
+
+impl<T: Element> Session<T> {
+    fn new(tree: AnyTree<T>) -> Self {
+        Session {
+            tree,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    // a new change starts a new line of history, so nothing undone before
+    // it can be redone any more
+    fn record(&mut self, change: Change<T>) {
+        let empty = match &change {
+            Change::Added(v) | Change::Deleted(v) => v.is_empty(),
+        };
+        if !empty {
+            self.undo.push(change);
+            self.redo.clear();
+        }
+    }
+
+    fn insert_all(&mut self, values: Vec<T>) {
+        let added = with_tree!(&mut self.tree, t => values
+            .into_iter()
+            .filter(|v| {
+                let new = !t.contains(v);
+                t.insert(v.clone());
+                new
+            })
+            .collect());
+        self.record(Change::Added(added));
+    }
+
+    fn delete_all(&mut self, values: Vec<T>) {
+        let deleted = with_tree!(&mut self.tree, t => values.into_iter().filter(|v| t.delete(v.clone())).collect());
+        self.record(Change::Deleted(deleted));
+    }
+}
+
+/**
+ * A session with the type of its values erased, so sessions of every type
+ * share one map and the commands don't need to know what a tree holds.
+ * Values come in as the literals they were typed as, and each session turns
+ * them into its own type, failing on ones that don't fit
+ */
+trait AnySession {
+    fn kind(&self) -> TreeSelection;
+    fn element(&self) -> ElementType;
+    fn shape(&self) -> Shape;
+    fn stats(&self) -> TreeStats;
+    fn add(&mut self, values: &[Literal]) -> Result<(), String>;
+    fn delete(&mut self, values: &[Literal]) -> Result<(), String>;
+    fn contains(&self, values: &[Literal]) -> Result<Vec<bool>, String>;
+    /// deletes every value, which can be undone like any other delete
+    fn clear(&mut self);
+    fn min(&self) -> Option<String>;
+    fn max(&self) -> Option<String>;
+    fn print(&self);
+    fn validate(&self) -> Result<(), InvariantError>;
+    fn export(&self, format: &ExportFormat) -> String;
+    fn to_snapshot(&self) -> Vec<u8>;
+    /// false when there was nothing to undo
+    fn undo(&mut self) -> bool;
+    /// false when there was nothing to redo
+    fn redo(&mut self) -> bool;
+    fn set_trace(&mut self, on: bool);
+}
+
+impl<T: Element> AnySession for Session<T> {
+    fn kind(&self) -> TreeSelection {
+        self.tree.kind()
+    }
+
+    fn element(&self) -> ElementType {
+        T::TYPE
+    }
+
+    fn shape(&self) -> Shape {
+        self.tree.shape()
+    }
+
+    fn stats(&self) -> TreeStats {
+        with_tree!(&self.tree, t => t.stats())
+    }
+
+    fn add(&mut self, values: &[Literal]) -> Result<(), String> {
+        self.insert_all(from_literals(values)?);
+        Ok(())
+    }
+
+    fn delete(&mut self, values: &[Literal]) -> Result<(), String> {
+        self.delete_all(from_literals(values)?);
+        Ok(())
+    }
+
+    fn contains(&self, values: &[Literal]) -> Result<Vec<bool>, String> {
+        let values: Vec<T> = from_literals(values)?;
+        Ok(with_tree!(&self.tree, t => values.iter().map(|v| t.contains(v)).collect()))
+    }
+
+    fn clear(&mut self) {
+        let values = with_tree!(&self.tree, t => t.iter().cloned().collect());
+        self.delete_all(values);
+    }
+
+    fn min(&self) -> Option<String> {
+        with_tree!(&self.tree, t => t.first().map(|v| v.to_string()))
+    }
+
+    fn max(&self) -> Option<String> {
+        with_tree!(&self.tree, t => t.last().map(|v| v.to_string()))
+    }
+
+    fn print(&self) {
+        self.tree.print()
+    }
+
+    fn validate(&self) -> Result<(), InvariantError> {
+        with_tree!(&self.tree, t => t.validate())
+    }
+
+    fn export(&self, format: &ExportFormat) -> String {
+        with_tree!(&self.tree, t => match format {
+            ExportFormat::Dot => t.to_dot(),
+            ExportFormat::Svg => t.to_svg(),
+        })
+    }
+
+    fn to_snapshot(&self) -> Vec<u8> {
+        with_tree!(&self.tree, t => t.to_snapshot())
+    }
+
+    fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(change) => {
+                change.undo(&mut self.tree);
+                self.redo.push(change);
+                true
             }
-            TreeSelection::BST => {
-                bs.delete(v);
+            None => false,
+        }
+    }
+
+    fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(change) => {
+                change.apply(&mut self.tree);
+                self.undo.push(change);
+                true
             }
-            TreeSelection::Undefined => eprintln!("Need to create a tree first!"),
-        },
+            None => false,
+        }
+    }
+
+    fn set_trace(&mut self, on: bool) {
+        self.tree.set_trace(on)
+    }
+}
+
+fn new_session(kind: TreeSelection, ty: ElementType) -> Box<dyn AnySession> {
+    with_element!(ty, T => Box::new(Session::new(AnyTree::<T>::new(kind))))
+}
+
+fn load_session(bytes: &[u8], ty: ElementType) -> Result<Box<dyn AnySession>, SnapshotError> {
+    with_element!(ty, T => Ok(Box::new(Session::new(AnyTree::<T>::from_snapshot(bytes)?))))
+}
+
+/// builds a tree of every kind out of the same values and prints a table of
+/// how each one came out
+fn compare_all<T: Element>(values: &[Literal]) -> Result<(), String> {
+    let values: Vec<T> = from_literals(values)?;
+    println!("{:<6}{:>8}{:>8}{:>8}", "tree", "height", "leaves", "nodes");
+    for kind in TreeSelection::ALL.iter() {
+        let mut tree = AnyTree::new(*kind);
+        with_tree!(&mut tree, t => values.iter().for_each(|v| t.insert(v.clone())));
+        let shape = tree.shape();
+        println!("{:<6}{:>8}{:>8}{:>8}", kind.name(), shape.height, shape.leaves, shape.nodes);
+    }
+    Ok(())
+}
+
+/// every tree the demo has made, by name, and the one commands go to
+struct Demo {
+    sessions: BTreeMap<String, Box<dyn AnySession>>,
+    current: Option<String>,
+    /// whether the trees that can report their steps print them
+    trace: bool,
+}
+
+impl Demo {
+    fn new() -> Self {
+        Demo {
+            sessions: BTreeMap::new(),
+            current: None,
+            trace: false,
+        }
+    }
+
+    fn session(&mut self) -> Result<&mut dyn AnySession, String> {
+        match &self.current {
+            Some(name) => Ok(self.sessions.get_mut(name).expect("current session").as_mut()),
+            None => Err(NO_TREE.to_string()),
+        }
+    }
+
+    /// starts a session, replacing any by the same name, and switches to it
+    fn open(&mut self, name: String, mut session: Box<dyn AnySession>) {
+        session.set_trace(self.trace);
+        self.sessions.insert(name.clone(), session);
+        self.current = Some(name);
+    }
+
+    /// name, or name-2, name-3 and so on when a session already has it
+    fn unused_name(&self, name: String) -> String {
+        if !self.sessions.contains_key(&name) {
+            return name;
+        }
+        (2..)
+            .map(|i| format!("{}-{}", name, i))
+            .find(|n| !self.sessions.contains_key(n))
+            .expect("a free name")
+    }
+
+    fn set_trace(&mut self, on: bool) {
+        self.trace = on;
+        for session in self.sessions.values_mut() {
+            session.set_trace(on);
+        }
+    }
+}
+
+fn print_tree<T: fmt::Debug, B: Tree<T>>(tree: &B, pretty: Option<String>, name: &str) {
+    if tree.is_empty() {
+        println!("Empty {}", name)
+    } else if let Some(s) = pretty {
+        println!("{}", s)
+    } else {
+        println!("{}", tree.to_pretty_string())
+    }
+}
+
+// prints one step of a traced tree and how the tree looks after it
+fn trace_step<T: fmt::Debug, B: Tree<T>>(event: &Event, tree: &B, pretty: Option<String>, name: &str) {
+    println!("-- {:?}", event);
+    print_tree(tree, pretty, name);
+}
+
+/// what the loop feeding commands in should do after one has run
+enum Flow {
+    Continue,
+    Quit,
+}
+
+const NO_TREE: &str = "Need to create a tree first!";
+
+/// runs one command, returning what went wrong as a message for stderr
+fn eval(cmd: Cmd, demo: &mut Demo) -> Result<Flow, String> {
+    match cmd {
+        Cmd::Quit => return Ok(Flow::Quit),
+        // print!("\x1B[2J"); // Clear should clear the tree not the screen
+        Cmd::Clear => demo.session()?.clear(),
+        Cmd::Print => demo.session()?.print(),
+        Cmd::Check => {
+            demo.session()?
+                .validate()
+                .map_err(|e| format!("Invariant broken: {}", e))?;
+            println!("All invariants hold");
+        }
+        Cmd::Export(format, file) => {
+            let out = demo.session()?.export(&format);
+            std::fs::write(&file, out).map_err(|e| format!("Could not write {}: {}", file, e))?;
+            println!("Wrote {}", file);
+        }
+        Cmd::Save(file) => {
+            let out = demo.session()?.to_snapshot();
+            std::fs::write(&file, out).map_err(|e| format!("Could not write {}: {}", file, e))?;
+            println!("Saved to {}", file);
+        }
+        Cmd::Load(file, ty) => {
+            let bytes = std::fs::read(&file).map_err(|e| format!("Could not read {}: {}", file, e))?;
+            // without a --type the values are read as the current tree's type
+            let ty = ty
+                .or_else(|| demo.session().ok().map(|s| s.element()))
+                .unwrap_or(ElementType::I64);
+            let session = load_session(&bytes, ty).map_err(|e| format!("Could not load {}: {}", file, e))?;
+            let kind = session.kind();
+            // the tree gets a session of its own, named after the file, so
+            // the one it was loaded from keeps its tree and undo history
+            let stem = Path::new(&file).file_stem().map(|s| s.to_string_lossy().to_string());
+            let name = demo.unused_name(stem.unwrap_or_else(|| file.clone()));
+            demo.open(name.clone(), session);
+            println!("Loaded {:?} tree of {} from {} as {}", kind, ty.name(), file, name);
+        }
+        Cmd::Add(values) => demo.session()?.add(&values)?,
+        Cmd::Delete(values) => demo.session()?.delete(&values)?,
+        Cmd::Contains(values) => {
+            for found in demo.session()?.contains(&values)? {
+                println!("{}", found);
+            }
+        }
+        Cmd::Height => println!("{}", demo.session()?.shape().height),
+        Cmd::Size => println!("{}", demo.session()?.shape().nodes),
+        Cmd::Leaves => println!("{}", demo.session()?.shape().leaves),
+        Cmd::Stats => print!("{}", demo.session()?.stats()),
+        Cmd::Min | Cmd::Max => {
+            let session = demo.session()?;
+            let end = match cmd {
+                Cmd::Min => session.min(),
+                _ => session.max(),
+            };
+            println!("{}", end.as_deref().unwrap_or("none"));
+        }
+        Cmd::Undo => {
+            if !demo.session()?.undo() {
+                return Err("Nothing to undo".to_string());
+            }
+        }
+        Cmd::Redo => {
+            if !demo.session()?.redo() {
+                return Err("Nothing to redo".to_string());
+            }
+        }
+        Cmd::Use(name) => {
+            if !demo.sessions.contains_key(&name) {
+                return Err(format!("No tree named {}", name));
+            }
+            demo.current = Some(name);
+        }
+        Cmd::List => {
+            for (name, session) in &demo.sessions {
+                let marker = if demo.current.as_ref() == Some(name) { "*" } else { " " };
+                println!(
+                    "{} {} ({} of {}, {} values)",
+                    marker,
+                    name,
+                    session.kind().name(),
+                    session.element().name(),
+                    session.shape().nodes
+                );
+            }
+        }
+        Cmd::Compare(values) => with_element!(ElementType::guess(&values), T => compare_all::<T>(&values))?,
         Cmd::Help => {
             println!("Commands:");
-            println!("  new [avl | rb | bst]");
-            println!("  add [VAL]");
-            println!("  delete [VAL]");
+            println!("  new [avl | rb | bst | splay | treap | aa | sg | btree] [NAME] [--type str | f64 | i64 | u64]");
+            println!("  use [NAME]");
+            println!("  list");
+            println!("  add [VALUES]");
+            println!("  delete [VALUES]");
+            println!("  undo");
+            println!("  redo");
+            println!("  contains [VALUES]");
+            println!("  height | size | leaves | min | max");
+            println!("  stats");
+            println!("  compare [VALUES]");
             println!("  print");
+            println!("  check");
+            println!("  export [dot | svg] [FILE]");
+            println!("  save [FILE]");
+            println!("  load [FILE] [--type str | f64 | i64 | u64]");
+            println!("  trace [on | off]");
             println!("  clear");
             println!("  quit");
+            println!("VALUES are split by spaces, and are numbers like 3 -9 2.5, ranges like");
+            println!("10..20 30..=40, or strings in quotes like \"pear\". Trees hold i64 unless");
+            println!("new is given another --type");
         }
-        Cmd::New(v) => {
-            *tree_type = v;
-            *rb = RBTree::new();
-            *avl = AVLTree::new();
-            *bs = BSTree::new();
-        }
-        Cmd::NumberError => {
-            println!("Only unsigned word sized numbers are supported in the demo. Other datatypes can be purchased for $5.99.");
-        }
-    }
-}
-
-fn read_and_eval(
-    rl: &mut Editor<()>,
-    mut rb: &mut RBTree<isize>,
-    mut avl: &mut AVLTree<isize>,
-    mut bs: &mut BSTree<isize>,
-    tree_type: &mut TreeSelection,
-) {
-    let readline = rl.readline("> ");
-    match readline {
-        Ok(line) => {
-            rl.add_history_entry(line.as_str());
-            let ss = line.as_str().to_string() + " ";
-            let res = command(ss.as_bytes());
-            if let Ok((_s, cmd)) = res {
-                eval(cmd, &mut rb, &mut avl, &mut bs, tree_type);
-            } else {
-                println!("Invalid Command. Try: help")
+        Cmd::New(kind, name, ty) => {
+            let name = name.unwrap_or_else(|| kind.name().to_string());
+            demo.open(name, new_session(kind, ty));
+        }
+        Cmd::Trace(on) => {
+            demo.set_trace(on);
+            let kind = demo.session().map(|s| s.kind());
+            match kind {
+                Ok(TreeSelection::RedBlack) | Ok(TreeSelection::AVL) | Ok(TreeSelection::BST) | Err(_) => {}
+                _ if on => println!("Only rb, avl and bst trees report their steps"),
+                _ => {}
             }
-        },
-        Err(ReadlineError::Interrupted) => {
-            println!();
-            std::process::exit(0);
-        },
-        Err(ReadlineError::Eof) => {
-            println!();
-            std::process::exit(0);
-        },
-        Err(err) => {
-            println!("Error: {:?}", err);
+        }
+        Cmd::TypeError => {
+            return Err("Only str, f64, i64 and u64 trees are supported in the demo. Other datatypes can be purchased for $5.99.".to_string());
         }
     }
+    Ok(Flow::Continue)
+}
 
+/// parses and runs a line of input. Blank lines and lines starting with #
+/// do nothing, so scripts can have comments
+fn run_line(line: &str, demo: &mut Demo) -> Result<Flow, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(Flow::Continue);
+    }
+    let padded = line.to_string() + " ";
+    match command(padded.as_bytes()) {
+        Ok((rest, cmd)) if rest.iter().all(u8::is_ascii_whitespace) => eval(cmd, demo),
+        _ => Err(format!("Invalid Command: {}. Try: help", line)),
+    }
 }
 
-#[derive(Debug)]
+/// runs every line of input as a command, stopping at the first one that
+/// fails. Returns the exit code: 0 once the input ends or quit is run, 1
+/// when a command fails and 2 when the input can't be read
+fn run_script<R: BufRead>(input: R, demo: &mut Demo) -> i32 {
+    for (i, line) in input.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Could not read input: {}", e);
+                return 2;
+            }
+        };
+        match run_line(&line, demo) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => return 0,
+            Err(e) => {
+                eprintln!("line {}: {}", i + 1, e);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+/// reads commands from the terminal until quit, ctrl-c or ctrl-d. A failed
+/// command is reported and the session carries on
+fn interactive(demo: &mut Demo) {
+    println!("Tree Editor CLI v1.0.0");
+    let mut rl = Editor::<()>::new();
+    loop {
+        match rl.readline("> ") {
+            Ok(line) => {
+                rl.add_history_entry(line.as_str());
+                match run_line(&line, demo) {
+                    Ok(Flow::Continue) => {}
+                    Ok(Flow::Quit) => return,
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                println!();
+                return;
+            }
+            Err(err) => {
+                eprintln!("Error: {:?}", err);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 enum TreeSelection {
     RedBlack,
     AVL,
     BST,
-    Undefined,
+    Splay,
+    Treap,
+    AA,
+    Scapegoat,
+    BTree,
 }
+
+impl TreeSelection {
+    const ALL: [TreeSelection; 8] = [
+        TreeSelection::RedBlack,
+        TreeSelection::AVL,
+        TreeSelection::BST,
+        TreeSelection::Splay,
+        TreeSelection::Treap,
+        TreeSelection::AA,
+        TreeSelection::Scapegoat,
+        TreeSelection::BTree,
+    ];
+
+    /// what new calls this kind of tree
+    fn name(self) -> &'static str {
+        match self {
+            TreeSelection::RedBlack => "rb",
+            TreeSelection::AVL => "avl",
+            TreeSelection::BST => "bst",
+            TreeSelection::Splay => "splay",
+            TreeSelection::Treap => "treap",
+            TreeSelection::AA => "aa",
+            TreeSelection::Scapegoat => "sg",
+            TreeSelection::BTree => "btree",
+        }
+    }
+}
+
+/// with a file argument, or `-` for stdin, the commands are read from there
+/// as a script. Piped input is run as a script too, and a terminal gets the
+/// interactive editor
 fn main() {
-    if stdin_isatty() {
-        println!("Tree Editor CLI v1.0.0");
+    let mut demo = Demo::new();
+    let stdin = std::io::stdin();
+    let code = match std::env::args().nth(1) {
+        Some(path) if path != "-" => match File::open(&path) {
+            Ok(file) => run_script(BufReader::new(file), &mut demo),
+            Err(e) => {
+                eprintln!("Could not open {}: {}", path, e);
+                2
+            }
+        },
+        None if stdin_isatty() => {
+            interactive(&mut demo);
+            0
+        }
+        _ => run_script(stdin.lock(), &mut demo),
+    };
+    std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<Cmd> {
+        let padded = line.to_string() + " ";
+        command(padded.as_bytes()).ok().map(|(_, cmd)| cmd)
     }
 
-    let mut rbtree = RBTree::new();
-    let mut avltree = AVLTree::new();
-    let mut bstree = BSTree::new();
-    let mut tree_type = TreeSelection::Undefined;
-    let mut rl = Editor::<()>::new();
-    loop {
-        read_and_eval(&mut rl, &mut rbtree, &mut avltree, &mut bstree, &mut tree_type);
+    #[test]
+    fn value_lists() {
+        match parse("add 3 -9 10..13 -2..=-1") {
+            Some(Cmd::Add(literals)) => {
+                let values: Vec<i64> = from_literals(&literals).unwrap();
+                assert_eq!(values, vec![3, -9, 10, 11, 12, -2, -1]);
+            }
+            other => panic!("parsed {:?}", other),
+        }
+        match parse("del 5..5") {
+            Some(Cmd::Delete(literals)) => assert_eq!(from_literals::<i64>(&literals), Ok(vec![])),
+            other => panic!("parsed {:?}", other),
+        }
+        assert!(matches!(parse("contains 1 2"), Some(Cmd::Contains(_))));
+
+        let too_big = [Literal::Number("99999999999999999999".to_string())];
+        assert!(from_literals::<i64>(&too_big).is_err());
+        assert!(from_literals::<u64>(&[Literal::Number("-1".to_string())]).is_err());
+    }
+
+    #[test]
+    fn typed_values() {
+        match parse(r#"add "pear" "say \"hi\"" "" -2.5e1 1..3"#) {
+            Some(Cmd::Add(literals)) => {
+                assert_eq!(literals[0], Literal::Str("pear".to_string()));
+                assert_eq!(literals[1], Literal::Str("say \"hi\"".to_string()));
+                assert_eq!(literals[2], Literal::Str(String::new()));
+                let numbers: Vec<f64> = from_literals(&literals[3..]).unwrap();
+                assert_eq!(numbers, vec![-25.0, 1.0, 2.0]);
+                assert!(from_literals::<String>(&literals).is_err());
+                assert!(from_literals::<i64>(&literals[3..]).is_err());
+            }
+            other => panic!("parsed {:?}", other),
+        }
+        assert!(parse(r#"add "open"#).is_none());
+        assert!(matches!(parse("new rb --type str"), Some(Cmd::New(_, None, ElementType::Str))));
+        assert!(matches!(
+            parse("new avl prices --type f64"),
+            Some(Cmd::New(_, Some(_), ElementType::F64))
+        ));
+        assert!(matches!(parse("new rb --type char"), Some(Cmd::TypeError)));
+        assert!(matches!(parse("load t.snap --type u64"), Some(Cmd::Load(_, Some(ElementType::U64)))));
+        assert_eq!(ElementType::guess(&[Literal::Number("1e3".to_string())]), ElementType::F64);
+    }
+
+    fn size(demo: &mut Demo) -> usize {
+        demo.session().unwrap().shape().nodes
+    }
+
+    #[test]
+    fn scripts() {
+        let mut demo = Demo::new();
+        let script = "new avl\n# a comment\n\nadd 1..100\ndelete 50..=60\ncheck\n";
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 88);
+
+        assert_eq!(run_script("height\nhelp\n".as_bytes(), &mut demo), 0);
+        assert_eq!(run_script("quit\nbogus\n".as_bytes(), &mut demo), 0);
+        assert_eq!(run_script("size\nbogus\nclear\n".as_bytes(), &mut demo), 1);
+        assert_eq!(size(&mut demo), 88);
+        assert_eq!(run_script("height 3\n".as_bytes(), &mut demo), 1);
+    }
+
+    #[test]
+    fn sessions() {
+        let mut demo = Demo::new();
+        let script = "new rb first\nadd 1..10\nnew bst second\nadd 5\nlist\nuse first\n";
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        assert_eq!(demo.current.as_deref(), Some("first"));
+        assert_eq!(size(&mut demo), 9);
+        assert_eq!(demo.session().unwrap().stats().black_height, Some(3));
+        assert_eq!(run_script("stats\n".as_bytes(), &mut demo), 0);
+        assert_eq!(run_script("use third\n".as_bytes(), &mut demo), 1);
+        assert_eq!(run_script("use second\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 1);
+
+        // a name-less new is named after its kind
+        assert_eq!(run_script("new avl\ncompare 1..20 5\n".as_bytes(), &mut demo), 0);
+        assert_eq!(demo.current.as_deref(), Some("avl"));
+        assert_eq!(demo.sessions.len(), 3);
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut demo = Demo::new();
+        let script = "new rb\nadd 1..10\nadd 5..15\ndelete 1 2 100\n";
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 12);
+
+        // only what each command really changed comes back
+        assert_eq!(run_script("undo\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 14);
+        assert_eq!(run_script("undo\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 9);
+        assert_eq!(run_script("redo\nredo\ncheck\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 12);
+        assert_eq!(run_script("redo\n".as_bytes(), &mut demo), 1);
+
+        assert_eq!(run_script("undo\nadd 50\n".as_bytes(), &mut demo), 0);
+        assert_eq!(run_script("redo\n".as_bytes(), &mut demo), 1);
+        assert_eq!(run_script("clear\nundo\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 15);
+    }
+
+    #[test]
+    fn typed_sessions() {
+        let mut demo = Demo::new();
+        let script = "new rb words --type str\nadd \"pear\" \"fig\" \"apple pie\"\ndelete \"fig\"\ncheck\n";
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 2);
+        assert_eq!(demo.session().unwrap().min().as_deref(), Some("apple pie"));
+        assert_eq!(run_script("add 5\n".as_bytes(), &mut demo), 1);
+        assert_eq!(run_script("undo\nundo\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 0);
+
+        let script = "new avl prices --type f64\nadd -1.5 2 3e2 1..4\ncheck\n";
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 5);
+        assert_eq!(demo.session().unwrap().max().as_deref(), Some("300"));
+
+        let script = "new bst big --type u64\nadd 18446744073709551615\n";
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        assert_eq!(run_script("add -1\n".as_bytes(), &mut demo), 1);
+        assert_eq!(run_script("new rb --type char\n".as_bytes(), &mut demo), 1);
+        assert_eq!(run_script("compare \"b\" \"a\"\ncompare 1 2.5\n".as_bytes(), &mut demo), 0);
+
+        // a snapshot comes back as the type of the tree that was current
+        let file = std::env::temp_dir().join(format!("demo-words-{}.snap", std::process::id()));
+        let file = file.to_string_lossy();
+        let script = format!("use words\nredo\nsave {0}\nclear\nload {0}\ncheck\n", file);
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        std::fs::remove_file(&*file).unwrap();
+        assert_eq!(demo.session().unwrap().element(), ElementType::Str);
+        assert_eq!(size(&mut demo), 3);
+    }
+
+    #[test]
+    fn load_keeps_sessions() {
+        let mut demo = Demo::new();
+        let file = std::env::temp_dir().join(format!("demo-load-{}.snap", std::process::id()));
+        let file = file.to_string_lossy();
+        let script = format!("new rb first\nadd 1..10\nsave {0}\nclear\nload {0}\nload {0}\n", file);
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        std::fs::remove_file(&*file).unwrap();
+        let name = format!("demo-load-{}", std::process::id());
+        assert_eq!(demo.current, Some(format!("{}-2", name)));
+        assert_eq!(size(&mut demo), 9);
+        assert_eq!(demo.sessions.len(), 3);
+
+        // the tree that was current when loading can still undo its clear
+        assert_eq!(run_script(format!("use {}\ncheck\n", name).as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 9);
+        assert_eq!(run_script("use first\nundo\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 9);
+        assert_eq!(run_script("undo\n".as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 0);
+    }
+
+    #[test]
+    fn btree_sessions() {
+        let mut demo = Demo::new();
+        let script = "new btree
+add 1..500
+delete 100..200
+check
+print
+undo
+";
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        assert_eq!(size(&mut demo), 499);
+        assert_eq!(demo.session().unwrap().kind().name(), "btree");
+
+        let file = std::env::temp_dir().join(format!("demo-btree-{}.snap", std::process::id()));
+        let file = file.to_string_lossy();
+        let script = format!("save {0}
+new rb
+load {0}
+check
+", file);
+        assert_eq!(run_script(script.as_bytes(), &mut demo), 0);
+        std::fs::remove_file(&*file).unwrap();
+        assert_eq!(demo.session().unwrap().kind().name(), "btree");
+        assert_eq!(size(&mut demo), 499);
+        assert_eq!(run_script("stats\n".as_bytes(), &mut demo), 0);
     }
 }
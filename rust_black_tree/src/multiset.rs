@@ -0,0 +1,265 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+
+use super::avltree::AVLTree;
+use super::rbtree::RBTree;
+use super::tree::Tree;
+
+use super::node::Node;
+
+/// A multiset backed by a red black tree
+pub type RBMultiset<T> = TreeMultiset<T, RBTree<Counted<T>>>;
+
+/// A multiset backed by an AVL tree
+pub type AVLMultiset<T> = TreeMultiset<T, AVLTree<Counted<T>>>;
+
+/**
+ * The value stored in each node of a multiset, along with how many times it
+ * was inserted. Only the value takes part in the ordering, so the count can
+ * be changed in place without moving the node
+ */
+pub struct Counted<T> {
+    pub value: T,
+    count: usize,
+}
+
+impl<T> Counted<T> {
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl<T: Ord> PartialEq for Counted<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Ord> PartialOrd for Counted<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.value.cmp(&other.value))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Counted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} x{}", self.value, self.count)
+    }
+}
+
+/**
+ * Ordered multiset on top of any of the trees. Each distinct value gets one
+ * node holding a count, so inserting a value a million times still takes a
+ * single node. Sizes count every copy, while the underlying tree from tree()
+ * only holds the distinct values
+ */
+pub struct TreeMultiset<T, B> {
+    tree: B,
+    total: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T, B> TreeMultiset<T, B>
+where
+    T: Ord,
+    B: Tree<Counted<T>>,
+{
+    pub fn new() -> Self
+    where
+        B::Cmp: Default,
+    {
+        Self {
+            tree: B::new(),
+            total: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// the tree the multiset is stored in
+    pub fn tree(&self) -> &B {
+        &self.tree
+    }
+
+    /// the number of values in the multiset, counting every copy
+    pub fn get_size(&self) -> usize {
+        self.total
+    }
+
+    /// the number of different values in the multiset
+    pub fn distinct(&self) -> usize {
+        self.tree.get_size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn find_value(&self, val: &T) -> Option<usize> {
+        self.tree.find_by(|e| e.value.cmp(val))
+    }
+
+    /// how many copies of val are in the multiset
+    pub fn count(&self, val: &T) -> usize {
+        self.find_value(val)
+            .map_or(0, |n| self.tree.get(n).get_value().count)
+    }
+
+    pub fn contains(&self, val: &T) -> bool {
+        self.find_value(val).is_some()
+    }
+
+    pub fn insert(&mut self, val: T) {
+        self.insert_n(val, 1)
+    }
+
+    /// adds n copies of val
+    pub fn insert_n(&mut self, val: T, n: usize) {
+        if n == 0 {
+            return;
+        }
+        match self.find_value(&val) {
+            Some(i) => self.tree.get_mut(i).get_value_mut().count += n,
+            None => {
+                self.tree.insert(Counted {
+                    value: val,
+                    count: n,
+                });
+            }
+        }
+        self.total += n;
+    }
+
+    /// removes a single copy of val, returning false if there was none
+    pub fn remove_one(&mut self, val: &T) -> bool {
+        match self.find_value(val) {
+            Some(i) => {
+                let entry = self.tree.get_mut(i).get_value_mut();
+                entry.count -= 1;
+                if entry.count == 0 {
+                    self.tree.delete_at(i);
+                }
+                self.total -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// removes every copy of val, returning how many there were
+    pub fn remove_all(&mut self, val: &T) -> usize {
+        match self.find_value(val) {
+            Some(i) => {
+                let count = self.tree.get(i).get_value().count;
+                self.tree.delete_at(i);
+                self.total -= count;
+                count
+            }
+            None => 0,
+        }
+    }
+
+    /// iterates over the distinct values in order, along with their counts
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&T, usize)> {
+        self.tree.iter().map(|e| (&e.value, e.count))
+    }
+
+    /// iterates over the values in order, repeating each one as many times as
+    /// it was inserted
+    // repeat_n would need Rust 1.82
+    #[allow(clippy::manual_repeat_n)]
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.tree
+            .iter()
+            .flat_map(|e| std::iter::repeat(&e.value).take(e.count))
+    }
+}
+
+impl<T, B> Default for TreeMultiset<T, B>
+where
+    T: Ord,
+    B: Tree<Counted<T>>,
+    B::Cmp: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, B> FromIterator<T> for TreeMultiset<T, B>
+where
+    T: Ord,
+    B: Tree<Counted<T>>,
+    B::Cmp: Default,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for val in iter {
+            set.insert(val);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        let mut set = RBMultiset::new();
+        assert!(set.is_empty());
+        set.insert(3);
+        set.insert(1);
+        set.insert(3);
+        set.insert_n(2, 4);
+        set.insert_n(5, 0);
+        assert_eq!(set.get_size(), 7);
+        assert_eq!(set.distinct(), 3);
+        assert_eq!(set.count(&3), 2);
+        assert_eq!(set.count(&2), 4);
+        assert_eq!(set.count(&5), 0);
+        assert!(!set.contains(&5));
+        assert_eq!(set.tree().validate(), Ok(()));
+
+        let pairs: Vec<(&i32, usize)> = set.iter().collect();
+        assert_eq!(pairs, vec![(&1, 1), (&2, 4), (&3, 2)]);
+        let values: Vec<i32> = set.values().cloned().collect();
+        assert_eq!(values, vec![1, 2, 2, 2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn remove() {
+        let mut set: AVLMultiset<i32> = (0..100).map(|x| x % 10).collect();
+        assert_eq!(set.get_size(), 100);
+        assert_eq!(set.distinct(), 10);
+
+        assert!(set.remove_one(&4));
+        assert_eq!(set.count(&4), 9);
+        assert_eq!(set.get_size(), 99);
+        assert_eq!(set.remove_all(&4), 9);
+        assert_eq!(set.remove_all(&4), 0);
+        assert!(!set.remove_one(&4));
+        assert_eq!(set.get_size(), 90);
+        assert_eq!(set.distinct(), 9);
+
+        // the last copy going takes the node with it
+        set.insert(42);
+        assert!(set.remove_one(&42));
+        assert!(!set.contains(&42));
+        assert_eq!(set.distinct(), 9);
+        assert_eq!(set.tree().validate(), Ok(()));
+    }
+
+    #[test]
+    fn histogram() {
+        let text = "the quick brown fox jumps over the lazy dog the end";
+        let words: RBMultiset<&str> = text.split(' ').collect();
+        assert_eq!(words.count(&"the"), 3);
+        assert_eq!(words.get_size(), 11);
+        let top = words.iter().max_by_key(|(_, c)| *c);
+        assert_eq!(top, Some((&"the", 3)));
+        assert!(words.tree().to_string().contains("V:\"the\" x3]"));
+    }
+}
@@ -1,13 +1,14 @@
 use std::cmp::max;
+use std::fmt::Debug;
 use std::ops::Not;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
     Red,
     Black,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Side {
     Left,
     Right,
@@ -42,84 +43,134 @@ pub fn endpaint() -> String {
     format!("{}[0m", esc)
 }
 
+/**
+ * How a node is drawn when its tree is exported to DOT or SVG. attributes
+ * hold the balancing data of the node, written out as DOT attributes and
+ * shown next to the node in both formats
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeStyle {
+    pub label: String,
+    pub fill: &'static str,
+    pub font: &'static str,
+    pub attributes: Vec<(&'static str, String)>,
+}
 
-pub trait Node<T> {
+/**
+ * A node lives in an arena (a Vec) owned by its tree, and refers to its parent
+ * and children by their index into that arena. Anything that needs to look at
+ * other nodes is handed the arena it lives in
+ */
+pub trait Node<T>: Sized {
     // Base methods
     fn get_value(&self) -> &T;
-    fn get(&self, i: usize) -> &Self;
-    fn get_mut(&self, i: usize) -> &mut Self;
+    fn get_value_mut(&mut self) -> &mut T;
+    fn into_value(self) -> T;
+    /// moves the value out of a node that is being freed, which leaves the
+    /// node without one until its slot is reused
+    fn take_value(&mut self) -> T;
     fn location(&self) -> usize;
+    fn set_location(&mut self, ptr: usize);
+    /// the node count kept up to date by the tree, unlike get_size which
+    /// walks the whole subtree to find it
+    fn subtree_size(&self) -> usize;
+    fn set_subtree_size(&mut self, size: usize);
     fn get_parent(&self) -> Option<usize>;
     fn set_parent(&mut self, p: Option<usize>);
     fn get_child(&self, side: Side) -> Option<usize>;
     fn set_child(&mut self, child: usize, side: Side);
     fn set_child_opt(&mut self, child: Option<usize>, side: Side);
-    fn to_self_string(&self) -> String;
-    fn to_self_string_display(&self) -> (String, usize);
-    fn is(&self, val: &T) -> bool;
-    fn greater(&self, val: &T) -> bool;
-    fn lesser(&self, val: &T) -> bool;
+    // ordering is up to the tree's comparator, and only printing a node
+    // needs its value to be Debug
+    fn to_self_string(&self) -> String
+    where
+        T: Debug;
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug;
+    fn export_style(&self, data: &[Self]) -> NodeStyle
+    where
+        T: Debug;
+    /// the color of the node, for the trees that color theirs
+    fn get_color(&self) -> Option<Color> {
+        None
+    }
+    /// the balance factor of the node, for the trees that keep one
+    fn get_balance_factor(&self) -> Option<isize> {
+        None
+    }
 
-    fn to_string(&self) -> String {
+    fn to_string(&self, data: &[Self]) -> String
+    where
+        T: Debug,
+    {
         let mut m_str = format!("({}", self.to_self_string());
         m_str = m_str
             + " "
             + &(if let Some(child) = self.get_child(Side::Left) {
-                self.get(child).to_string()
+                data[child].to_string(data)
             } else {
                 String::from("()")
             });
         m_str = m_str
             + " "
             + &(if let Some(child) = self.get_child(Side::Right) {
-                self.get(child).to_string()
+                data[child].to_string(data)
             } else {
                 String::from("()")
             });
         m_str + ")"
     }
 
-    fn to_pretty_string(&self, indent: usize) -> String {
+    fn to_pretty_string(&self, indent: usize, data: &[Self]) -> String
+    where
+        T: Debug,
+    {
         let i = indent * 2;
         let mut m_str = format!("({}", self.to_self_string());
         m_str = m_str
             + &(if let Some(child) = self.get_child(Side::Left) {
-                "\n".to_owned() + &" ".repeat(i) + &self.get(child).to_pretty_string(indent + 1)
+                "\n".to_owned()
+                    + &" ".repeat(i)
+                    + &data[child].to_pretty_string(indent + 1, data)
             } else {
                 String::from(" ()")
             });
         m_str = m_str
             + &(if let Some(child) = self.get_child(Side::Right) {
-                "\n".to_owned() + &" ".repeat(i) + &self.get(child).to_pretty_string(indent + 1)
+                "\n".to_owned()
+                    + &" ".repeat(i)
+                    + &data[child].to_pretty_string(indent + 1, data)
             } else {
                 String::from(" ()")
             });
         m_str + ")"
     }
 
-    fn get_height(&self) -> usize {
-        let f = |c| Some(1 + self.get(c).get_height());
+    fn get_height(&self, data: &[Self]) -> usize {
+        let f = |c: usize| 1 + data[c].get_height(data);
         max(
-            self.get_child(Side::Left).and_then(f).unwrap_or(1),
-            self.get_child(Side::Right).and_then(f).unwrap_or(1),
+            self.get_child(Side::Left).map(f).unwrap_or(1),
+            self.get_child(Side::Right).map(f).unwrap_or(1),
         )
     }
 
-    fn get_depth(&self) -> usize {
-        let f = |c| Some(1 + self.get(c).get_depth());
-        self.get_parent().and_then(f).unwrap_or(0)
+    fn get_depth(&self, data: &[Self]) -> usize {
+        let f = |c: usize| 1 + data[c].get_depth(data);
+        self.get_parent().map(f).unwrap_or(0)
     }
 
-    fn get_size(&self) -> usize {
-        let f = |c| Some(self.get(c).get_size());
+    fn get_size(&self, data: &[Self]) -> usize {
+        let f = |c: usize| data[c].get_size(data);
 
-        1 + self.get_child(Side::Left).and_then(f).unwrap_or(0)
-            + self.get_child(Side::Right).and_then(f).unwrap_or(0)
+        1 + self.get_child(Side::Left).map(f).unwrap_or(0)
+            + self.get_child(Side::Right).map(f).unwrap_or(0)
     }
 
-    fn get_leaf_count(&self) -> usize {
-        let f = |c| Some(self.get(c).get_leaf_count());
-        let val = self.get_child(Side::Left).and_then(f).unwrap_or(0) + self.get_child(Side::Right).and_then(f).unwrap_or(0);
+    fn get_leaf_count(&self, data: &[Self]) -> usize {
+        let f = |c: usize| data[c].get_leaf_count(data);
+        let val = self.get_child(Side::Left).map(f).unwrap_or(0)
+            + self.get_child(Side::Right).map(f).unwrap_or(0);
         if val == 0 {
             1
         } else {
@@ -127,28 +178,60 @@ pub trait Node<T> {
         }
     }
 
-    fn find_min(&self) -> usize {
+    fn find_min(&self, data: &[Self]) -> usize {
         if let Some(l) = self.get_child(Side::Left) {
-            self.get(l).find_min()
+            data[l].find_min(data)
         } else {
             self.location()
         }
     }
 
-    fn side(&self) -> Side {
-        if self.is_child(Side::Left) {
+    fn find_max(&self, data: &[Self]) -> usize {
+        if let Some(r) = self.get_child(Side::Right) {
+            data[r].find_max(data)
+        } else {
+            self.location()
+        }
+    }
+
+    // the next node in sorted order, found through the parent/child links
+    fn get_successor(&self, data: &[Self]) -> Option<usize> {
+        if let Some(r) = self.get_child(Side::Right) {
+            return Some(data[r].find_min(data));
+        }
+        let mut n = self;
+        while n.is_child(Side::Right, data) {
+            n = &data[n.get_parent().unwrap()];
+        }
+        n.get_parent()
+    }
+
+    // the previous node in sorted order, found through the parent/child links
+    fn get_predecessor(&self, data: &[Self]) -> Option<usize> {
+        if let Some(l) = self.get_child(Side::Left) {
+            return Some(data[l].find_max(data));
+        }
+        let mut n = self;
+        while n.is_child(Side::Left, data) {
+            n = &data[n.get_parent().unwrap()];
+        }
+        n.get_parent()
+    }
+
+    fn side(&self, data: &[Self]) -> Side {
+        if self.is_child(Side::Left, data) {
             Side::Left
         } else {
             Side::Right
         }
     }
 
-    fn get_sibling(&self) -> Option<usize> {
+    fn get_sibling(&self, data: &[Self]) -> Option<usize> {
         if let Some(p) = self.get_parent() {
-            let parent = self.get(p);
-            if self.is_child(Side::Left) {
+            let parent = &data[p];
+            if self.is_child(Side::Left, data) {
                 parent.get_child(Side::Right)
-            } else if self.is_child(Side::Right) {
+            } else if self.is_child(Side::Right, data) {
                 parent.get_child(Side::Left)
             } else {
                 None
@@ -158,16 +241,15 @@ pub trait Node<T> {
         }
     }
 
-    fn get_uncle(&self) -> Option<usize> {
+    fn get_uncle(&self, data: &[Self]) -> Option<usize> {
         self.get_parent()
-            .and_then(|p| Some(self.get(p)))
-            .and_then(|p| p.get_sibling())
+            .map(|p| &data[p])
+            .and_then(|p| p.get_sibling(data))
     }
 
-    fn is_child(&self, side: Side) -> bool {
+    fn is_child(&self, side: Side, data: &[Self]) -> bool {
         if let Some(p) = self.get_parent() {
-            let parent = self.get(p);
-            parent.get_child(side).is_some() && parent.get_child(side).unwrap() == self.location()
+            data[p].get_child(side) == Some(self.location())
         } else {
             false
         }
@@ -179,13 +261,10 @@ mod tests {
     use super::*;
     use super::Node;
     use crate::rbtree::ColorNode;
-    use crate::rbtree::ColoredNode;
-    use std::rc::Rc;
-    use std::cell::RefCell;
 
-    fn attach_child(data: &mut Vec<ColorNode<i32>>, p: usize, c: usize, side: Side) {
-        let par = &mut data[p];
-        par.set_child(c, side)
+    fn attach_child(data: &mut [ColorNode<i32>], p: usize, c: usize, side: Side) {
+        data[p].set_child(c, side);
+        data[c].set_parent(Some(p));
     }
     /*
     ========
@@ -200,38 +279,34 @@ mod tests {
          ([P:Some(0) C:Black V:6]
               ([P:Some(2) C:Black V:7])))
     */
-    fn make_fake_tree_node() -> Rc<RefCell<Vec<ColorNode<i32>>>> {
-        let rc = Rc::new(RefCell::new(Vec::new()));
-        let v = vec![
-            ColorNode::new(5, 0, rc.clone()),
-            ColorNode::new(4, 1, rc.clone()),
-            ColorNode::new(6, 2, rc.clone()),
-            ColorNode::new(0, 3, rc.clone()),
-            ColorNode::new(1, 4, rc.clone()),
-            ColorNode::new(7, 5, rc.clone()),
+    fn make_fake_tree_node() -> Vec<ColorNode<i32>> {
+        let mut data = vec![
+            ColorNode::new(5, 0),
+            ColorNode::new(4, 1),
+            ColorNode::new(6, 2),
+            ColorNode::new(0, 3),
+            ColorNode::new(1, 4),
+            ColorNode::new(7, 5),
         ];
-        (*rc).replace(v);
-        let ptrs: Vec<usize> = (*rc).borrow_mut().iter().map(|v| v.ptr).collect();
-        attach_child(&mut (*rc).borrow_mut(), ptrs[0], ptrs[4], Side::Left);
-        attach_child(&mut (*rc).borrow_mut(), ptrs[0], ptrs[2], Side::Right);
-        attach_child(&mut (*rc).borrow_mut(), ptrs[4], ptrs[3], Side::Left);
-        attach_child(&mut (*rc).borrow_mut(), ptrs[4], ptrs[1], Side::Right);
-        attach_child(&mut (*rc).borrow_mut(), ptrs[2], ptrs[5], Side::Right);
-        rc
+        let ptrs: Vec<usize> = data.iter().map(|v| v.ptr).collect();
+        attach_child(&mut data, ptrs[0], ptrs[4], Side::Left);
+        attach_child(&mut data, ptrs[0], ptrs[2], Side::Right);
+        attach_child(&mut data, ptrs[4], ptrs[3], Side::Left);
+        attach_child(&mut data, ptrs[4], ptrs[1], Side::Right);
+        attach_child(&mut data, ptrs[2], ptrs[5], Side::Right);
+        data
     }
 
     #[test]
     fn print_tree_test() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
+        let data = make_fake_tree_node();
         let root = &data[0];
-        assert_eq!(root.to_string(), "([P:None C:Black V:5] ([P:Some(0) C:Black V:1] ([P:Some(4) C:Black V:0] () ()) ([P:Some(4) C:Black V:4] () ())) ([P:Some(0) C:Black V:6] () ([P:Some(2) C:Black V:7] () ())))");
+        assert_eq!(root.to_string(&data), "([P:None C:Black V:5] ([P:Some(0) C:Black V:1] ([P:Some(4) C:Black V:0] () ()) ([P:Some(4) C:Black V:4] () ())) ([P:Some(0) C:Black V:6] () ([P:Some(2) C:Black V:7] () ())))");
     }
 
     #[test]
     fn get_child_test() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
+        let data = make_fake_tree_node();
         let root = &data[0];
 
         assert_eq!(root.get_child(Side::Left), Some(4));
@@ -246,66 +321,60 @@ mod tests {
             Some(3)
         );
 
-        assert_eq!(root.value, 5);
-        assert_eq!(data[root.get_child(Side::Left).unwrap()].value, 1);
+        assert_eq!(*root.get_value(), 5);
+        assert_eq!(*data[root.get_child(Side::Left).unwrap()].get_value(), 1);
     }
 
     #[test]
     fn get_sibling_test() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
-        assert_eq!(data[2].get_sibling(), Some(4));
-        assert_eq!(data[4].get_sibling(), Some(2));
-        assert_eq!(data[0].get_sibling(), None);
-        assert_eq!(data[3].get_sibling(), Some(1));
-        assert_eq!(data[1].get_sibling(), Some(3));
-        assert_eq!(data[5].get_sibling(), None);
+        let data = make_fake_tree_node();
+        assert_eq!(data[2].get_sibling(&data), Some(4));
+        assert_eq!(data[4].get_sibling(&data), Some(2));
+        assert_eq!(data[0].get_sibling(&data), None);
+        assert_eq!(data[3].get_sibling(&data), Some(1));
+        assert_eq!(data[1].get_sibling(&data), Some(3));
+        assert_eq!(data[5].get_sibling(&data), None);
     }
 
     #[test]
     fn get_uncle_test() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
-        assert_eq!(data[0].get_uncle(), None);
-        assert_eq!(data[1].get_uncle(), Some(2));
-        assert_eq!(data[2].get_uncle(), None);
-        assert_eq!(data[3].get_uncle(), Some(2));
-        assert_eq!(data[4].get_uncle(), None);
-        assert_eq!(data[5].get_uncle(), Some(4));
+        let data = make_fake_tree_node();
+        assert_eq!(data[0].get_uncle(&data), None);
+        assert_eq!(data[1].get_uncle(&data), Some(2));
+        assert_eq!(data[2].get_uncle(&data), None);
+        assert_eq!(data[3].get_uncle(&data), Some(2));
+        assert_eq!(data[4].get_uncle(&data), None);
+        assert_eq!(data[5].get_uncle(&data), Some(4));
     }
 
     #[test]
     fn get_size() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
-        assert_eq!(data[0].get_size(), 6);
-        assert_eq!(data[4].get_size(), 3);
-        assert_eq!(data[5].get_size(), 1);
+        let data = make_fake_tree_node();
+        assert_eq!(data[0].get_size(&data), 6);
+        assert_eq!(data[4].get_size(&data), 3);
+        assert_eq!(data[5].get_size(&data), 1);
     }
 
     #[test]
     fn get_height() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
-        assert_eq!(data[0].get_height(), 3);
-        assert_eq!(data[4].get_height(), 2);
-        assert_eq!(data[5].get_height(), 1);
+        let data = make_fake_tree_node();
+        assert_eq!(data[0].get_height(&data), 3);
+        assert_eq!(data[4].get_height(&data), 2);
+        assert_eq!(data[5].get_height(&data), 1);
     }
 
     #[test]
     fn find_min() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
-        assert_eq!(data[(data[0].find_min())].value, 0);
-        assert_eq!(data[(data[2].find_min())].value, 6);
+        let data = make_fake_tree_node();
+        assert_eq!(*data[data[0].find_min(&data)].get_value(), 0);
+        assert_eq!(*data[data[2].find_min(&data)].get_value(), 6);
     }
 
     #[test]
     fn find_leaf_count() {
-        let rc = make_fake_tree_node();
-        let data = rc.borrow_mut();
-        println!("{}", data[0].to_pretty_string(1));
-        assert_eq!(data[0].get_leaf_count(), 3);
+        let data = make_fake_tree_node();
+        println!("{}", data[0].to_pretty_string(1, &data));
+        assert_eq!(data[0].get_leaf_count(&data), 3);
     }
 }
 
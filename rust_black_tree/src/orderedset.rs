@@ -0,0 +1,296 @@
+use std::ops::{Bound, RangeBounds};
+
+use super::aatree::AATree;
+use super::augment::Augment;
+use super::avltree::AVLTree;
+use super::btree::BTree;
+use super::comparator::Comparator;
+use super::rbtree::RBTree;
+use super::scapegoattree::ScapegoatTree;
+use super::splaytree::SplayTree;
+use super::treap::Treap;
+use super::tree::{BaseTree, InvariantError, Tree};
+use super::unbalancetree::BSTree;
+
+/**
+ * The query, insert and delete methods every tree has, whatever its nodes
+ * look like. Tree needs binary nodes it can hand out by index, which BTree
+ * doesn't have, so code that only asks questions of a set and changes it
+ * through values should be written against OrderedSet to work with BTree
+ * too.
+ *
+ * The methods share their names with Tree's, so a concrete tree with both
+ * traits in scope needs them spelled out as Tree::insert(&mut t, 3). Generic
+ * code bounded by OrderedSet alone never sees the clash
+ */
+pub trait OrderedSet<T> {
+    fn get_size(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.get_size() == 0
+    }
+
+    fn contains(&self, val: &T) -> bool;
+
+    /// adds val, or does nothing if an equal value is already in the set
+    fn insert(&mut self, val: T);
+
+    /// removes val, returning false if it wasn't there
+    fn delete(&mut self, val: T) -> bool;
+
+    fn pop_first(&mut self) -> Option<T>;
+    fn pop_last(&mut self) -> Option<T>;
+
+    fn first(&self) -> Option<&T>;
+    fn last(&self) -> Option<&T>;
+    fn floor(&self, val: &T) -> Option<&T>;
+    fn ceiling(&self, val: &T) -> Option<&T>;
+    fn predecessor(&self, val: &T) -> Option<&T>;
+    fn successor(&self, val: &T) -> Option<&T>;
+
+    /// the number of values smaller than val
+    fn rank(&self, val: &T) -> usize;
+
+    /// the k-th smallest value, counting from 0
+    fn select(&self, k: usize) -> Option<&T>;
+
+    /// the number of values inside of the range, given by its two bounds so
+    /// that the trait can be used as a trait object
+    fn count_between(&self, start: Bound<&T>, end: Bound<&T>) -> usize;
+
+    fn count_range<R: RangeBounds<T>>(&self, range: R) -> usize
+    where
+        Self: Sized,
+    {
+        self.count_between(range.start_bound(), range.end_bound())
+    }
+
+    fn validate(&self) -> Result<(), InvariantError>;
+
+    /// the values in sorted order, boxed since every tree walks itself with
+    /// an iterator of its own
+    fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = &T> + '_>;
+}
+
+// every binary tree answers through the Tree methods of the same name
+macro_rules! through_tree {
+    ($tree:ident $(, $param:ident: $bound:path)*) => {
+        impl<T, C: Comparator<T> $(, $param: $bound)*> OrderedSet<T> for $tree<T, C $(, $param)*> {
+            fn get_size(&self) -> usize {
+                BaseTree::get_size(self)
+            }
+
+            fn is_empty(&self) -> bool {
+                Tree::is_empty(self)
+            }
+
+            fn contains(&self, val: &T) -> bool {
+                Tree::contains(self, val)
+            }
+
+            fn insert(&mut self, val: T) {
+                Tree::insert(self, val)
+            }
+
+            fn delete(&mut self, val: T) -> bool {
+                Tree::delete(self, val)
+            }
+
+            fn pop_first(&mut self) -> Option<T> {
+                Tree::pop_first(self)
+            }
+
+            fn pop_last(&mut self) -> Option<T> {
+                Tree::pop_last(self)
+            }
+
+            fn first(&self) -> Option<&T> {
+                Tree::first(self)
+            }
+
+            fn last(&self) -> Option<&T> {
+                Tree::last(self)
+            }
+
+            fn floor(&self, val: &T) -> Option<&T> {
+                Tree::floor(self, val)
+            }
+
+            fn ceiling(&self, val: &T) -> Option<&T> {
+                Tree::ceiling(self, val)
+            }
+
+            fn predecessor(&self, val: &T) -> Option<&T> {
+                Tree::predecessor(self, val)
+            }
+
+            fn successor(&self, val: &T) -> Option<&T> {
+                Tree::successor(self, val)
+            }
+
+            fn rank(&self, val: &T) -> usize {
+                Tree::rank(self, val)
+            }
+
+            fn select(&self, k: usize) -> Option<&T> {
+                Tree::select(self, k)
+            }
+
+            fn count_between(&self, start: Bound<&T>, end: Bound<&T>) -> usize {
+                Tree::count_range(self, (start, end))
+            }
+
+            fn validate(&self) -> Result<(), InvariantError> {
+                Tree::validate(self)
+            }
+
+            fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = &T> + '_> {
+                Box::new(Tree::iter(self))
+            }
+        }
+    };
+}
+
+through_tree!(RBTree, A: Augment<T>);
+through_tree!(AVLTree, A: Augment<T>);
+through_tree!(BSTree);
+through_tree!(SplayTree);
+through_tree!(Treap);
+through_tree!(AATree);
+through_tree!(ScapegoatTree);
+
+impl<T, const B: usize, C: Comparator<T>> OrderedSet<T> for BTree<T, B, C> {
+    fn get_size(&self) -> usize {
+        BTree::get_size(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        BTree::is_empty(self)
+    }
+
+    fn contains(&self, val: &T) -> bool {
+        BTree::contains(self, val)
+    }
+
+    fn insert(&mut self, val: T) {
+        BTree::insert(self, val)
+    }
+
+    fn delete(&mut self, val: T) -> bool {
+        BTree::delete(self, val)
+    }
+
+    fn pop_first(&mut self) -> Option<T> {
+        BTree::pop_first(self)
+    }
+
+    fn pop_last(&mut self) -> Option<T> {
+        BTree::pop_last(self)
+    }
+
+    fn first(&self) -> Option<&T> {
+        BTree::first(self)
+    }
+
+    fn last(&self) -> Option<&T> {
+        BTree::last(self)
+    }
+
+    fn floor(&self, val: &T) -> Option<&T> {
+        BTree::floor(self, val)
+    }
+
+    fn ceiling(&self, val: &T) -> Option<&T> {
+        BTree::ceiling(self, val)
+    }
+
+    fn predecessor(&self, val: &T) -> Option<&T> {
+        BTree::predecessor(self, val)
+    }
+
+    fn successor(&self, val: &T) -> Option<&T> {
+        BTree::successor(self, val)
+    }
+
+    fn rank(&self, val: &T) -> usize {
+        BTree::rank(self, val)
+    }
+
+    fn select(&self, k: usize) -> Option<&T> {
+        BTree::select(self, k)
+    }
+
+    fn count_between(&self, start: Bound<&T>, end: Bound<&T>) -> usize {
+        BTree::count_range(self, (start, end))
+    }
+
+    fn validate(&self) -> Result<(), InvariantError> {
+        BTree::validate(self)
+    }
+
+    fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = &T> + '_> {
+        Box::new(BTree::iter(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparator::Natural;
+    use std::collections::BTreeSet;
+
+    // the same scrambled inserts and deletes through nothing but OrderedSet
+    fn churn<S: OrderedSet<u32> + Default>() {
+        let mut set = S::default();
+        let mut want = BTreeSet::new();
+        let mut x: u32 = 1;
+        for step in 0..1500 {
+            x = x * 7919 % 1009;
+            if step % 3 == 2 {
+                assert_eq!(set.delete(x), want.remove(&x));
+            } else {
+                set.insert(x);
+                want.insert(x);
+            }
+        }
+        assert_eq!(set.validate(), Ok(()));
+        assert_eq!(set.get_size(), want.len());
+        assert!(set.iter().eq(want.iter()));
+        assert!(set.iter().rev().eq(want.iter().rev()));
+        assert_eq!(set.floor(&500), want.range(..=500).next_back());
+        assert_eq!(set.successor(&500), want.range(501..).next());
+        assert_eq!(set.rank(&500), want.range(..500).count());
+        assert_eq!(set.select(10), want.iter().nth(10));
+        assert_eq!(set.count_range(100..=200), want.range(100..=200).count());
+        assert_eq!(set.pop_first(), want.pop_first());
+        assert_eq!(set.pop_last(), want.pop_last());
+    }
+
+    #[test]
+    fn every_tree() {
+        churn::<RBTree<u32>>();
+        churn::<AVLTree<u32>>();
+        churn::<BSTree<u32>>();
+        churn::<SplayTree<u32>>();
+        churn::<Treap<u32>>();
+        churn::<AATree<u32>>();
+        churn::<ScapegoatTree<u32>>();
+        churn::<BTree<u32>>();
+        churn::<BTree<u32, 3>>();
+    }
+
+    #[test]
+    fn trait_objects() {
+        let mut sets: Vec<Box<dyn OrderedSet<i32>>> = vec![
+            Box::new(RBTree::<i32, Natural>::new()),
+            Box::new(BTree::<i32, 4>::new()),
+        ];
+        for set in sets.iter_mut() {
+            for x in (0..100).rev() {
+                set.insert(x);
+            }
+            assert_eq!(set.count_between(Bound::Included(&10), Bound::Excluded(&20)), 10);
+            assert_eq!(set.iter().nth(42), Some(&42));
+        }
+    }
+}
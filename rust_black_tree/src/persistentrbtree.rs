@@ -0,0 +1,717 @@
+use std::cmp::{max, Ordering};
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+use std::ops::{Bound, RangeBounds};
+use std::rc::Rc;
+
+use super::comparator::{Comparator, Natural};
+use super::node::Color::{self, Black, Red};
+use super::tree::InvariantError;
+
+const BROKEN: &str = "persistent red black tree lost its balance";
+
+type Link<T> = Option<Rc<PersistentNode<T>>>;
+
+/// a node is never changed once it is made, so any number of trees can
+/// point at it
+#[derive(Debug)]
+struct PersistentNode<T> {
+    color: Color,
+    value: T,
+    left: Link<T>,
+    right: Link<T>,
+    /// number of nodes in the subtree rooted here, including this one
+    size: usize,
+}
+
+impl<T: Debug> PersistentNode<T> {
+    fn write(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "([C:{:?} V:{:?}]", self.color, self.value)?;
+        for child in &[&self.left, &self.right] {
+            match child {
+                Some(c) => {
+                    write!(f, " ")?;
+                    c.write(f)?;
+                }
+                None => write!(f, " ()")?,
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+fn len<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |n| n.size)
+}
+
+fn mk<T>(color: Color, left: Link<T>, value: T, right: Link<T>) -> Link<T> {
+    let size = len(&left) + len(&right) + 1;
+    Some(Rc::new(PersistentNode {
+        color,
+        value,
+        left,
+        right,
+        size,
+    }))
+}
+
+fn red<T>(link: &Link<T>) -> Option<&Rc<PersistentNode<T>>> {
+    link.as_ref().filter(|n| n.color == Red)
+}
+
+fn black<T>(link: &Link<T>) -> Option<&Rc<PersistentNode<T>>> {
+    link.as_ref().filter(|n| n.color == Black)
+}
+
+/// n in the given color, shared as it is if it already has it
+fn paint<T: Clone>(n: &Rc<PersistentNode<T>>, color: Color) -> Link<T> {
+    if n.color == color {
+        Some(n.clone())
+    } else {
+        mk(color, n.left.clone(), n.value.clone(), n.right.clone())
+    }
+}
+
+/// turns a black node red, which takes one off the black height below it
+fn sub1<T: Clone>(link: &Link<T>) -> Link<T> {
+    paint(black(link).expect(BROKEN), Red)
+}
+
+/**
+ * Builds a black node out of a, x and b, unless one of them starts with two
+ * reds in a row. Those four shapes, and a node with two red children, all
+ * come out as a red node over two black ones instead
+ */
+fn balance<T: Clone>(a: Link<T>, x: T, b: Link<T>) -> Link<T> {
+    if let (Some(l), Some(r)) = (red(&a), red(&b)) {
+        return mk(Red, paint(l, Black), x, paint(r, Black));
+    }
+    if let Some(l) = red(&a) {
+        if let Some(ll) = red(&l.left) {
+            let right = mk(Black, l.right.clone(), x, b);
+            return mk(Red, paint(ll, Black), l.value.clone(), right);
+        }
+        if let Some(lr) = red(&l.right) {
+            let left = mk(Black, l.left.clone(), l.value.clone(), lr.left.clone());
+            let right = mk(Black, lr.right.clone(), x, b);
+            return mk(Red, left, lr.value.clone(), right);
+        }
+    }
+    if let Some(r) = red(&b) {
+        if let Some(rr) = red(&r.right) {
+            let left = mk(Black, a, x, r.left.clone());
+            return mk(Red, left, r.value.clone(), paint(rr, Black));
+        }
+        if let Some(rl) = red(&r.left) {
+            let left = mk(Black, a, x, rl.left.clone());
+            let right = mk(Black, rl.right.clone(), r.value.clone(), r.right.clone());
+            return mk(Red, left, rl.value.clone(), right);
+        }
+    }
+    mk(Black, a, x, b)
+}
+
+fn ins<T: Clone, C: Comparator<T>>(link: &Link<T>, x: T, cmp: &C) -> Link<T> {
+    let n = match link {
+        Some(n) => n,
+        None => return mk(Red, None, x, None),
+    };
+    match (cmp.compare(&x, &n.value), n.color) {
+        (Ordering::Less, Black) => balance(ins(&n.left, x, cmp), n.value.clone(), n.right.clone()),
+        (Ordering::Less, Red) => mk(Red, ins(&n.left, x, cmp), n.value.clone(), n.right.clone()),
+        (Ordering::Greater, Black) => {
+            balance(n.left.clone(), n.value.clone(), ins(&n.right, x, cmp))
+        }
+        (Ordering::Greater, Red) => mk(Red, n.left.clone(), n.value.clone(), ins(&n.right, x, cmp)),
+        (Ordering::Equal, _) => Some(n.clone()),
+    }
+}
+
+/**
+ * Removes x, which has to be somewhere below link. Going down through a black
+ * node leaves its subtree one black short, which bal_left and bal_right make
+ * up for on the way back up
+ */
+fn del<T: Clone, C: Comparator<T>>(link: &Link<T>, x: &T, cmp: &C) -> Link<T> {
+    let n = link.as_ref()?;
+    match cmp.compare(x, &n.value) {
+        Ordering::Less if black(&n.left).is_some() => {
+            bal_left(del(&n.left, x, cmp), n.value.clone(), n.right.clone())
+        }
+        Ordering::Less => mk(Red, del(&n.left, x, cmp), n.value.clone(), n.right.clone()),
+        Ordering::Greater if black(&n.right).is_some() => {
+            bal_right(n.left.clone(), n.value.clone(), del(&n.right, x, cmp))
+        }
+        Ordering::Greater => mk(Red, n.left.clone(), n.value.clone(), del(&n.right, x, cmp)),
+        Ordering::Equal => fuse(&n.left, &n.right),
+    }
+}
+
+/// joins l, x and r where l is one black shorter than r
+fn bal_left<T: Clone>(l: Link<T>, x: T, r: Link<T>) -> Link<T> {
+    if let Some(ln) = red(&l) {
+        return mk(Red, paint(ln, Black), x, r);
+    }
+    if let Some(rn) = black(&r) {
+        return balance(l, x, paint(rn, Red));
+    }
+    let rn = red(&r).expect(BROKEN);
+    let rl = black(&rn.left).expect(BROKEN);
+    let left = mk(Black, l, x, rl.left.clone());
+    let right = balance(rl.right.clone(), rn.value.clone(), sub1(&rn.right));
+    mk(Red, left, rl.value.clone(), right)
+}
+
+/// joins l, x and r where r is one black shorter than l
+fn bal_right<T: Clone>(l: Link<T>, x: T, r: Link<T>) -> Link<T> {
+    if let Some(rn) = red(&r) {
+        return mk(Red, l, x, paint(rn, Black));
+    }
+    if let Some(ln) = black(&l) {
+        return balance(paint(ln, Red), x, r);
+    }
+    let ln = red(&l).expect(BROKEN);
+    let lr = black(&ln.right).expect(BROKEN);
+    let left = balance(sub1(&ln.left), ln.value.clone(), lr.left.clone());
+    let right = mk(Black, lr.right.clone(), x, r);
+    mk(Red, left, lr.value.clone(), right)
+}
+
+/// joins the two children of a removed node, every value in a being smaller
+/// than every value in b
+fn fuse<T: Clone>(a: &Link<T>, b: &Link<T>) -> Link<T> {
+    let (an, bn) = match (a, b) {
+        (None, _) => return b.clone(),
+        (_, None) => return a.clone(),
+        (Some(an), Some(bn)) => (an, bn),
+    };
+    match (an.color, bn.color) {
+        (Red, Red) => {
+            let mid = fuse(&an.right, &bn.left);
+            match red(&mid) {
+                Some(m) => {
+                    let left = mk(Red, an.left.clone(), an.value.clone(), m.left.clone());
+                    let right = mk(Red, m.right.clone(), bn.value.clone(), bn.right.clone());
+                    mk(Red, left, m.value.clone(), right)
+                }
+                None => {
+                    let right = mk(Red, mid, bn.value.clone(), bn.right.clone());
+                    mk(Red, an.left.clone(), an.value.clone(), right)
+                }
+            }
+        }
+        (Black, Black) => {
+            let mid = fuse(&an.right, &bn.left);
+            match red(&mid) {
+                Some(m) => {
+                    let left = mk(Black, an.left.clone(), an.value.clone(), m.left.clone());
+                    let right = mk(Black, m.right.clone(), bn.value.clone(), bn.right.clone());
+                    mk(Red, left, m.value.clone(), right)
+                }
+                None => {
+                    let right = mk(Black, mid, bn.value.clone(), bn.right.clone());
+                    bal_left(an.left.clone(), an.value.clone(), right)
+                }
+            }
+        }
+        (Black, Red) => mk(Red, fuse(a, &bn.left), bn.value.clone(), bn.right.clone()),
+        (Red, Black) => mk(Red, an.left.clone(), an.value.clone(), fuse(&an.right, b)),
+    }
+}
+
+fn blacken<T: Clone>(link: Link<T>) -> Link<T> {
+    match red(&link) {
+        Some(n) => paint(n, Black),
+        None => link,
+    }
+}
+
+/**
+ * A red black tree that is never changed in place. insert and delete hand
+ * back a new tree and leave the old one as it was; the two share every node
+ * off the path that was rebuilt, so keeping an old version around costs
+ * O(log n) nodes per change rather than a copy of the tree. Cloning a tree
+ * only copies the pointer to its root
+ */
+#[derive(Debug)]
+pub struct PersistentRBTree<T, C = Natural> {
+    root: Link<T>,
+    cmp: C,
+}
+
+impl<T, C: Clone> Clone for PersistentRBTree<T, C> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            cmp: self.cmp.clone(),
+        }
+    }
+}
+
+impl<T: PartialOrd> PersistentRBTree<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+}
+
+impl<T: PartialOrd> Default for PersistentRBTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialOrd + Clone> FromIterator<T> for PersistentRBTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |tree, val| tree.insert(val))
+    }
+}
+
+impl<T, C: Comparator<T>> PersistentRBTree<T, C> {
+    /// an empty tree that orders its values with cmp
+    pub fn with_comparator(cmp: C) -> Self {
+        Self { root: None, cmp }
+    }
+
+    /// a new version of the tree holding val as well
+    pub fn insert(&self, val: T) -> Self
+    where
+        T: Clone,
+        C: Clone,
+    {
+        if self.contains(&val) {
+            return self.clone();
+        }
+        Self {
+            root: blacken(ins(&self.root, val, &self.cmp)),
+            cmp: self.cmp.clone(),
+        }
+    }
+
+    /// a new version of the tree without val
+    pub fn delete(&self, val: &T) -> Self
+    where
+        T: Clone,
+        C: Clone,
+    {
+        if !self.contains(val) {
+            return self.clone();
+        }
+        Self {
+            root: blacken(del(&self.root, val, &self.cmp)),
+            cmp: self.cmp.clone(),
+        }
+    }
+
+    /// true if both trees are the same version, or one was made from the
+    /// other without changing anything
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn get_size(&self) -> usize {
+        len(&self.root)
+    }
+
+    pub fn contains(&self, val: &T) -> bool {
+        let mut n = &self.root;
+        while let Some(node) = n {
+            n = match self.cmp.compare(&node.value, val) {
+                Ordering::Less => &node.right,
+                Ordering::Greater => &node.left,
+                Ordering::Equal => return true,
+            };
+        }
+        false
+    }
+
+    pub fn get_height(&self) -> usize {
+        fn height<T>(link: &Link<T>) -> usize {
+            link.as_ref()
+                .map_or(0, |n| 1 + max(height(&n.left), height(&n.right)))
+        }
+        height(&self.root)
+    }
+
+    pub fn get_leaf_count(&self) -> usize {
+        fn leaves<T>(link: &Link<T>) -> usize {
+            match link {
+                None => 0,
+                Some(n) => max(1, leaves(&n.left) + leaves(&n.right)),
+            }
+        }
+        leaves(&self.root)
+    }
+
+    /// iterate over the values of the tree in sorted order
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.range(..)
+    }
+
+    /// iterate in sorted order over the values of the tree that fall inside of
+    /// the given range
+    /// usage: tree.range(3..10), tree.range(..=7)
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Iter<'_, T> {
+        let mut stack = Vec::new();
+        let mut n = &self.root;
+        while let Some(node) = n {
+            if self.inside_start(&node.value, range.start_bound()) {
+                stack.push(&**node);
+                n = &node.left;
+            } else {
+                n = &node.right;
+            }
+        }
+        Iter {
+            stack,
+            remaining: self.count_range(range),
+        }
+    }
+
+    fn inside_start(&self, val: &T, bound: Bound<&T>) -> bool {
+        match bound {
+            Bound::Included(v) => self.cmp.compare(val, v) != Ordering::Less,
+            Bound::Excluded(v) => self.cmp.compare(val, v) == Ordering::Greater,
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn inside_end(&self, val: &T, bound: Bound<&T>) -> bool {
+        match bound {
+            Bound::Included(v) => self.cmp.compare(val, v) != Ordering::Greater,
+            Bound::Excluded(v) => self.cmp.compare(val, v) == Ordering::Less,
+            Bound::Unbounded => true,
+        }
+    }
+
+    /// the smallest value that is still inside of the bound
+    fn lower_bound(&self, bound: Bound<&T>) -> Option<&T> {
+        let mut n = &self.root;
+        let mut found = None;
+        while let Some(node) = n {
+            if self.inside_start(&node.value, bound) {
+                found = Some(&node.value);
+                n = &node.left;
+            } else {
+                n = &node.right;
+            }
+        }
+        found
+    }
+
+    /// the largest value that is still inside of the bound
+    fn upper_bound(&self, bound: Bound<&T>) -> Option<&T> {
+        let mut n = &self.root;
+        let mut found = None;
+        while let Some(node) = n {
+            if self.inside_end(&node.value, bound) {
+                found = Some(&node.value);
+                n = &node.right;
+            } else {
+                n = &node.left;
+            }
+        }
+        found
+    }
+
+    /// the smallest value in the tree
+    pub fn first(&self) -> Option<&T> {
+        self.lower_bound(Bound::Unbounded)
+    }
+
+    /// the largest value in the tree
+    pub fn last(&self) -> Option<&T> {
+        self.upper_bound(Bound::Unbounded)
+    }
+
+    /// the largest value no greater than val
+    pub fn floor(&self, val: &T) -> Option<&T> {
+        self.upper_bound(Bound::Included(val))
+    }
+
+    /// the smallest value no less than val
+    pub fn ceiling(&self, val: &T) -> Option<&T> {
+        self.lower_bound(Bound::Included(val))
+    }
+
+    /// the largest value strictly below val, whether or not val is in the tree
+    pub fn predecessor(&self, val: &T) -> Option<&T> {
+        self.upper_bound(Bound::Excluded(val))
+    }
+
+    /// the smallest value strictly above val, whether or not val is in the tree
+    pub fn successor(&self, val: &T) -> Option<&T> {
+        self.lower_bound(Bound::Excluded(val))
+    }
+
+    /// the number of values in the tree that are smaller than val
+    pub fn rank(&self, val: &T) -> usize {
+        self.count_below(val, false)
+    }
+
+    /// counts the values smaller than val, or no greater than it when
+    /// inclusive is set
+    pub fn count_below(&self, val: &T, inclusive: bool) -> usize {
+        let mut n = &self.root;
+        let mut count = 0;
+        while let Some(node) = n {
+            let ord = self.cmp.compare(&node.value, val);
+            if ord == Ordering::Less || (inclusive && ord == Ordering::Equal) {
+                count += len(&node.left) + 1;
+                n = &node.right;
+            } else {
+                n = &node.left;
+            }
+        }
+        count
+    }
+
+    /// the k-th smallest value in the tree, counting from 0
+    pub fn select(&self, k: usize) -> Option<&T> {
+        let mut n = &self.root;
+        let mut k = k;
+        while let Some(node) = n {
+            let left = len(&node.left);
+            n = match k.cmp(&left) {
+                Ordering::Less => &node.left,
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Greater => {
+                    k -= left + 1;
+                    &node.right
+                }
+            };
+        }
+        None
+    }
+
+    /// the number of values inside of the range, without visiting them
+    pub fn count_range<R: RangeBounds<T>>(&self, range: R) -> usize {
+        let below_start = match range.start_bound() {
+            Bound::Included(v) => self.count_below(v, false),
+            Bound::Excluded(v) => self.count_below(v, true),
+            Bound::Unbounded => 0,
+        };
+        let below_end = match range.end_bound() {
+            Bound::Included(v) => self.count_below(v, true),
+            Bound::Excluded(v) => self.count_below(v, false),
+            Bound::Unbounded => self.get_size(),
+        };
+        below_end.saturating_sub(below_start)
+    }
+
+    /**
+     * Checks the red black rules, the order of the values and the stored
+     * sizes. There is no arena here, so the nodes named in an error are
+     * counted by their position in sorted order
+     */
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        // returns the black height of the subtree, whose first node sits at
+        // position offset
+        fn check<T>(
+            link: &Link<T>,
+            offset: usize,
+            parent_red: Option<usize>,
+        ) -> Result<usize, InvariantError> {
+            let n = match link {
+                Some(n) => n,
+                None => return Ok(0),
+            };
+            let pos = offset + len(&n.left);
+            if let (Some(parent), Red) = (parent_red, n.color) {
+                return Err(InvariantError::RedRedViolation { parent, child: pos });
+            }
+            let me = if n.color == Red { Some(pos) } else { None };
+            let left = check(&n.left, offset, me)?;
+            let right = check(&n.right, pos + 1, me)?;
+            if left != right {
+                return Err(InvariantError::BlackHeightMismatch {
+                    node: pos,
+                    left,
+                    right,
+                });
+            }
+            let actual = len(&n.left) + len(&n.right) + 1;
+            if n.size != actual {
+                return Err(InvariantError::SizeWrong {
+                    node: pos,
+                    stored: n.size,
+                    actual,
+                });
+            }
+            Ok(left + if n.color == Black { 1 } else { 0 })
+        }
+
+        check(&self.root, 0, None)?;
+        let values: Vec<&T> = self.iter().collect();
+        for (i, pair) in values.windows(2).enumerate() {
+            if self.cmp.compare(pair[0], pair[1]) != Ordering::Less {
+                return Err(InvariantError::OrderViolation {
+                    node: i,
+                    next: i + 1,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the same nested form Tree::to_string gives, without the parent links
+impl<T: Debug, C> fmt::Display for PersistentRBTree<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.root {
+            Some(root) => root.write(f),
+            None => write!(f, "(Empty tree)"),
+        }
+    }
+}
+
+/// iterates over a persistent tree in sorted order, keeping the nodes it
+/// still has to come back to on a stack
+pub struct Iter<'a, T> {
+    stack: Vec<&'a PersistentNode<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.stack.pop()?;
+        let mut n = &node.right;
+        while let Some(c) = n {
+            self.stack.push(c);
+            n = &c.left;
+        }
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a PersistentRBTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn old_versions_stay_valid() {
+        let mut versions = vec![PersistentRBTree::new()];
+        for x in 0..200 {
+            let next = versions.last().unwrap().insert(x);
+            versions.push(next);
+        }
+        for x in (0..200).step_by(2) {
+            let next = versions.last().unwrap().delete(&x);
+            versions.push(next);
+        }
+        for (i, tree) in versions.iter().enumerate() {
+            let want: Vec<i32> = if i <= 200 {
+                (0..i as i32).collect()
+            } else {
+                let deleted = 2 * (i as i32 - 200);
+                (0..200).filter(|x| x % 2 == 1 || *x >= deleted).collect()
+            };
+            assert_eq!(tree.validate(), Ok(()), "version {}", i);
+            assert_eq!(
+                tree.iter().cloned().collect::<Vec<_>>(),
+                want,
+                "version {}",
+                i
+            );
+            assert_eq!(tree.get_size(), want.len());
+        }
+        assert!(versions[200].get_height() <= 2 * 8);
+    }
+
+    #[test]
+    fn shares_structure() {
+        let tree: PersistentRBTree<i32> = (0..64).collect();
+        let root = tree.root.as_ref().unwrap();
+        // only the path down to the new value is rebuilt
+        let bigger = tree.insert(1000);
+        let new_root = bigger.root.as_ref().unwrap();
+        assert!(Rc::ptr_eq(
+            root.left.as_ref().unwrap(),
+            new_root.left.as_ref().unwrap()
+        ));
+        assert!(!tree.contains(&1000));
+        assert_eq!(Rc::strong_count(root.left.as_ref().unwrap()), 2);
+
+        // nothing to change leaves the tree as it was
+        assert!(tree.insert(5).ptr_eq(&tree));
+        assert!(tree.delete(&100).ptr_eq(&tree));
+        assert!(!tree.delete(&5).ptr_eq(&tree));
+        assert!(PersistentRBTree::<i32>::new().ptr_eq(&PersistentRBTree::new()));
+    }
+
+    #[test]
+    // is_multiple_of would need Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    fn matches_btreeset() {
+        let mut tree = PersistentRBTree::new();
+        let mut set = BTreeSet::new();
+        let mut state: u64 = 42;
+        for _ in 0..3000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let v = (state % 300) as i32;
+            if state % 3 == 0 {
+                tree = tree.delete(&v);
+                set.remove(&v);
+            } else {
+                tree = tree.insert(v);
+                set.insert(v);
+            }
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.get_size(), set.len());
+            assert_eq!(tree.rank(&v), set.range(..v).count());
+            assert_eq!(tree.successor(&v), set.range(v + 1..).next());
+            assert_eq!(tree.floor(&v), set.range(..=v).next_back());
+        }
+        assert!(tree.iter().eq(set.iter()));
+        assert!(tree.range(100..200).eq(set.range(100..200)));
+        assert_eq!(tree.first(), set.iter().next());
+        assert_eq!(tree.last(), set.iter().next_back());
+        let k = set.len() / 2;
+        assert_eq!(tree.select(k), set.iter().nth(k));
+        assert_eq!(tree.select(set.len()), None);
+    }
+
+    #[test]
+    fn comparator() {
+        let tree = PersistentRBTree::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        let tree = (0..10).fold(tree, |t, x| t.insert(x));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.first(), Some(&9));
+        assert_eq!(tree.delete(&9).first(), Some(&8));
+        assert_eq!(tree.first(), Some(&9));
+        assert_eq!(
+            PersistentRBTree::new().insert(2).insert(1).to_string(),
+            "([C:Black V:2] ([C:Red V:1] () ()) ())"
+        );
+    }
+}
@@ -1,33 +1,67 @@
 use super::node::Node;
 use super::node::Side;
+use super::tree::BaseTree;
 
-use super::avltree::AVLNode;
-use super::rbtree::ColorNode;
-use super::unbalancetree::RegularNode;
+use super::aatree::AATree;
+use super::avltree::AVLTree;
+use super::rbtree::RBTree;
+use super::scapegoattree::ScapegoatTree;
+use super::splaytree::SplayTree;
+use super::treap::Treap;
+use super::unbalancetree::BSTree;
 
 const LEFT: &str = "╱";
 const RIGHT: &str = "╲";
 const ZWSP: &str = "​"; // this string contains a zero width space
 
 pub fn printprettybst<T: std::fmt::Debug + std::cmp::PartialOrd>(
-    node: &RegularNode<T>,
+    tree: &BSTree<T>,
 ) -> Option<String> {
-    print_node_pretty(node)
+    print_tree_pretty(tree)
 }
 
 pub fn printprettyrb<T: std::fmt::Debug + std::cmp::PartialOrd>(
-    node: &ColorNode<T>,
+    tree: &RBTree<T>,
 ) -> Option<String> {
-    print_node_pretty(node)
+    print_tree_pretty(tree)
 }
 
 pub fn printprettyavl<T: std::fmt::Debug + std::cmp::PartialOrd>(
-    node: &AVLNode<T>,
+    tree: &AVLTree<T>,
 ) -> Option<String> {
-    print_node_pretty(node)
+    print_tree_pretty(tree)
 }
 
-fn print_node_pretty<T: std::fmt::Debug, N: Node<T>>(node: &N) -> Option<String> {
+pub fn printprettysplay<T: std::fmt::Debug + std::cmp::PartialOrd>(
+    tree: &SplayTree<T>,
+) -> Option<String> {
+    print_tree_pretty(tree)
+}
+
+pub fn printprettytreap<T: std::fmt::Debug + std::cmp::PartialOrd>(
+    tree: &Treap<T>,
+) -> Option<String> {
+    print_tree_pretty(tree)
+}
+
+pub fn printprettyaa<T: std::fmt::Debug + std::cmp::PartialOrd>(
+    tree: &AATree<T>,
+) -> Option<String> {
+    print_tree_pretty(tree)
+}
+
+pub fn printprettyscapegoat<T: std::fmt::Debug + std::cmp::PartialOrd>(
+    tree: &ScapegoatTree<T>,
+) -> Option<String> {
+    print_tree_pretty(tree)
+}
+
+fn print_tree_pretty<T: std::fmt::Debug, B: BaseTree<T>>(tree: &B) -> Option<String> {
+    tree.get_root()
+        .and_then(|root| print_node_pretty(tree.get(root), tree.get_data()))
+}
+
+fn print_node_pretty<T: std::fmt::Debug, N: Node<T>>(node: &N, data: &[N]) -> Option<String> {
     let (grid_width, grid_height) = term_size::dimensions().unwrap_or((150, 100));
     let mut grid: Vec<Vec<String>> = Vec::with_capacity(grid_height);
     let mut used_depth = 0;
@@ -44,7 +78,7 @@ fn print_node_pretty<T: std::fmt::Debug, N: Node<T>>(node: &N) -> Option<String>
         x: usize,
         depth: usize,
         n: &N,
-        node: &N,
+        data: &[N],
         grid: &mut Vec<Vec<String>>,
         ud: &mut usize,
     ) -> bool {
@@ -53,11 +87,11 @@ fn print_node_pretty<T: std::fmt::Debug, N: Node<T>>(node: &N) -> Option<String>
         let val_str = " ".to_string() + &val_str;
         let lw = n
             .get_child(Side::Left)
-            .map(|x| node.get(x).get_size())
+            .map(|x| data[x].get_size(data))
             .unwrap_or(0);
         let rw = n
             .get_child(Side::Right)
-            .map(|x| node.get(x).get_size())
+            .map(|x| data[x].get_size(data))
             .unwrap_or(0);
         let mw = std::cmp::max(lw, rw) * cw;
         if x + mw >= grid[0].len() {
@@ -86,7 +120,7 @@ fn print_node_pretty<T: std::fmt::Debug, N: Node<T>>(node: &N) -> Option<String>
             for i in 1..mw {
                 grid[depth + i][x - i] = String::from(LEFT);
             }
-            if !fill_grid(x - mw, depth + mw, node.get(c), node, grid, ud) {
+            if !fill_grid(x - mw, depth + mw, &data[c], data, grid, ud) {
                 return false;
             }
         }
@@ -94,20 +128,20 @@ fn print_node_pretty<T: std::fmt::Debug, N: Node<T>>(node: &N) -> Option<String>
             for i in 1..mw {
                 grid[depth + i][x + i] = String::from(RIGHT);
             }
-            if !fill_grid(x + mw, depth + mw, node.get(c), node, grid, ud) {
+            if !fill_grid(x + mw, depth + mw, &data[c], data, grid, ud) {
                 return false;
             }
         }
-        return true;
+        true
     }
-    if !fill_grid(grid_width / 2, 0, node, node, &mut grid, &mut used_depth) {
+    if !fill_grid(grid_width / 2, 0, node, data, &mut grid, &mut used_depth) {
         return None;
     }
 
     // grid to string
     let mut res = String::from("");
-    for x in 0..used_depth {
-        res += &(grid[x].join("").trim_end().to_string() + "\n")
+    for row in grid.iter().take(used_depth) {
+        res += &(row.join("").trim_end().to_string() + "\n")
     }
     Some(res)
 }
@@ -117,7 +151,7 @@ fn print_node_pretty<T: std::fmt::Debug, N: Node<T>>(node: &N) -> Option<String>
 mod tests {
     use super::super::rbtree::RBTree;
     use super::super::avltree::AVLTree;
-    use super::super::tree::{BaseTree, Tree};
+    use super::super::tree::Tree;
     use super::*;
 
     #[test]
@@ -131,7 +165,7 @@ mod tests {
         t.insert(4);
         t.insert(6);
 
-        if let Some(s) = printprettyrb(t.get(t.get_root().unwrap())) {
+        if let Some(s) = printprettyrb(&t) {
             println!("{}", s);
         }
         assert!(false);
@@ -143,7 +177,7 @@ mod tests {
         for x in 0..20 {
             t.insert(x);
         }
-        if let Some(s) = printprettyrb(t.get(t.get_root().unwrap())) {
+        if let Some(s) = printprettyrb(&t) {
             println!("{}", s);
         }
         assert!(false);
@@ -155,7 +189,7 @@ mod tests {
         for x in &[100, 232, 754, 877, 123, 654, 546, 324, 654, 876] {
             t.insert(x);
         }
-        if let Some(s) = printprettyrb(t.get(t.get_root().unwrap())) {
+        if let Some(s) = printprettyrb(&t) {
             println!("{}", s);
         }
         assert!(false);
@@ -167,7 +201,7 @@ mod tests {
         for x in &[0, 20, -2, 30, -1, -3, 10, 40, -4] {
             t.insert(x);
         }
-        if let Some(s) = printprettyrb(t.get(t.get_root().unwrap())) {
+        if let Some(s) = printprettyrb(&t) {
             println!("{}", s);
         }
         assert!(false);
@@ -179,7 +213,7 @@ mod tests {
         for x in &[0, 20, -2, 30, -1, -3, 10, 40, -4] {
             t.insert(x);
         }
-        if let Some(s) = printprettyavl(t.get(t.get_root().unwrap())) {
+        if let Some(s) = printprettyavl(&t) {
             println!("{}", s);
         }
         assert!(false);
@@ -1,8 +1,21 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+use super::augment::{combine_at, fold_range, validate_summaries, Augment, Summarized};
+use super::comparator::{Comparator, Natural};
+use super::cursor::Cursors;
+use super::join::{self, link, Join, Subtree};
 use super::tree::BaseTree;
 use super::tree::Tree;
+use super::tree::{
+    compact, in_order, link_balanced, sorted_arena, swap_values, validate_links, IntoIter, Iter,
+};
+use super::tree::InvariantError;
+use super::tree::{Event, Observer};
+use super::snapshot::{
+    read_snapshot, write_snapshot, SnapshotError, SnapshotKind, SnapshotNode, SnapshotValue,
+};
 
 use super::node::Node;
 use super::node::{paint, endpaint};
@@ -25,40 +38,57 @@ macro_rules! redblack {
 }
 
 #[derive(Debug)]
-pub struct ColorNode<T> {
-    pub value: T,
+pub struct ColorNode<T, S = ()> {
+    pub value: Option<T>,
     pub ptr: usize,
     pub parent: Option<usize>,
     pub lchild: Option<usize>,
     pub rchild: Option<usize>,
     pub color: Color,
-    data: Rc<RefCell<Vec<ColorNode<T>>>>,
+    /// number of nodes in the subtree rooted here, including this one
+    pub size: usize,
+    /// what the tree's Augment made of the values in this subtree
+    pub summary: S,
 }
 
-pub trait ColoredNode<T>: Node<T> {
-    fn new(val: T, selfptr: usize, data: Rc<RefCell<Vec<ColorNode<T>>>>) -> Self;
-    fn is_red(&self) -> bool;
-    fn is_child_black(&self, side: Side) -> bool;
-    fn is_parent_black(&self) -> bool;
-    fn is_sibling_black(&self) -> bool;
+impl<T> ColorNode<T> {
+    pub fn new(val: T, selfptr: usize) -> Self {
+        Self::with_summary(val, selfptr, ())
+    }
 }
 
-impl<T> ColoredNode<T> for ColorNode<T>
-where
-    T: std::fmt::Debug,
-    T: std::cmp::PartialOrd,
-{
-    fn new(val: T, selfptr: usize, data: Rc<RefCell<Vec<ColorNode<T>>>>) -> Self {
+impl<T, S> ColorNode<T, S> {
+    pub fn with_summary(val: T, selfptr: usize, summary: S) -> Self {
         Self {
-            value: val,
+            value: Some(val),
             ptr: selfptr,
             parent: None,
             lchild: None,
             rchild: None,
             color: Color::Black,
-            data: data,
+            size: 1,
+            summary,
         }
     }
+}
+
+pub trait ColoredNode<T>: Node<T> {
+    fn color(&self) -> Color;
+    fn set_color(&mut self, color: Color);
+    fn is_red(&self) -> bool;
+    fn is_child_black(&self, side: Side, data: &[Self]) -> bool;
+    fn is_parent_black(&self, data: &[Self]) -> bool;
+    fn is_sibling_black(&self, data: &[Self]) -> bool;
+}
+
+impl<T, S> ColoredNode<T> for ColorNode<T, S> {
+    fn color(&self) -> Color {
+        self.color
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
 
     fn is_red(&self) -> bool {
         match self.color {
@@ -68,40 +98,52 @@ where
     }
 
     // Nil nodes are black children too
-    fn is_child_black(&self, side: Side) -> bool {
-        let child = self.get_child(side);
-        if child.is_some() && self.get(child.unwrap()).is_red() {
-            false
-        } else {
-            true
+    fn is_child_black(&self, side: Side, data: &[Self]) -> bool {
+        match self.get_child(side) {
+            Some(c) => !data[c].is_red(),
+            None => true,
         }
     }
 
     // this will panic of called on root node
-    fn is_parent_black(&self) -> bool {
+    fn is_parent_black(&self, data: &[Self]) -> bool {
         let p = self.parent.unwrap();
-        !self.get(p).is_red()
+        !data[p].is_red()
     }
 
     // Nil nodes are black children too
-    fn is_sibling_black(&self) -> bool {
-        let sib = self.get_sibling();
-        if sib.is_some() && self.get(sib.unwrap()).is_red() {
-            false
-        } else {
-            true
+    fn is_sibling_black(&self, data: &[Self]) -> bool {
+        match self.get_sibling(data) {
+            Some(s) => !data[s].is_red(),
+            None => true,
         }
     }
 }
 
-impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for ColorNode<T> {
-    fn to_self_string(&self) -> String {
+impl<T, S> Summarized<S> for ColorNode<T, S> {
+    fn summary(&self) -> &S {
+        &self.summary
+    }
+
+    fn set_summary(&mut self, summary: S) {
+        self.summary = summary;
+    }
+}
+
+impl<T, S> Node<T> for ColorNode<T, S> {
+    fn to_self_string(&self) -> String
+    where
+        T: Debug,
+    {
         format!(
             "[P:{:?} C:{:?} V:{:?}]",
-            self.parent, self.color, self.value
+            self.parent, self.color, self.get_value()
         )
     }
-    fn to_self_string_display(&self) -> (String, usize) {
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug,
+    {
         const RED: usize = 1;
         const BLK: usize = 0;
         const FG: usize = 30;
@@ -111,51 +153,52 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for ColorNode<T> {
                 format!(
                     "{}{:?}{}",
                     paint(FG + BLK, BG + RED),
-                    self.value,
+                    self.get_value(),
                     endpaint()
                 ),
-                format!("{:?}", self.value).len(),
+                format!("{:?}", self.get_value()).len(),
             )
         } else {
             (
                 format!(
                     "{}{:?}{}",
                     paint(FG + RED, BG + BLK),
-                    self.value,
+                    self.get_value(),
                     endpaint()
                 ),
-                format!("{:?}", self.value).len(),
+                format!("{:?}", self.get_value()).len(),
             )
         }
     }
 
-    fn is(&self, val: &T) -> bool {
-        &self.value == val
-    }
-    fn greater(&self, val: &T) -> bool {
-        &self.value > val
+    fn export_style(&self, _data: &[Self]) -> NodeStyle
+    where
+        T: Debug,
+    {
+        NodeStyle {
+            label: format!("{:?}", self.get_value()),
+            fill: if self.is_red() { "red" } else { "black" },
+            font: "white",
+            attributes: Vec::new(),
+        }
     }
-    fn lesser(&self, val: &T) -> bool {
-        &self.value < val
+
+    fn get_color(&self) -> Option<Color> {
+        Some(self.color)
     }
 
     fn get_value(&self) -> &T {
-        &self.value
+        self.value.as_ref().expect("freed node")
     }
-    /**
-     * In order to return a reference to a value of a vector contained within a
-     * refcell, a raw pointer is used. The unsafe code could be avoided by
-     * replacing each call to self.get(n) with &self.data.borrow()[n] and each call
-     * to self.get_mut(n) with &mut self.data.borrow()[n]
-     */
-    fn get(&self, ptr: usize) -> &ColorNode<T> {
-        unsafe { &(*self.data.as_ptr())[ptr] }
+    fn get_value_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("freed node")
     }
-
-    fn get_mut(&self, ptr: usize) -> &mut ColorNode<T> {
-        unsafe { &mut (*self.data.as_ptr())[ptr] }
+    fn into_value(self) -> T {
+        self.value.expect("freed node")
+    }
+    fn take_value(&mut self) -> T {
+        self.value.take().expect("freed node")
     }
-
     fn get_child(&self, side: Side) -> Option<usize> {
         match side {
             Side::Left => self.lchild,
@@ -172,9 +215,6 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for ColorNode<T> {
             Side::Left => self.lchild = c,
             Side::Right => self.rchild = c,
         };
-        if let Some(child) = c {
-            self.get_mut(child).parent = Some(self.location());
-        }
     }
     fn set_parent(&mut self, p: Option<usize>) {
         self.parent = p;
@@ -187,57 +227,162 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for ColorNode<T> {
     fn location(&self) -> usize {
         self.ptr
     }
+
+    fn set_location(&mut self, ptr: usize) {
+        self.ptr = ptr;
+    }
+
+    fn subtree_size(&self) -> usize {
+        self.size
+    }
+
+    fn set_subtree_size(&mut self, size: usize) {
+        self.size = size;
+    }
 }
 
 /**
- * Arena based memory tree structure
+ * Arena based memory tree structure, ordered by C. Every node keeps the
+ * summary A makes of its subtree, which is nothing unless A says otherwise
 */
 #[derive(Debug)]
-pub struct RBTree<T> {
+pub struct RBTree<T, C = Natural, A: Augment<T> = ()> {
     root: Option<usize>,
     size: usize,
-    data: Rc<RefCell<Vec<ColorNode<T>>>>,
+    data: Vec<ColorNode<T, A::Summary>>,
     free: Vec<usize>,
+    cmp: C,
+    augment: PhantomData<A>,
+    observer: Observer<Self>,
 }
 
-impl<T> Tree<T> for RBTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
-    fn new() -> Self {
+impl<T> SnapshotNode<T> for ColorNode<T> {
+    const KIND: SnapshotKind = SnapshotKind::RedBlack;
+
+    fn blank(val: T, ptr: usize) -> Self {
+        ColorNode::new(val, ptr)
+    }
+
+    fn write_extra(&self, out: &mut Vec<u8>) {
+        self.is_red().write(out);
+    }
+
+    fn read_extra(&mut self, input: &mut &[u8]) -> Result<(), SnapshotError> {
+        self.color = if bool::read(input)? {
+            Color::Red
+        } else {
+            Color::Black
+        };
+        Ok(())
+    }
+}
+
+impl<T, C: Comparator<T>, A: Augment<T>> Tree<T> for RBTree<T, C, A> {
+    fn with_comparator(cmp: C) -> Self {
         Self {
             root: None,
-            data: Rc::new(RefCell::new(Vec::new())),
+            data: Vec::new(),
             size: 0,
             free: Vec::new(),
+            cmp,
+            augment: PhantomData,
+            observer: Observer::default(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), InvariantError> {
+        validate_links(self)?;
+        if let Some(r) = self.root {
+            if self.get(r).is_red() {
+                return Err(InvariantError::RedRoot { node: r });
+            }
         }
+        self.root.map_or(Ok(0), |r| self.black_height(r))?;
+        validate_summaries::<T, A, _>(&self.data, self.root)
+    }
+
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Self {
+        let mut data = sorted_arena(iter, &cmp, |val, n| {
+            let summary = A::combine(&val, None, None);
+            ColorNode::with_summary(val, n, summary)
+        });
+        // the halves never differ by more than one node, so every path ends
+        // on the bottom two levels. Painting the deepest level red gives each
+        // path the same number of black nodes
+        let bottom = (usize::BITS - data.len().leading_zeros()).saturating_sub(1) as usize;
+        let root = link_balanced(&mut data, &mut |data: &mut [ColorNode<T, A::Summary>], n, depth| {
+            if depth == bottom && depth > 0 {
+                data[n].color = Color::Red;
+            }
+            data[n].summary = combine_at::<T, A, _>(data, n);
+        });
+        Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp,
+            augment: PhantomData,
+            observer: Observer::default(),
+        }
+    }
+
+    // split, join and the set operations go through Join instead of
+    // rebuilding both trees the way the defaults do
+    fn split(self, val: &T) -> (Self, Self)
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::split(self, val)
+    }
+
+    fn join(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::join(self, other)
+    }
+
+    fn union(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::union(self, other)
+    }
+
+    fn intersection(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::intersection(self, other)
+    }
+
+    fn difference(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        join::difference(self, other)
     }
 }
-const TREE_END: usize = 0xFFFFFFFF;
-impl<T> BaseTree<T> for RBTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
-    type MNode = ColorNode<T>;
-    /**
-     * In order to return a reference to a value of a vector contained within a refcell, a raw
-     * pointer is used. The unsafe code could be avoided by replacing each call to self.get(n) with
-     * &self.data.borrow()[n] and each call to self.get_mut(n) with &mut self.data.borrow()[n]. This
-     * allows us to do the same thing with less keystrokes. It does make the program not
-     * thread-safe, but a this data structure is a pretty terrible choice for a multi-threaded data
-     * structure anyways, since re-balancing can require that most of the tree be locked to one
-     * thread during an insertion or deletion
-     */
+
+impl<T, C: Comparator<T>, A: Augment<T>> BaseTree<T> for RBTree<T, C, A> {
+    type MNode = ColorNode<T, A::Summary>;
+    type Cmp = C;
     fn get(&self, val: usize) -> &Self::MNode {
-        unsafe { &(*self.data.as_ptr())[val] }
+        &self.data[val]
+    }
+
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode {
+        &mut self.data[val]
     }
 
-    fn get_mut(&self, val: usize) -> &mut Self::MNode {
-        unsafe { &mut (*self.data.as_ptr())[val] }
+    fn get_data(&self) -> &[Self::MNode] {
+        &self.data
     }
 
     fn get_root(&self) -> Option<usize> {
@@ -252,235 +397,368 @@ where
         self.size = (self.size as isize + amount) as usize;
     }
 
-    fn attach_child(&self, p: usize, c: usize, side: Side) {
-        self.get_mut(p).set_child(c, side)
+    fn update_summary(&mut self, n: usize) {
+        self.data[n].summary = combine_at::<T, A, _>(&self.data, n);
     }
 
     fn rebalance_ins(&mut self, n: usize) {
         self.fix_ins_color(n);
     }
 
-    fn rebalance_del(&mut self, n: usize, child: usize) {
-        /*
-        println!("Deleting {} with {}: ", n, child);
-        for n in self.data.borrow().iter() {
-            print!("({} -> {:?})", &n.ptr, &n.value);
-        }
-        println!();
-        println!("From tree:\n{}", self.to_pretty_string());
-        */
-        if self.get(n).ptr == TREE_END || self.get(child).ptr == TREE_END {
-            self.fix_del_color_long();
-        } else {
-            self.fix_del_color(n, child)
-        }
+    fn rebalance_del(&mut self, n: usize, _child: usize) {
+        let child = self.get(n).lchild.or(self.get(n).rchild);
+        self.fix_del_color(n, child);
+        self.replace_node(n, child);
     }
 
     fn delete_replace(&mut self, n: usize) -> usize {
-        self.get_mut(n).ptr = TREE_END;
-        n
+        // a node with two children trades values with its successor, which
+        // has at most one child, and the successor gets removed instead
+        match (self.get(n).lchild, self.get(n).rchild) {
+            (Some(_lc), Some(rc)) => {
+                let successor = self.get(rc).find_min(&self.data);
+                swap_values(&mut self.data, n, successor);
+                successor
+            }
+            _ => n,
+        }
     }
 
     fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>) {
-        let node = self.get(to_delete);
-        self.get_mut(to_delete).ptr = TREE_END;
-        if let Some(p) = node.parent {
-            if node.is_child(Side::Left) {
-                self.get_mut(p).lchild = to_attach;
-            } else {
-                self.get_mut(p).rchild = to_attach;
-            }
+        let parent = self.get(to_delete).parent;
+        if let Some(p) = parent {
+            let side = self.get(to_delete).side(&self.data);
+            self.get_mut(p).set_child_opt(to_attach, side);
         } else {
             self.root = to_attach;
         }
+        if let Some(c) = to_attach {
+            self.get_mut(c).parent = parent;
+        }
     }
 
     fn get_size(&self) -> usize {
-        return self.size;
+        self.size
+    }
+
+    fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    fn observer(&mut self) -> Option<&mut Observer<Self>> {
+        Some(&mut self.observer)
     }
 
     fn create_node(&mut self, val: T) -> usize {
-        let loc = self.data.borrow().len();
-        self.data
-            .borrow_mut()
-            .push(ColorNode::new(val, loc, self.data.clone()));
-        loc
+        let summary = A::combine(&val, None, None);
+        if let Some(n) = self.free.pop() {
+            self.data[n] = ColorNode::with_summary(val, n, summary);
+            n
+        } else {
+            let loc = self.data.len();
+            self.data.push(ColorNode::with_summary(val, loc, summary));
+            loc
+        }
     }
 
-    fn delete_node(&mut self, index: usize) {
+    fn delete_node(&mut self, index: usize) -> T {
         self.free.push(index);
+        self.data[index].take_value()
+    }
+}
+
+// kept apart from the Tree impl so that RBTree::new() settles on the natural
+// order instead of asking which comparator to use
+impl<T: PartialOrd> RBTree<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_by(iter, Natural)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        let (data, root) = read_snapshot(bytes)?;
+        let tree = Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp: Natural,
+            augment: PhantomData,
+            observer: Observer::default(),
+        };
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd, A: Augment<T>> Default for RBTree<T, Natural, A> {
+    fn default() -> Self {
+        Self::with_comparator(Natural)
+    }
+}
+
+impl<T, C: Comparator<T>> RBTree<T, C> {
+    /// Tree::with_comparator for a tree without a summary, so that the
+    /// comparator alone settles the type
+    pub fn with_comparator(cmp: C) -> Self {
+        Tree::with_comparator(cmp)
+    }
+
+    /// a binary snapshot keeping the exact shape and colors of the tree,
+    /// which from_snapshot restores node for node
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        write_snapshot(&self.data, self.root)
     }
 }
 
-impl<T> RBTree<T>
+impl<T, C: Comparator<T>, A: Augment<T>> RBTree<T, C, A> {
+    /// calls f with every insert, delete, rotation, recoloring and delete
+    /// case the tree goes through, along with the tree right after it
+    /// usage: tree.set_observer(|event, tree| println!("{:?}\n{}", event, tree.to_string()))
+    pub fn set_observer<F>(&mut self, f: F)
+    where
+        F: FnMut(&Event, &Self) + Send + Sync + 'static,
+    {
+        self.observer = Observer::new(f);
+    }
+
+    /// stops reporting events
+    pub fn clear_observer(&mut self) {
+        self.observer = Observer::default();
+    }
+
+    /// the summary of every value in the tree, kept at the root
+    pub fn summary(&self) -> Option<&A::Summary> {
+        self.root.map(|r| &self.data[r].summary)
+    }
+
+    /// the summary of just the values inside of range, put together from
+    /// the summaries along two paths down the tree, so in O(log n)
+    pub fn fold_range<R: RangeBounds<T>>(&self, range: R) -> Option<A::Summary> {
+        fold_range::<T, A, _, _>(self, range)
+    }
+
+    /// moves every live node to the front of the arena and drops the freed
+    /// slots, giving the memory back
+    pub fn shrink_to_fit(&mut self) {
+        compact(&mut self.data, &mut self.root);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+
+    // a red root can always be painted black, which puts one more black
+    // node on every path through the subtree
+    fn blacken(&mut self, t: Subtree) -> Subtree {
+        match t.root {
+            Some(r) if self.get(r).is_red() => {
+                self.recolor(r, Color::Black);
+                Subtree {
+                    root: t.root,
+                    rank: t.rank + 1,
+                }
+            }
+            _ => t,
+        }
+    }
+
+    #[allow(dead_code)]
+    fn get_size_recursive(&self) -> usize {
+        if let Some(root) = self.root {
+            self.get(root).get_size(&self.data)
+        } else {
+            0
+        }
+    }
+}
+
+/**
+ * The red black balancing steps, written against ColoredNode so that any tree
+ * whose nodes carry a color can use them. A tree only has to call
+ * fix_ins_color from rebalance_ins, and fix_del_color before unlinking a node
+ * in rebalance_del
+ */
+pub trait RedBlack<T>: Tree<T>
 where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
+    Self::MNode: ColoredNode<T>,
 {
-    // child is the new node in the location, n is being deleted
-    fn fix_del_color(&mut self, n: usize, child: usize) {
+    // n is about to be unlinked and replaced by its only child, if it has
+    // one. Taking out a black node leaves its path a black node short, so
+    // that has to be fixed while n is still in the tree
+    fn fix_del_color(&mut self, n: usize, child: Option<usize>) {
         if !self.get(n).is_red() {
-            if self.get(child).is_red() {
-                self.get_mut(child).color = Color::Black;
-            } else {
-                self.delete_case_1(child);
+            match child {
+                Some(c) if self.get(c).is_red() => self.recolor(c, Color::Black),
+                _ => self.delete_case_1(n),
             }
         }
     }
 
+    /// paints n, telling the observer if that changed its color
+    fn recolor(&mut self, n: usize, color: Color) {
+        if self.get(n).color() != color {
+            self.get_mut(n).set_color(color);
+            self.emit(Event::Recolored(n, color));
+        }
+    }
+
     // sets a node to black if it exists. This is fine, cause all
     // nodes that don't exist are by definition black anyways
     fn set_maybe_black(&mut self, no: Option<usize>) {
         if let Some(n) = no {
-            self.get_mut(n).color = Color::Black;
+            self.recolor(n, Color::Black);
         }
     }
 
+    // n is the root, so every path lost a black node and nothing is off
     fn delete_case_1(&mut self, n: usize) {
-        if self.get(n).parent.is_some() {
+        self.emit(Event::DeleteCase(1));
+        if self.get(n).get_parent().is_some() {
             self.delete_case_2(n);
         }
     }
 
+    // a red sibling gets rotated above the parent, so that n ends up with a
+    // black sibling
     fn delete_case_2(&mut self, n: usize) {
-        let s = self.get(n).get_sibling();
-        if self.get(n).is_sibling_black() {
-            let p = self.get(n).parent.expect("D2 P");
-            self.set_maybe_black(s);
-            self.get_mut(p).color = Color::Red;
-            self.rotate(self.get(n).side(), p);
+        self.emit(Event::DeleteCase(2));
+        let s = self.get(n).get_sibling(self.get_data()).expect("D2 S");
+        if self.get(s).is_red() {
+            let p = self.get(n).get_parent().expect("D2 P");
+            self.recolor(p, Color::Red);
+            self.recolor(s, Color::Black);
+            self.rotate(self.get(n).side(self.get_data()), s);
         }
         self.delete_case_3(n);
     }
 
+    // parent, sibling and its children are all black. Painting the sibling
+    // red takes a black node off of the parent's other side too, so the
+    // parent is now the one that is short
     fn delete_case_3(&mut self, n: usize) {
-        let s = self.get(n).get_sibling().expect("D3 S");
-        let p = self.get(n).parent.expect("D3 P");
-        if self.get(n).is_parent_black()
-            && !self.get(s).is_red()
-            && self.get(s).is_child_black(Side::Left)
-            && self.get(s).is_child_black(Side::Right)
+        self.emit(Event::DeleteCase(3));
+        let s = self.get(n).get_sibling(self.get_data()).expect("D3 S");
+        let p = self.get(n).get_parent().expect("D3 P");
+        if self.get(n).is_parent_black(self.get_data())
+            && self.get(n).is_sibling_black(self.get_data())
+            && self.get(s).is_child_black(Side::Left, self.get_data())
+            && self.get(s).is_child_black(Side::Right, self.get_data())
         {
+            self.recolor(s, Color::Red);
             self.delete_case_1(p);
         } else {
-            self.delete_case_4(p);
+            self.delete_case_4(n);
         }
     }
 
+    // the parent is red but the sibling and its children are black, so the
+    // colors of the parent and sibling can be swapped
     fn delete_case_4(&mut self, n: usize) {
-        let node = self.get(n);
-        let s = node.get_sibling().expect("D4 S");
-        let p = node.parent.expect("D4 P");
-
-        if !node.is_parent_black()
-            && node.is_sibling_black()
-            && self.get(s).is_child_black(Side::Left)
-            && self.get(s).is_child_black(Side::Right)
+        self.emit(Event::DeleteCase(4));
+        let s = self.get(n).get_sibling(self.get_data()).expect("D4 S");
+        let p = self.get(n).get_parent().expect("D4 P");
+        if !self.get(n).is_parent_black(self.get_data())
+            && self.get(n).is_sibling_black(self.get_data())
+            && self.get(s).is_child_black(Side::Left, self.get_data())
+            && self.get(s).is_child_black(Side::Right, self.get_data())
         {
-            self.get_mut(s).color = Color::Red;
-            self.get_mut(p).color = Color::Black;
+            self.recolor(s, Color::Red);
+            self.recolor(p, Color::Black);
         } else {
-            self.delete_case_5(n)
+            self.delete_case_5(n);
         }
     }
 
+    // the sibling is black with its red child on the inside, so it gets
+    // rotated to the outside for case 6
     fn delete_case_5(&mut self, n: usize) {
-        let s = self.get(n).get_sibling().expect("D5 S");
+        self.emit(Event::DeleteCase(5));
+        let s = self.get(n).get_sibling(self.get_data()).expect("D5 S");
         if !self.get(s).is_red() {
-            if self.get(n).is_child(Side::Left)
-                && self.get(s).is_child_black(Side::Right)
-                && !self.get(s).is_child_black(Side::Left)
+            if self.get(n).is_child(Side::Left, self.get_data())
+                && self.get(s).is_child_black(Side::Right, self.get_data())
+                && !self.get(s).is_child_black(Side::Left, self.get_data())
             {
-                let scl = self.get(s).get_child(Side::Left);
-                self.get_mut(s).color = Color::Red;
-                self.set_maybe_black(scl);
-                self.rotate(Side::Right, s);
-            } else if self.get(n).is_child(Side::Right)
-                && self.get(s).is_child_black(Side::Left)
-                && !self.get(s).is_child_black(Side::Right)
+                let scl = self.get(s).get_child(Side::Left).expect("D5 SCL");
+                self.recolor(s, Color::Red);
+                self.recolor(scl, Color::Black);
+                self.rotate(Side::Right, scl);
+            } else if self.get(n).is_child(Side::Right, self.get_data())
+                && self.get(s).is_child_black(Side::Left, self.get_data())
+                && !self.get(s).is_child_black(Side::Right, self.get_data())
             {
-                let scr = self.get(s).get_child(Side::Right);
-                self.get_mut(s).color = Color::Red;
-                self.set_maybe_black(scr);
-                self.rotate(Side::Left, s);
+                let scr = self.get(s).get_child(Side::Right).expect("D5 SCR");
+                self.recolor(s, Color::Red);
+                self.recolor(scr, Color::Black);
+                self.rotate(Side::Left, scr);
             }
         }
         self.delete_case_6(n)
     }
 
+    // the sibling is black with a red child on the outside. Rotating the
+    // sibling above the parent puts an extra black node above n
     fn delete_case_6(&mut self, n: usize) {
-        let s = self.get(n).get_sibling().expect("D6 S");
-        let p = self.get(n).parent.expect("D6 P");
-        let pc = self.get(p).color;
-        self.get_mut(s).color = pc;
-        self.get_mut(p).color = Color::Black;
-
-        if self.get(n).is_child(Side::Left) {
+        self.emit(Event::DeleteCase(6));
+        let s = self.get(n).get_sibling(self.get_data()).expect("D6 S");
+        let p = self.get(n).get_parent().expect("D6 P");
+        let pc = self.get(p).color();
+        self.recolor(s, pc);
+        self.recolor(p, Color::Black);
+
+        if self.get(n).is_child(Side::Left, self.get_data()) {
             let scr = self.get(s).get_child(Side::Right);
             self.set_maybe_black(scr);
-            self.rotate(Side::Left, p);
+            self.rotate(Side::Left, s);
         } else {
             let scl = self.get(s).get_child(Side::Left);
             self.set_maybe_black(scl);
-            self.rotate(Side::Right, p);
+            self.rotate(Side::Right, s);
         }
     }
 
-    fn fix_del_color_long(&mut self) {
-        let mut t = RBTree::new();
-        let mut v = self.data.borrow_mut().pop();
-        while v.is_some() {
-            let n = v.unwrap();
-            if n.ptr != TREE_END {
-                t.insert(n.value);
-            }
-            v = self.data.borrow_mut().pop();
-        }
-
-        //self.data = t.data;
-        //self.root = t.root;
-        //self.size = t.size;
-        //self.free = t.free;
-        // println!("the new post deleted tree:\n{}", t.to_pretty_string());
-        *self = t;
-        self.size += 1;
-    }
-
     fn fix_ins_color(&mut self, n: usize) {
-        self.get_mut(n).color = Color::Red;
-        if let Some(p) = self.get(n).parent {
+        self.recolor(n, Color::Red);
+        if let Some(p) = self.get(n).get_parent() {
             if !self.get(p).is_red() {
                 // parent is black
                 // do nothing
-            } else if self.get(n).get_uncle().is_some()
-                && self.get(self.get(n).get_uncle().unwrap()).is_red()
+            } else if self.get(n).get_uncle(self.get_data()).is_some()
+                && self.get(self.get(n).get_uncle(self.get_data()).unwrap()).is_red()
             {
                 // uncle exists and is red
-                let p = self.get(n).parent.unwrap();
-                let u = self.get(n).get_uncle().unwrap();
-                self.get_mut(p).color = Color::Black;
-                self.get_mut(u).color = Color::Black;
-                self.fix_ins_color(self.get(p).parent.unwrap());
+                let p = self.get(n).get_parent().unwrap();
+                let u = self.get(n).get_uncle(self.get_data()).unwrap();
+                self.recolor(p, Color::Black);
+                self.recolor(u, Color::Black);
+                self.fix_ins_color(self.get(p).get_parent().unwrap());
             } else {
                 // uncle is black
                 self.do_ins_hard_case(n);
             }
-        } else {
         }
-        self.get_mut(self.root.unwrap()).color = Color::Black;
+        let root = self.get_root().unwrap();
+        self.recolor(root, Color::Black);
     }
 
     fn do_ins_hard_case(&mut self, nn: usize) {
         let mut n = nn;
-        let mut p = self.get(n).parent.unwrap();
-        if self.get(p).is_child(Side::Left) && self.get(n).is_child(Side::Right) {
+        let mut p = self.get(n).get_parent().unwrap();
+        if self.get(p).is_child(Side::Left, self.get_data()) && self.get(n).is_child(Side::Right, self.get_data()) {
             self.rotate(Side::Left, n);
             n = self.get(n).get_child(Side::Left).unwrap();
         }
 
-        p = self.get(n).parent.unwrap();
-        if self.get(p).is_child(Side::Right) && self.get(n).is_child(Side::Left) {
+        p = self.get(n).get_parent().unwrap();
+        if self.get(p).is_child(Side::Right, self.get_data()) && self.get(n).is_child(Side::Left, self.get_data()) {
             self.rotate(Side::Right, n);
             n = self.get(n).get_child(Side::Right).unwrap();
         }
@@ -489,26 +767,160 @@ where
     }
 
     fn do_ins_hard_case2(&mut self, n: usize) {
-        let p = self.get(n).parent.unwrap();
-        let g = self.get(p).parent.unwrap();
+        let p = self.get(n).get_parent().unwrap();
+        let g = self.get(p).get_parent().unwrap();
 
-        self.get_mut(p).color = Color::Black;
-        self.get_mut(g).color = Color::Red;
-        if self.get(p).is_child(Side::Right) {
+        self.recolor(p, Color::Black);
+        self.recolor(g, Color::Red);
+        if self.get(p).is_child(Side::Right, self.get_data()) {
             self.rotate(Side::Left, p);
-        } else if self.get(p).is_child(Side::Left) {
+        } else if self.get(p).is_child(Side::Left, self.get_data()) {
             self.rotate(Side::Right, p);
         }
     }
 
-    #[allow(dead_code)]
-    fn get_size_recursive(&self) -> usize {
-        if let Some(root) = self.root {
-            self.get(root).get_size()
+    // the number of black nodes on any path from n down to a leaf, counting
+    // the empty leaf itself, after checking all such paths agree
+    fn black_height(&self, n: usize) -> Result<usize, InvariantError> {
+        let node = self.get(n);
+        let mut heights = [1, 1];
+        for (i, side) in [Side::Left, Side::Right].iter().enumerate() {
+            if let Some(c) = node.get_child(*side) {
+                if node.is_red() && self.get(c).is_red() {
+                    return Err(InvariantError::RedRedViolation {
+                        parent: n,
+                        child: c,
+                    });
+                }
+                heights[i] = self.black_height(c)?;
+            }
+        }
+        if heights[0] != heights[1] {
+            return Err(InvariantError::BlackHeightMismatch {
+                node: n,
+                left: heights[0],
+                right: heights[1],
+            });
+        }
+        Ok(heights[0] + if node.is_red() { 0 } else { 1 })
+    }
+}
+
+impl<T, C: Comparator<T>, A: Augment<T>> RedBlack<T> for RBTree<T, C, A> {}
+
+impl<T, C: Comparator<T>, A: Augment<T>> Cursors<T> for RBTree<T, C, A> {}
+
+impl<T, C: Comparator<T>, A: Augment<T>> Join<T> for RBTree<T, C, A> {
+    // the black height, counting the root itself when it is black
+    fn rank_of(&self, root: Option<usize>) -> usize {
+        let mut rank = 0;
+        let mut next = root;
+        while let Some(n) = next {
+            rank += !self.get(n).is_red() as usize;
+            next = self.get(n).lchild;
+        }
+        rank
+    }
+
+    fn child_rank(&self, n: usize, rank: usize, _side: Side) -> usize {
+        rank - !self.get(n).is_red() as usize
+    }
+
+    fn join_at(&mut self, left: Subtree, mid: usize, right: Subtree) -> Subtree {
+        let left = self.blacken(left);
+        let right = self.blacken(right);
+        if left.rank == right.rank {
+            link(self, left.root, mid, right.root);
+            self.recolor(mid, Color::Black);
+            return Subtree {
+                root: Some(mid),
+                rank: left.rank + 1,
+            };
+        }
+        // walk down the inner edge of the taller tree to a black node with
+        // the same black height as the shorter tree, for mid to replace
+        let (tall, short, side) = if left.rank > right.rank {
+            (left, right, Side::Right)
         } else {
-            0
+            (right, left, Side::Left)
+        };
+        let mut parent = tall.root.expect("a taller tree has a root");
+        let mut rank = tall.rank - 1;
+        let mut next = self.get(parent).get_child(side);
+        while rank > short.rank || matches!(next, Some(c) if self.get(c).is_red()) {
+            let c = next.expect("a black height above 0 has a node");
+            rank -= !self.get(c).is_red() as usize;
+            parent = c;
+            next = self.get(c).get_child(side);
+        }
+        if let Some(c) = next {
+            self.data[c].parent = None;
+        }
+        match side {
+            Side::Right => link(self, next, mid, short.root),
+            Side::Left => link(self, short.root, mid, next),
+        }
+        let added = self.data[mid].size - next.map_or(0, |c| self.data[c].size);
+        self.attach_child(parent, mid, side);
+        self.add_size_to_path(parent, added as isize);
+        // mid goes in red like a new node and red parents get fixed the way
+        // fix_ins_color does, except that painting the top black again
+        // makes the subtree one black node taller. Rotations at the top
+        // report the new top through the root
+        self.root = tall.root;
+        self.recolor(mid, Color::Red);
+        let mut n = mid;
+        let mut grown = false;
+        while let Some(p) = self.get(n).get_parent() {
+            if !self.get(p).is_red() {
+                break;
+            }
+            let g = self.get(p).get_parent().expect("a red node is never the top");
+            match self.get(p).get_sibling(self.get_data()) {
+                Some(u) if self.get(u).is_red() => {
+                    self.recolor(p, Color::Black);
+                    self.recolor(u, Color::Black);
+                    self.recolor(g, Color::Red);
+                    n = g;
+                }
+                _ => {
+                    self.do_ins_hard_case(n);
+                    break;
+                }
+            }
+        }
+        if self.get(n).get_parent().is_none() && self.get(n).is_red() {
+            self.recolor(n, Color::Black);
+            grown = true;
+        }
+        Subtree {
+            root: self.root,
+            rank: tall.rank + grown as usize,
         }
     }
+
+    fn settle_root(&mut self, root: usize) {
+        self.recolor(root, Color::Black);
+    }
+}
+
+impl<T, C: Comparator<T>, A: Augment<T>> IntoIterator for RBTree<T, C, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let order = in_order(&self);
+        IntoIter::new(self.data, order)
+    }
+}
+
+impl<'a, T, C: Comparator<T>, A: Augment<T>> IntoIterator for &'a RBTree<T, C, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, RBTree<T, C, A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 #[cfg(test)]
@@ -639,7 +1051,259 @@ mod tests {
         tree.delete(14);
         tree.delete(12);
         tree.delete(13);
-        assert_eq!(tree.to_string(), "([P:None C:Black V:8] ([P:Some(3) C:Black V:4] ([P:Some(7) C:Red V:2] ([P:Some(9) C:Black V:1] ([P:Some(10) C:Red V:0] () ()) ()) ([P:Some(9) C:Black V:3] () ())) ([P:Some(7) C:Red V:6] ([P:Some(5) C:Black V:5] () ()) ([P:Some(5) C:Black V:7] () ()))) ([P:Some(3) C:Black V:10] ([P:Some(1) C:Black V:9] () ()) ([P:Some(1) C:Black V:11] () ())))");
+        assert_eq!(tree.to_string(), "([P:None C:Black V:3] ([P:Some(3) C:Black V:1] ([P:Some(1) C:Black V:0] () ()) ([P:Some(1) C:Black V:2] () ())) ([P:Some(3) C:Red V:7] ([P:Some(7) C:Black V:5] ([P:Some(5) C:Black V:4] () ()) ([P:Some(5) C:Black V:6] () ())) ([P:Some(7) C:Black V:9] ([P:Some(9) C:Black V:8] () ()) ([P:Some(9) C:Black V:11] ([P:Some(11) C:Red V:10] () ()) ()))))");
+    }
+
+    #[test]
+    fn delete_reuses_slots() {
+        let mut tree = RBTree::new();
+        for x in 0..64 {
+            tree.insert(x);
+        }
+        for round in 0..10 {
+            for x in (round % 2..64).step_by(2) {
+                tree.delete(x);
+            }
+            for x in (round % 2..64).step_by(2) {
+                tree.insert(x);
+            }
+        }
+        assert_eq!(tree.data.len(), 64);
+        assert_eq!(
+            tree.iter().cloned().collect::<Vec<_>>(),
+            (0..64).collect::<Vec<_>>()
+        );
+
+        for x in (0..64).filter(|x| x % 3 != 0) {
+            tree.delete(x);
+        }
+        assert_eq!(tree.get_size(), 22);
+        tree.shrink_to_fit();
+        assert_eq!(tree.data.len(), 22);
+        assert_eq!(tree.root, Some(0));
+        let expected: Vec<i32> = (0..64).filter(|x| x % 3 == 0).collect();
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), expected);
+        assert_eq!(tree.iter().rev().count(), 22);
+        for x in &expected {
+            assert!(tree.contains(x));
+        }
+        tree.insert(100);
+        assert_eq!(tree.data.len(), 23);
+    }
+
+    #[test]
+    fn delete_drops_values() {
+        use std::rc::Rc;
+        let vals: Vec<Rc<i32>> = (0..10).map(Rc::new).collect();
+        let mut tree = RBTree::new();
+        for v in &vals {
+            tree.insert(v.clone());
+        }
+        tree.delete(Rc::new(3));
+        assert_eq!(Rc::strong_count(&vals[3]), 1);
+        // deleting a node with two children hands back its own value
+        let root = tree.root.unwrap();
+        let val = tree.delete_at(root);
+        assert_eq!(Rc::strong_count(&val), 2);
+        drop(val);
+        assert!(vals.iter().filter(|v| Rc::strong_count(v) == 1).count() == 2);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn order_statistics() {
+        let mut tree = RBTree::new();
+        for x in 0..200 {
+            tree.insert((x * 7) % 200);
+        }
+        for x in (0..200).filter(|x| x % 4 == 1) {
+            tree.delete(x);
+        }
+        // every stored size has to agree with a full count of the subtree
+        for n in in_order(&tree) {
+            assert_eq!(tree.get(n).size, tree.get(n).get_size(&tree.data));
+        }
+        let values: Vec<i32> = tree.iter().cloned().collect();
+        for (k, v) in values.iter().enumerate() {
+            assert_eq!(tree.rank(v), k);
+            assert_eq!(tree.select(k), Some(v));
+        }
+        assert_eq!(tree.select(values.len()), None);
+        assert_eq!(tree.rank(&1), 1);
+        assert_eq!(tree.count_range(0..8), 6);
+        assert_eq!(tree.count_range(100..), 75);
+        assert_eq!(tree.count_range(9..=9), 0);
+        assert_eq!(tree.count_range(8..=8), 1);
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        for len in 0..70 {
+            let tree = RBTree::from_sorted_iter(0..len);
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.get_size(), len as usize);
+            assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), (0..len).collect::<Vec<_>>());
+            if let Some(r) = tree.root {
+                assert!(!tree.get(r).is_red());
+                assert_eq!(tree.get(r).size, len as usize);
+            }
+        }
+        let mut tree = RBTree::from_sorted_iter(vec![1, 1, 2, 3, 3, 3, 8]);
+        assert_eq!(tree.get_size(), 4);
+        tree.insert(5);
+        tree.delete(1);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), vec![2, 3, 5, 8]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_unsorted_iter() {
+        RBTree::from_sorted_iter(vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn split_join() {
+        let tree = RBTree::from_sorted_iter(0..100);
+        let (low, high) = tree.split(&40);
+        assert_eq!(low.validate(), Ok(()));
+        assert_eq!(high.validate(), Ok(()));
+        assert_eq!(low.iter().cloned().collect::<Vec<_>>(), (0..40).collect::<Vec<_>>());
+        assert_eq!(high.iter().cloned().collect::<Vec<_>>(), (40..100).collect::<Vec<_>>());
+        assert_eq!(high.select(0), Some(&40));
+
+        let tree = low.join(high);
+        assert_eq!(tree.get_size(), 100);
+        assert_eq!(tree.rank(&40), 40);
+        let (empty, all) = tree.split(&-5);
+        assert!(empty.is_empty());
+        assert_eq!(all.join(RBTree::new()).get_size(), 100);
+    }
+
+    #[test]
+    #[should_panic]
+    fn join_overlapping() {
+        RBTree::from_sorted_iter(0..10).join(RBTree::from_sorted_iter(9..20));
+    }
+
+    #[test]
+    fn set_algebra() {
+        let evens = || RBTree::from_sorted_iter((0..30).filter(|x| x % 2 == 0));
+        let threes = || RBTree::from_sorted_iter((0..30).filter(|x| x % 3 == 0));
+
+        let union = evens().union(threes());
+        assert_eq!(union.validate(), Ok(()));
+        let expected: Vec<i32> = (0..30).filter(|x| x % 2 == 0 || x % 3 == 0).collect();
+        assert_eq!(union.iter().cloned().collect::<Vec<_>>(), expected);
+
+        let both = evens().intersection(threes());
+        assert_eq!(both.iter().cloned().collect::<Vec<_>>(), vec![0, 6, 12, 18, 24]);
+
+        let diff = evens().difference(threes());
+        let expected: Vec<i32> = (0..30).filter(|x| x % 2 == 0 && x % 3 != 0).collect();
+        assert_eq!(diff.iter().cloned().collect::<Vec<_>>(), expected);
+        assert!(evens().intersection(RBTree::new()).is_empty());
+    }
+
+    #[test]
+    fn navigation() {
+        let mut tree = RBTree::new();
+        assert_eq!(tree.first(), None);
+        assert_eq!(tree.successor(&1), None);
+        assert_eq!(tree.pop_last(), None);
+        for x in (10..=100).step_by(10) {
+            tree.insert(x);
+        }
+        assert_eq!((tree.first(), tree.last()), (Some(&10), Some(&100)));
+        assert_eq!(tree.floor(&35), Some(&30));
+        assert_eq!(tree.floor(&30), Some(&30));
+        assert_eq!(tree.floor(&5), None);
+        assert_eq!(tree.ceiling(&35), Some(&40));
+        assert_eq!(tree.ceiling(&101), None);
+        assert_eq!(tree.predecessor(&30), Some(&20));
+        assert_eq!(tree.predecessor(&10), None);
+        assert_eq!(tree.successor(&30), Some(&40));
+        assert_eq!(tree.successor(&35), Some(&40));
+        assert_eq!(tree.successor(&100), None);
+
+        assert_eq!(tree.pop_first(), Some(10));
+        assert_eq!(tree.pop_last(), Some(100));
+        assert_eq!(tree.get_size(), 8);
+        assert_eq!((tree.first(), tree.last()), (Some(&20), Some(&90)));
+        assert_eq!(tree.validate(), Ok(()));
+        while tree.pop_first().is_some() {
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert!(tree.is_empty());
+
+        // popping hands back the value itself, so it needs no Clone
+        #[derive(Debug, PartialEq, PartialOrd)]
+        struct Unique(i32);
+        let mut tree = RBTree::new();
+        tree.insert(Unique(2));
+        tree.insert(Unique(1));
+        assert_eq!(tree.pop_first(), Some(Unique(1)));
+        assert_eq!(tree.pop_last(), Some(Unique(2)));
+    }
+
+    #[test]
+    fn validate() {
+        let valid = || RBTree::from_sorted_iter(0..20);
+        let mut tree = valid();
+        for x in 20..40 {
+            tree.insert(x);
+        }
+        for x in (0..40).step_by(3) {
+            tree.delete(x);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+
+        let mut tree = valid();
+        let r = tree.root.unwrap();
+        let leaf = tree.data[r].find_min(&tree.data);
+        let p = tree.data[leaf].parent.unwrap();
+        tree.data[p].color = Color::Red;
+        assert_eq!(
+            tree.validate(),
+            Err(InvariantError::RedRedViolation {
+                parent: p,
+                child: leaf
+            })
+        );
+
+        let mut tree = valid();
+        tree.data[r].color = Color::Red;
+        assert_eq!(tree.validate(), Err(InvariantError::RedRoot { node: r }));
+
+        let mut tree = valid();
+        tree.data[leaf].color = Color::Black;
+        assert!(matches!(
+            tree.validate(),
+            Err(InvariantError::BlackHeightMismatch { .. })
+        ));
+
+        let mut tree = valid();
+        tree.data[leaf].parent = None;
+        assert_eq!(
+            tree.validate(),
+            Err(InvariantError::ParentLinkBroken {
+                node: leaf,
+                expected: Some(p),
+                found: None
+            })
+        );
+
+        let mut tree = valid();
+        *tree.data[leaf].get_value_mut() = 100;
+        assert_eq!(
+            tree.validate(),
+            Err(InvariantError::OrderViolation { node: leaf, next: p })
+        );
+
+        let mut tree = valid();
+        tree.data[r].size = 3;
+        let err = tree.validate().unwrap_err();
+        assert_eq!(err.to_string(), format!("node {} stores size 3 but has 20 nodes", r));
     }
 
     #[test]
@@ -666,4 +1330,107 @@ mod tests {
         tree.delete(1);
         assert!(tree.is_empty());
     }
+
+    #[test]
+    fn test_iter() {
+        let tree = make_fake_tree_node_no_balance();
+        let v: Vec<i32> = tree.iter().cloned().collect();
+        assert_eq!(v, vec![0, 15, 20, 25, 30, 35, 40, 50, 60, 65, 70, 75, 80, 85, 100]);
+        let v: Vec<i32> = tree.iter().rev().cloned().collect();
+        assert_eq!(v, vec![100, 85, 80, 75, 70, 65, 60, 50, 40, 35, 30, 25, 20, 15, 0]);
+
+        let mut it = tree.iter();
+        assert_eq!(it.next(), Some(&0));
+        assert_eq!(it.next_back(), Some(&100));
+        assert_eq!(it.next(), Some(&15));
+        assert_eq!(it.count(), 12);
+
+        assert_eq!(RBTree::<i32>::new().iter().next(), None);
+    }
+
+    #[test]
+    fn test_range() {
+        let tree = make_fake_tree_node_no_balance();
+        let v: Vec<&i32> = tree.range(20..65).collect();
+        assert_eq!(v, vec![&20, &25, &30, &35, &40, &50, &60]);
+        let v: Vec<&i32> = tree.range(21..=65).rev().collect();
+        assert_eq!(v, vec![&65, &60, &50, &40, &35, &30, &25]);
+        let v: Vec<&i32> = tree.range(..15).collect();
+        assert_eq!(v, vec![&0]);
+        let v: Vec<&i32> = tree.range(86..).collect();
+        assert_eq!(v, vec![&100]);
+        assert_eq!(tree.range(41..50).next(), None);
+        assert_eq!(tree.range(50..50).next(), None);
+        assert_eq!(tree.range(101..).next(), None);
+        assert_eq!(tree.range(..).count(), 15);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let tree = make_fake_tree_node_no_balance();
+        let mut total = 0;
+        for x in &tree {
+            total += x;
+        }
+        assert_eq!(total, 750);
+        let v: Vec<i32> = tree.into_iter().collect();
+        assert_eq!(v, vec![0, 15, 20, 25, 30, 35, 40, 50, 60, 65, 70, 75, 80, 85, 100]);
+    }
+
+    #[test]
+    fn observer() {
+        use std::sync::{Arc, Mutex};
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tree = RBTree::new();
+        tree.insert(1);
+        tree.insert(2);
+        let seen = log.clone();
+        tree.set_observer(move |event, tree: &RBTree<i32>| {
+            seen.lock().unwrap().push((*event, tree.get_height()));
+        });
+
+        // the third value makes a straight line, which one rotation fixes
+        tree.insert(3);
+        assert_eq!(
+            log.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![
+                (Event::Inserted(2), 3),
+                (Event::Recolored(2, Color::Red), 3),
+                (Event::Recolored(1, Color::Black), 3),
+                (Event::Recolored(0, Color::Red), 3),
+                (Event::Rotated(Side::Left, 1), 2),
+            ]
+        );
+
+        tree.insert(4);
+        log.lock().unwrap().clear();
+        tree.delete(1);
+        let events: Vec<Event> = log.lock().unwrap().iter().map(|(e, _)| *e).collect();
+        assert_eq!(events[0], Event::Deleted(0));
+        assert!(events.contains(&Event::DeleteCase(1)));
+
+        log.lock().unwrap().clear();
+        tree.clear_observer();
+        tree.insert(10);
+        tree.delete(2);
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn send_to_thread() {
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<RBTree<i32>>();
+
+        let mut tree = RBTree::new();
+        for x in 0..50 {
+            tree.insert(x);
+        }
+        let handle = std::thread::spawn(move || {
+            tree.delete(25);
+            tree
+        });
+        let tree = handle.join().unwrap();
+        assert!(!tree.contains(&25));
+        assert_eq!(tree.iter().count(), 49);
+    }
 }
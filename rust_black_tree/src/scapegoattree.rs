@@ -0,0 +1,484 @@
+use std::fmt::Debug;
+
+use super::comparator::{Comparator, Natural};
+use super::tree::BaseTree;
+use super::tree::Tree;
+use super::tree::{
+    compact, in_order, link_balanced, sorted_arena, swap_values, validate_links, IntoIter, Iter,
+};
+use super::tree::InvariantError;
+use super::snapshot::{
+    read_snapshot, write_snapshot, SnapshotError, SnapshotKind, SnapshotNode, SnapshotValue,
+};
+
+use super::node::Node;
+use super::node::*;
+
+/// a nice convenient macro which allows a user to initialize a tree with
+/// a number of elements
+/// usage: scapegoat!{1, 2, 3, 4, 5, 6, 7, 8, 9, 0};
+#[macro_export]
+macro_rules! scapegoat {
+    ( $( $x:expr ),* ) => {
+        {
+            let mut temp_tree = ScapegoatTree::new();
+            $(
+                temp_tree.insert($x);
+            )*
+            temp_tree
+        }
+    };
+}
+
+/// how deep a node may sit in a scapegoat tree of n nodes, the log of n to
+/// the base 3/2. Alpha is 2/3, so no child may hold more than two thirds of
+/// the nodes below its parent
+fn depth_limit(n: usize) -> usize {
+    ((n as f64).ln() / 1.5f64.ln()).floor() as usize
+}
+
+#[derive(Debug)]
+pub struct ScapegoatNode<T> {
+    pub value: Option<T>,
+    pub ptr: usize,
+    pub parent: Option<usize>,
+    pub lchild: Option<usize>,
+    pub rchild: Option<usize>,
+    /// number of nodes in the subtree rooted here, including this one
+    pub size: usize,
+}
+
+impl<T> ScapegoatNode<T> {
+    fn new(val: T, selfptr: usize) -> Self {
+        Self {
+            value: Some(val),
+            ptr: selfptr,
+            parent: None,
+            lchild: None,
+            rchild: None,
+            size: 1,
+        }
+    }
+}
+
+impl<T> Node<T> for ScapegoatNode<T> {
+    fn to_self_string(&self) -> String
+    where
+        T: Debug,
+    {
+        format!("[P:{:?} V:{:?}]", self.parent, self.get_value())
+    }
+
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug,
+    {
+        let s = format!("{:?}", self.get_value());
+        let l = s.len();
+        (s, l)
+    }
+
+    // the subtree sizes are what decide where a rebuild happens
+    fn export_style(&self, _data: &[Self]) -> NodeStyle
+    where
+        T: Debug,
+    {
+        NodeStyle {
+            label: format!("{:?}", self.get_value()),
+            fill: "lightgrey",
+            font: "black",
+            attributes: vec![("size", self.size.to_string())],
+        }
+    }
+
+    fn get_value(&self) -> &T {
+        self.value.as_ref().expect("freed node")
+    }
+    fn get_value_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("freed node")
+    }
+    fn into_value(self) -> T {
+        self.value.expect("freed node")
+    }
+    fn take_value(&mut self) -> T {
+        self.value.take().expect("freed node")
+    }
+    fn get_child(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Left => self.lchild,
+            Side::Right => self.rchild,
+        }
+    }
+
+    fn set_child(&mut self, child: usize, side: Side) {
+        self.set_child_opt(Some(child), side)
+    }
+
+    fn set_child_opt(&mut self, c: Option<usize>, side: Side) {
+        match side {
+            Side::Left => self.lchild = c,
+            Side::Right => self.rchild = c,
+        };
+    }
+    fn set_parent(&mut self, p: Option<usize>) {
+        self.parent = p;
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn location(&self) -> usize {
+        self.ptr
+    }
+
+    fn set_location(&mut self, ptr: usize) {
+        self.ptr = ptr;
+    }
+
+    fn subtree_size(&self) -> usize {
+        self.size
+    }
+
+    fn set_subtree_size(&mut self, size: usize) {
+        self.size = size;
+    }
+}
+
+/**
+ * Arena based memory tree structure. Nodes keep no balancing data at all and
+ * nothing is rotated. When an insert lands too deep, the subtree of the
+ * lowest ancestor that is out of balance by weight (the scapegoat) is torn
+ * down and rebuilt perfectly balanced. Deletes let the tree shrink until it
+ * is a third smaller than its largest size, then rebuild all of it
+*/
+#[derive(Debug)]
+pub struct ScapegoatTree<T, C = Natural> {
+    root: Option<usize>,
+    size: usize,
+    data: Vec<ScapegoatNode<T>>,
+    free: Vec<usize>,
+    cmp: C,
+    /// the largest size since the whole tree was last rebuilt
+    max_size: usize,
+}
+
+impl<T> SnapshotNode<T> for ScapegoatNode<T> {
+    const KIND: SnapshotKind = SnapshotKind::Scapegoat;
+
+    fn blank(val: T, ptr: usize) -> Self {
+        ScapegoatNode::new(val, ptr)
+    }
+
+    fn write_extra(&self, _out: &mut Vec<u8>) {}
+
+    fn read_extra(&mut self, _input: &mut &[u8]) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+impl<T, C: Comparator<T>> Tree<T> for ScapegoatTree<T, C> {
+    fn with_comparator(cmp: C) -> Self {
+        Self {
+            root: None,
+            data: Vec::new(),
+            size: 0,
+            free: Vec::new(),
+            cmp,
+            max_size: 0,
+        }
+    }
+
+    /// besides the shared checks, no node may be deeper than the limit for
+    /// the largest size the tree has had since its last full rebuild
+    fn validate(&self) -> Result<(), InvariantError> {
+        validate_links(self)?;
+        let limit = depth_limit(self.max_size) + 1;
+        let mut stack: Vec<(usize, usize)> = self.root.map(|r| (r, 0)).into_iter().collect();
+        while let Some((n, depth)) = stack.pop() {
+            if depth > limit {
+                return Err(InvariantError::TooDeep { node: n, depth, limit });
+            }
+            let node = self.get(n);
+            for c in node.lchild.iter().chain(node.rchild.iter()) {
+                stack.push((*c, depth + 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Self {
+        let mut data = sorted_arena(iter, &cmp, ScapegoatNode::new);
+        let root = link_balanced(&mut data, &mut |_: &mut [ScapegoatNode<T>], _, _| {});
+        Self {
+            root,
+            size: data.len(),
+            max_size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp,
+        }
+    }
+}
+
+impl<T, C: Comparator<T>> BaseTree<T> for ScapegoatTree<T, C> {
+    type MNode = ScapegoatNode<T>;
+    type Cmp = C;
+    fn get(&self, val: usize) -> &Self::MNode {
+        &self.data[val]
+    }
+
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode {
+        &mut self.data[val]
+    }
+
+    fn get_data(&self) -> &[Self::MNode] {
+        &self.data
+    }
+
+    fn get_root(&self) -> Option<usize> {
+        self.root
+    }
+
+    fn set_root(&mut self, new_root: Option<usize>) {
+        self.root = new_root
+    }
+
+    fn crement_size(&mut self, amount: isize) {
+        self.size = (self.size as isize + amount) as usize;
+    }
+
+    // the size of the tree is only counted up once this returns, so the new
+    // node is added in by hand
+    fn rebalance_ins(&mut self, n: usize) {
+        let size = self.size + 1;
+        self.max_size = std::cmp::max(self.max_size, size);
+        if self.get(n).get_depth(&self.data) <= depth_limit(size) {
+            return;
+        }
+        // some ancestor has a child holding more than two thirds of its
+        // nodes, or n could not be this deep
+        let mut child = n;
+        while let Some(p) = self.get(child).parent {
+            if 3 * self.get(child).size > 2 * self.get(p).size {
+                self.rebuild(p);
+                return;
+            }
+            child = p;
+        }
+    }
+
+    fn rebalance_del(&mut self, n: usize, _child: usize) {
+        let child = self.get(n).lchild.or(self.get(n).rchild);
+        self.replace_node(n, child);
+        let size = self.size - 1;
+        if 3 * size < 2 * self.max_size {
+            if let Some(r) = self.root {
+                self.rebuild(r);
+            }
+            self.max_size = size;
+        }
+    }
+
+    fn delete_replace(&mut self, n: usize) -> usize {
+        match (self.get(n).lchild, self.get(n).rchild) {
+            (Some(_lc), Some(rc)) => {
+                let successor = self.get(rc).find_min(&self.data);
+                swap_values(&mut self.data, n, successor);
+                successor
+            }
+            _ => n,
+        }
+    }
+
+    fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>) {
+        let parent = self.get(to_delete).parent;
+        if let Some(p) = parent {
+            let side = self.get(to_delete).side(&self.data);
+            self.get_mut(p).set_child_opt(to_attach, side);
+        } else {
+            self.root = to_attach;
+        }
+        if let Some(c) = to_attach {
+            self.get_mut(c).parent = parent;
+        }
+    }
+
+    fn get_size(&self) -> usize {
+        self.size
+    }
+
+    fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    fn create_node(&mut self, val: T) -> usize {
+        if let Some(n) = self.free.pop() {
+            self.data[n] = ScapegoatNode::new(val, n);
+            n
+        } else {
+            let loc = self.data.len();
+            self.data.push(ScapegoatNode::new(val, loc));
+            loc
+        }
+    }
+
+    fn delete_node(&mut self, index: usize) -> T {
+        self.free.push(index);
+        self.data[index].take_value()
+    }
+}
+
+impl<T: PartialOrd> ScapegoatTree<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_by(iter, Natural)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        let (data, root) = read_snapshot(bytes)?;
+        let tree = Self {
+            root,
+            size: data.len(),
+            max_size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp: Natural,
+        };
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd> Default for ScapegoatTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, C: Comparator<T>> ScapegoatTree<T, C> {
+    /// a binary snapshot keeping the exact shape of the tree
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        write_snapshot(&self.data, self.root)
+    }
+
+    /**
+     * Relinks the subtree under top into a perfectly balanced one, in place.
+     * Its nodes are collected in order and the middle of each run becomes
+     * the parent of the two halves, so no value moves between nodes
+     */
+    fn rebuild(&mut self, top: usize) {
+        fn link<T>(data: &mut [ScapegoatNode<T>], order: &[usize]) -> Option<usize> {
+            if order.is_empty() {
+                return None;
+            }
+            let mid = order.len() / 2;
+            let n = order[mid];
+            let left = link(data, &order[..mid]);
+            let right = link(data, &order[mid + 1..]);
+            data[n].lchild = left;
+            data[n].rchild = right;
+            for c in left.iter().chain(right.iter()) {
+                data[*c].parent = Some(n);
+            }
+            data[n].size = order.len();
+            Some(n)
+        }
+
+        let parent = self.get(top).parent;
+        let side = self.get(top).side(&self.data);
+        let mut order = Vec::with_capacity(self.get(top).size);
+        let mut n = Some(self.get(top).find_min(&self.data));
+        for _ in 0..self.get(top).size {
+            let i = n.expect("subtree smaller than its size");
+            order.push(i);
+            n = self.get(i).get_successor(&self.data);
+        }
+
+        let new_top = link(&mut self.data, &order).expect("rebuilt an empty subtree");
+        self.get_mut(new_top).parent = parent;
+        match parent {
+            Some(p) => self.get_mut(p).set_child(new_top, side),
+            None => self.root = Some(new_top),
+        }
+    }
+
+    /// moves every live node to the front of the arena and drops the freed
+    /// slots
+    pub fn shrink_to_fit(&mut self) {
+        compact(&mut self.data, &mut self.root);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+}
+
+impl<T, C: Comparator<T>> IntoIterator for ScapegoatTree<T, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let order = in_order(&self);
+        IntoIter::new(self.data, order)
+    }
+}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a ScapegoatTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, ScapegoatTree<T, C>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_limits() {
+        assert_eq!(depth_limit(1), 0);
+        assert_eq!(depth_limit(2), 1);
+        assert_eq!(depth_limit(3), 2);
+        assert_eq!(depth_limit(100), 11);
+    }
+
+    #[test]
+    fn sorted_inserts() {
+        let mut tree = ScapegoatTree::new();
+        for x in 0..1000 {
+            tree.insert(x);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert!(tree.get_height() <= depth_limit(1000) + 1);
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+        assert_eq!(tree.select(500), Some(&500));
+    }
+
+    #[test]
+    fn delete_rebuilds() {
+        let mut tree = ScapegoatTree::from_sorted_iter(0..90);
+        for x in 0..30 {
+            tree.delete(x);
+            assert_eq!(tree.max_size, 90);
+        }
+        // the 31st delete takes the tree under two thirds of its largest size
+        tree.delete(30);
+        assert_eq!(tree.max_size, 59);
+        assert_eq!(tree.get_height(), 6);
+        assert_eq!(tree.validate(), Ok(()));
+
+        let mut tree = ScapegoatTree::from_sorted_iter(0..10);
+        tree.max_size = 1;
+        assert!(matches!(tree.validate(), Err(InvariantError::TooDeep { .. })));
+    }
+}
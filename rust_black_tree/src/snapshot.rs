@@ -0,0 +1,482 @@
+use std::fmt;
+
+use super::node::{Node, Side};
+
+/**
+ * A snapshot is a small header followed by every node of the tree in pre
+ * order. The header is the magic bytes, the format version, the kind of tree
+ * and the node count. Each node is a byte saying which children follow, then
+ * its value, then whatever balancing data its kind of tree keeps. All
+ * numbers are little endian
+ */
+const MAGIC: &[u8; 4] = b"RBTS";
+const VERSION: u8 = 1;
+const HAS_LEFT: u8 = 1;
+const HAS_RIGHT: u8 = 2;
+
+/// the kind of tree a snapshot was taken of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotKind {
+    RedBlack,
+    AVL,
+    Unbalanced,
+    Splay,
+    Treap,
+    AA,
+    Scapegoat,
+    BTree,
+}
+
+impl SnapshotKind {
+    fn from_byte(b: u8) -> Result<Self, SnapshotError> {
+        match b {
+            0 => Ok(SnapshotKind::RedBlack),
+            1 => Ok(SnapshotKind::AVL),
+            2 => Ok(SnapshotKind::Unbalanced),
+            3 => Ok(SnapshotKind::Splay),
+            4 => Ok(SnapshotKind::Treap),
+            5 => Ok(SnapshotKind::AA),
+            6 => Ok(SnapshotKind::Scapegoat),
+            7 => Ok(SnapshotKind::BTree),
+            _ => Err(SnapshotError::UnknownKind(b)),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            SnapshotKind::RedBlack => 0,
+            SnapshotKind::AVL => 1,
+            SnapshotKind::Unbalanced => 2,
+            SnapshotKind::Splay => 3,
+            SnapshotKind::Treap => 4,
+            SnapshotKind::AA => 5,
+            SnapshotKind::Scapegoat => 6,
+            SnapshotKind::BTree => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// the data does not start with the snapshot magic bytes
+    BadMagic,
+    /// the snapshot was written by a newer version of the format
+    UnsupportedVersion(u8),
+    UnknownKind(u8),
+    /// the snapshot is of a different kind of tree than the one loading it
+    WrongKind {
+        expected: SnapshotKind,
+        found: SnapshotKind,
+    },
+    /// the data ends in the middle of a node
+    Truncated,
+    /// a value or balancing field could not be decoded
+    BadValue,
+    /// the child flags don't describe a tree with the given node count
+    BadShape,
+    /// there is data left over after the last node
+    TrailingData,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not a tree snapshot"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "snapshot format version {} is not supported", v)
+            }
+            SnapshotError::UnknownKind(k) => write!(f, "unknown tree kind {}", k),
+            SnapshotError::WrongKind { expected, found } => write!(
+                f,
+                "snapshot is of a {:?} tree, expected {:?}",
+                found, expected
+            ),
+            SnapshotError::Truncated => write!(f, "snapshot ends too early"),
+            SnapshotError::BadValue => write!(f, "snapshot holds a value that can't be read"),
+            SnapshotError::BadShape => write!(f, "snapshot does not describe a tree"),
+            SnapshotError::TrailingData => write!(f, "snapshot has data after the last node"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// a value that can be written into and read back out of a snapshot
+pub trait SnapshotValue: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(input: &mut &[u8]) -> Result<Self, SnapshotError>;
+}
+
+/// splits the first n bytes off of the input
+pub fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], SnapshotError> {
+    if input.len() < n {
+        return Err(SnapshotError::Truncated);
+    }
+    let (head, rest) = input.split_at(n);
+    *input = rest;
+    Ok(head)
+}
+
+macro_rules! snapshot_int {
+    ($($t:ty),*) => {$(
+        impl SnapshotValue for $t {
+            fn write(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                let len = bytes.len();
+                bytes.copy_from_slice(take(input, len)?);
+                Ok(<$t>::from_le_bytes(bytes))
+            }
+        }
+    )*};
+}
+
+snapshot_int!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128);
+
+// word sized numbers are always written as 64 bits so a snapshot reads the
+// same on any machine
+impl SnapshotValue for isize {
+    fn write(&self, out: &mut Vec<u8>) {
+        (*self as i64).write(out)
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        let v = i64::read(input)?;
+        if v < isize::MIN as i64 || v > isize::MAX as i64 {
+            return Err(SnapshotError::BadValue);
+        }
+        Ok(v as isize)
+    }
+}
+
+impl SnapshotValue for usize {
+    fn write(&self, out: &mut Vec<u8>) {
+        (*self as u64).write(out)
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        let v = u64::read(input)?;
+        if v > usize::MAX as u64 {
+            return Err(SnapshotError::BadValue);
+        }
+        Ok(v as usize)
+    }
+}
+
+// floats go by their bits, so even a NaN or a negative zero comes back as
+// it was
+macro_rules! snapshot_float {
+    ($($t:ty),*) => {$(
+        impl SnapshotValue for $t {
+            fn write(&self, out: &mut Vec<u8>) {
+                self.to_bits().write(out)
+            }
+
+            fn read(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+                Ok(<$t>::from_bits(SnapshotValue::read(input)?))
+            }
+        }
+    )*};
+}
+
+snapshot_float!(f32, f64);
+
+impl SnapshotValue for bool {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8)
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        match take(input, 1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::BadValue),
+        }
+    }
+}
+
+impl SnapshotValue for char {
+    fn write(&self, out: &mut Vec<u8>) {
+        (*self as u32).write(out)
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        std::char::from_u32(u32::read(input)?).ok_or(SnapshotError::BadValue)
+    }
+}
+
+impl SnapshotValue for String {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.len().write(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        let len = usize::read(input)?;
+        String::from_utf8(take(input, len)?.to_vec()).map_err(|_| SnapshotError::BadValue)
+    }
+}
+
+/// the per node half of the format, implemented next to each kind of node
+pub(crate) trait SnapshotNode<T>: Node<T> {
+    const KIND: SnapshotKind;
+    fn blank(val: T, ptr: usize) -> Self;
+    fn write_extra(&self, out: &mut Vec<u8>);
+    fn read_extra(&mut self, input: &mut &[u8]) -> Result<(), SnapshotError>;
+}
+
+/// reads the header of a snapshot to find out which kind of tree it holds
+pub fn snapshot_kind(bytes: &[u8]) -> Result<SnapshotKind, SnapshotError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+    let mut input = &bytes[MAGIC.len()..];
+    let version = u8::read(&mut input)?;
+    if version != VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    SnapshotKind::from_byte(u8::read(&mut input)?)
+}
+
+/// the header of a snapshot of a kind of tree holding count nodes
+pub(crate) fn write_header(kind: SnapshotKind, count: usize) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(kind.to_byte());
+    count.write(&mut out);
+    out
+}
+
+/// checks that a snapshot is of the expected kind, returning its node count
+/// and the data after the header
+pub(crate) fn read_header(bytes: &[u8], kind: SnapshotKind) -> Result<(usize, &[u8]), SnapshotError> {
+    let found = snapshot_kind(bytes)?;
+    if found != kind {
+        return Err(SnapshotError::WrongKind {
+            expected: kind,
+            found,
+        });
+    }
+    let mut input = &bytes[MAGIC.len() + 2..];
+    let count = usize::read(&mut input)?;
+    Ok((count, input))
+}
+
+pub(crate) fn write_snapshot<T, N>(data: &[N], root: Option<usize>) -> Vec<u8>
+where
+    T: SnapshotValue,
+    N: SnapshotNode<T>,
+{
+    let mut order = Vec::new();
+    let mut stack: Vec<usize> = root.into_iter().collect();
+    while let Some(n) = stack.pop() {
+        order.push(n);
+        stack.extend(data[n].get_child(Side::Right));
+        stack.extend(data[n].get_child(Side::Left));
+    }
+
+    let mut out = write_header(N::KIND, order.len());
+    for &n in &order {
+        let node = &data[n];
+        let mut flags = 0;
+        if node.get_child(Side::Left).is_some() {
+            flags |= HAS_LEFT;
+        }
+        if node.get_child(Side::Right).is_some() {
+            flags |= HAS_RIGHT;
+        }
+        out.push(flags);
+        node.get_value().write(&mut out);
+        node.write_extra(&mut out);
+    }
+    out
+}
+
+/**
+ * Rebuilds the arena a snapshot was taken of, numbered in pre order, and
+ * returns it with its root. Subtree sizes are worked out again rather than
+ * stored. Only the links are checked here, so the tree built from the arena
+ * has to validate itself before it is handed out
+ */
+pub(crate) fn read_snapshot<T, N>(bytes: &[u8]) -> Result<(Vec<N>, Option<usize>), SnapshotError>
+where
+    T: SnapshotValue,
+    N: SnapshotNode<T>,
+{
+    let (count, mut input) = read_header(bytes, N::KIND)?;
+
+    let mut data: Vec<N> = Vec::new();
+    // the slots still waiting for a node, the next one to fill on top
+    let mut pending: Vec<(Option<usize>, Side)> = vec![(None, Side::Left)];
+    for i in 0..count {
+        let (parent, side) = pending.pop().ok_or(SnapshotError::BadShape)?;
+        let flags = u8::read(&mut input)?;
+        if flags & !(HAS_LEFT | HAS_RIGHT) != 0 {
+            return Err(SnapshotError::BadShape);
+        }
+        let mut node = N::blank(T::read(&mut input)?, i);
+        node.read_extra(&mut input)?;
+        node.set_parent(parent);
+        if let Some(p) = parent {
+            data[p].set_child(i, side);
+        }
+        data.push(node);
+        if flags & HAS_RIGHT != 0 {
+            pending.push((Some(i), Side::Right));
+        }
+        if flags & HAS_LEFT != 0 {
+            pending.push((Some(i), Side::Left));
+        }
+    }
+    if count > 0 && !pending.is_empty() {
+        return Err(SnapshotError::BadShape);
+    }
+    if !input.is_empty() {
+        return Err(SnapshotError::TrailingData);
+    }
+
+    // in pre order every child comes after its parent
+    for i in (0..data.len()).rev() {
+        let size = |c: Option<usize>| c.map(|c| data[c].subtree_size()).unwrap_or(0);
+        let total = 1 + size(data[i].get_child(Side::Left)) + size(data[i].get_child(Side::Right));
+        data[i].set_subtree_size(total);
+    }
+    let root = if data.is_empty() { None } else { Some(0) };
+    Ok((data, root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avltree::AVLTree;
+    use crate::rbtree::RBTree;
+    use crate::tree::Tree;
+    use crate::unbalancetree::BSTree;
+
+    #[test]
+    fn values_round_trip() {
+        let mut out = Vec::new();
+        (-5i32).write(&mut out);
+        String::from("tree").write(&mut out);
+        'x'.write(&mut out);
+        true.write(&mut out);
+        (-3isize).write(&mut out);
+        (-0.5f64).write(&mut out);
+        let mut input = &out[..];
+        assert_eq!(i32::read(&mut input), Ok(-5));
+        assert_eq!(String::read(&mut input), Ok(String::from("tree")));
+        assert_eq!(char::read(&mut input), Ok('x'));
+        assert_eq!(bool::read(&mut input), Ok(true));
+        assert_eq!(isize::read(&mut input), Ok(-3));
+        assert_eq!(f64::read(&mut input), Ok(-0.5));
+        assert!(input.is_empty());
+        assert_eq!(u16::read(&mut input), Err(SnapshotError::Truncated));
+    }
+
+    // to_string without the parent indices, which are renumbered on load
+    fn shape<T: std::fmt::Debug, B: Tree<T>>(tree: &B) -> String {
+        let s = tree.to_string();
+        let mut parts = s.split("P:");
+        let first = parts.next().unwrap_or("").to_string();
+        parts.fold(first, |acc, part| acc + part.split_once(' ').map_or("", |(_, rest)| rest))
+    }
+
+    #[test]
+    fn same_shape() {
+        let mut rb = RBTree::new();
+        let mut avl = AVLTree::new();
+        let mut bst = BSTree::new();
+        for x in &[50, 20, 80, 10, 30, 70, 90, 25, 35, 5, 1] {
+            rb.insert(*x);
+            avl.insert(*x);
+            bst.insert(*x);
+        }
+        rb.delete(80);
+        avl.delete(80);
+        bst.delete(50);
+
+        let back = RBTree::<i32>::from_snapshot(&rb.to_snapshot()).unwrap();
+        assert_eq!(shape(&back), shape(&rb));
+        assert_eq!(back.to_snapshot(), rb.to_snapshot());
+        assert_eq!(back.validate(), Ok(()));
+        let back = AVLTree::<i32>::from_snapshot(&avl.to_snapshot()).unwrap();
+        assert_eq!(shape(&back), shape(&avl));
+        assert_eq!(back.to_snapshot(), avl.to_snapshot());
+        assert_eq!(back.validate(), Ok(()));
+        let back = BSTree::<i32>::from_snapshot(&bst.to_snapshot()).unwrap();
+        assert_eq!(shape(&back), shape(&bst));
+        assert_eq!(back.to_snapshot(), bst.to_snapshot());
+        assert_eq!(back.rank(&30), 5);
+
+        let empty = RBTree::<String>::new().to_snapshot();
+        assert!(RBTree::<String>::from_snapshot(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_snapshots() {
+        let rb = RBTree::from_sorted_iter(0..10i64);
+        let bytes = rb.to_snapshot();
+        assert_eq!(snapshot_kind(&bytes), Ok(SnapshotKind::RedBlack));
+        assert_eq!(
+            AVLTree::<i64>::from_snapshot(&bytes).unwrap_err(),
+            SnapshotError::WrongKind {
+                expected: SnapshotKind::AVL,
+                found: SnapshotKind::RedBlack
+            }
+        );
+        assert_eq!(
+            RBTree::<i64>::from_snapshot(&bytes[..bytes.len() - 1]).unwrap_err(),
+            SnapshotError::Truncated
+        );
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            RBTree::<i64>::from_snapshot(&longer).unwrap_err(),
+            SnapshotError::TrailingData
+        );
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(
+            RBTree::<i64>::from_snapshot(&newer).unwrap_err(),
+            SnapshotError::UnsupportedVersion(2)
+        );
+        assert_eq!(
+            RBTree::<i64>::from_snapshot(b"nope").unwrap_err(),
+            SnapshotError::BadMagic
+        );
+        // the root claims no children, leaving the other nine nodes nowhere
+        let mut flat = bytes;
+        flat[14] = 0;
+        assert_eq!(
+            RBTree::<i64>::from_snapshot(&flat).unwrap_err(),
+            SnapshotError::BadShape
+        );
+    }
+
+    #[test]
+    fn corrupt_trees() {
+        // a lone red node, which would break the rule that the root is black
+        let mut red_root = write_header(SnapshotKind::RedBlack, 1);
+        red_root.push(0);
+        7i64.write(&mut red_root);
+        true.write(&mut red_root);
+        assert_eq!(
+            RBTree::<i64>::from_snapshot(&red_root).unwrap_err(),
+            SnapshotError::BadShape
+        );
+
+        // a well formed tree whose left child is larger than its root
+        let mut unsorted = write_header(SnapshotKind::Unbalanced, 2);
+        unsorted.push(HAS_LEFT);
+        1i64.write(&mut unsorted);
+        unsorted.push(0);
+        2i64.write(&mut unsorted);
+        assert_eq!(
+            BSTree::<i64>::from_snapshot(&unsorted).unwrap_err(),
+            SnapshotError::BadShape
+        );
+    }
+}
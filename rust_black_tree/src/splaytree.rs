@@ -0,0 +1,446 @@
+use std::fmt::Debug;
+
+use super::comparator::{Comparator, Natural};
+use super::tree::BaseTree;
+use super::tree::Tree;
+use super::tree::{
+    compact, in_order, link_balanced, sorted_arena, swap_values, validate_links, IntoIter, Iter,
+};
+use super::tree::InvariantError;
+use super::snapshot::{
+    read_snapshot, write_snapshot, SnapshotError, SnapshotKind, SnapshotNode, SnapshotValue,
+};
+
+use super::node::Node;
+use super::node::*;
+
+/// a nice convenient macro which allows a user to initialize a tree with
+/// a number of elements
+/// usage: splay!{1, 2, 3, 4, 5, 6, 7, 8, 9, 0};
+#[macro_export]
+macro_rules! splay {
+    ( $( $x:expr ),* ) => {
+        {
+            let mut temp_tree = SplayTree::new();
+            $(
+                temp_tree.insert($x);
+            )*
+            temp_tree
+        }
+    };
+}
+
+#[derive(Debug)]
+pub struct SplayNode<T> {
+    pub value: Option<T>,
+    pub ptr: usize,
+    pub parent: Option<usize>,
+    pub lchild: Option<usize>,
+    pub rchild: Option<usize>,
+    /// number of nodes in the subtree rooted here, including this one
+    pub size: usize,
+}
+
+impl<T> SplayNode<T> {
+    fn new(val: T, selfptr: usize) -> Self {
+        Self {
+            value: Some(val),
+            ptr: selfptr,
+            parent: None,
+            lchild: None,
+            rchild: None,
+            size: 1,
+        }
+    }
+}
+
+impl<T> Node<T> for SplayNode<T> {
+    fn to_self_string(&self) -> String
+    where
+        T: Debug,
+    {
+        format!("[P:{:?} V:{:?}]", self.parent, self.get_value())
+    }
+
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug,
+    {
+        let s = format!("{:?}", self.get_value());
+        let l = s.len();
+        (s, l)
+    }
+
+    fn export_style(&self, _data: &[Self]) -> NodeStyle
+    where
+        T: Debug,
+    {
+        NodeStyle {
+            label: format!("{:?}", self.get_value()),
+            fill: if self.parent.is_none() { "gold" } else { "white" },
+            font: "black",
+            attributes: Vec::new(),
+        }
+    }
+
+    fn get_value(&self) -> &T {
+        self.value.as_ref().expect("freed node")
+    }
+    fn get_value_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("freed node")
+    }
+    fn into_value(self) -> T {
+        self.value.expect("freed node")
+    }
+    fn take_value(&mut self) -> T {
+        self.value.take().expect("freed node")
+    }
+    fn get_child(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Left => self.lchild,
+            Side::Right => self.rchild,
+        }
+    }
+
+    fn set_child(&mut self, child: usize, side: Side) {
+        self.set_child_opt(Some(child), side)
+    }
+
+    fn set_child_opt(&mut self, c: Option<usize>, side: Side) {
+        match side {
+            Side::Left => self.lchild = c,
+            Side::Right => self.rchild = c,
+        };
+    }
+    fn set_parent(&mut self, p: Option<usize>) {
+        self.parent = p;
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn location(&self) -> usize {
+        self.ptr
+    }
+
+    fn set_location(&mut self, ptr: usize) {
+        self.ptr = ptr;
+    }
+
+    fn subtree_size(&self) -> usize {
+        self.size
+    }
+
+    fn set_subtree_size(&mut self, size: usize) {
+        self.size = size;
+    }
+}
+
+/**
+ * Arena based memory tree structure. Every node that is inserted, or looked
+ * up through access, is rotated up to the root, so recently used values stay
+ * close to the top. There is no balance to keep, only amortized O(log n)
+ * per operation
+*/
+#[derive(Debug)]
+pub struct SplayTree<T, C = Natural> {
+    root: Option<usize>,
+    size: usize,
+    data: Vec<SplayNode<T>>,
+    free: Vec<usize>,
+    cmp: C,
+}
+
+impl<T> SnapshotNode<T> for SplayNode<T> {
+    const KIND: SnapshotKind = SnapshotKind::Splay;
+
+    fn blank(val: T, ptr: usize) -> Self {
+        SplayNode::new(val, ptr)
+    }
+
+    fn write_extra(&self, _out: &mut Vec<u8>) {}
+
+    fn read_extra(&mut self, _input: &mut &[u8]) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+impl<T, C: Comparator<T>> Tree<T> for SplayTree<T, C> {
+    fn with_comparator(cmp: C) -> Self {
+        Self {
+            root: None,
+            data: Vec::new(),
+            size: 0,
+            free: Vec::new(),
+            cmp,
+        }
+    }
+
+    fn validate(&self) -> Result<(), InvariantError> {
+        validate_links(self)
+    }
+
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Self {
+        let mut data = sorted_arena(iter, &cmp, SplayNode::new);
+        let root = link_balanced(&mut data, &mut |_: &mut [SplayNode<T>], _, _| {});
+        Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp,
+        }
+    }
+}
+
+impl<T, C: Comparator<T>> BaseTree<T> for SplayTree<T, C> {
+    type MNode = SplayNode<T>;
+    type Cmp = C;
+    fn get(&self, val: usize) -> &Self::MNode {
+        &self.data[val]
+    }
+
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode {
+        &mut self.data[val]
+    }
+
+    fn get_data(&self) -> &[Self::MNode] {
+        &self.data
+    }
+
+    fn get_root(&self) -> Option<usize> {
+        self.root
+    }
+
+    fn set_root(&mut self, new_root: Option<usize>) {
+        self.root = new_root
+    }
+
+    fn crement_size(&mut self, amount: isize) {
+        self.size = (self.size as isize + amount) as usize;
+    }
+
+    fn rebalance_ins(&mut self, n: usize) {
+        self.splay(n);
+    }
+
+    // the parent of the removed node was the last one touched, so it is the
+    // one brought up to the root
+    fn rebalance_del(&mut self, n: usize, _child: usize) {
+        let parent = self.get(n).parent;
+        let child = self.get(n).lchild.or(self.get(n).rchild);
+        self.replace_node(n, child);
+        if let Some(p) = parent {
+            self.splay(p);
+        }
+    }
+
+    fn delete_replace(&mut self, n: usize) -> usize {
+        match (self.get(n).lchild, self.get(n).rchild) {
+            (Some(_lc), Some(rc)) => {
+                let successor = self.get(rc).find_min(&self.data);
+                swap_values(&mut self.data, n, successor);
+                successor
+            }
+            _ => n,
+        }
+    }
+
+    fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>) {
+        let parent = self.get(to_delete).parent;
+        if let Some(p) = parent {
+            let side = self.get(to_delete).side(&self.data);
+            self.get_mut(p).set_child_opt(to_attach, side);
+        } else {
+            self.root = to_attach;
+        }
+        if let Some(c) = to_attach {
+            self.get_mut(c).parent = parent;
+        }
+    }
+
+    fn get_size(&self) -> usize {
+        self.size
+    }
+
+    fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    fn create_node(&mut self, val: T) -> usize {
+        if let Some(n) = self.free.pop() {
+            self.data[n] = SplayNode::new(val, n);
+            n
+        } else {
+            let loc = self.data.len();
+            self.data.push(SplayNode::new(val, loc));
+            loc
+        }
+    }
+
+    fn delete_node(&mut self, index: usize) -> T {
+        self.free.push(index);
+        self.data[index].take_value()
+    }
+}
+
+impl<T: PartialOrd> SplayTree<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_by(iter, Natural)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        let (data, root) = read_snapshot(bytes)?;
+        let tree = Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp: Natural,
+        };
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd> Default for SplayTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, C: Comparator<T>> SplayTree<T, C> {
+    /// a binary snapshot keeping the exact shape of the tree
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        write_snapshot(&self.data, self.root)
+    }
+
+    /**
+     * Looks val up and splays the node it ends on to the root, which is the
+     * node holding val if there is one. Tree::contains can't move anything
+     * since it only borrows the tree, so this is the lookup that keeps the
+     * values being asked for near the top
+     */
+    pub fn access(&mut self, val: &T) -> bool {
+        if self.root.is_none() {
+            return false;
+        }
+        let n = self.find(val);
+        self.splay(n);
+        self.contains(val)
+    }
+
+    // rotates n up until it is the root, two levels at a time
+    fn splay(&mut self, n: usize) {
+        while let Some(p) = self.get(n).parent {
+            let side = self.get(n).side(&self.data);
+            match self.get(p).parent {
+                // zig: p is the root
+                None => self.rotate(!side, n),
+                // zig-zig: n and p are on the same side, p goes first
+                Some(_) if self.get(p).side(&self.data) == side => {
+                    self.rotate(!side, p);
+                    self.rotate(!side, n);
+                }
+                // zig-zag
+                Some(_) => {
+                    self.rotate(!side, n);
+                    self.rotate(side, n);
+                }
+            }
+        }
+    }
+
+    /// moves every live node to the front of the arena and drops the freed
+    /// slots
+    pub fn shrink_to_fit(&mut self) {
+        compact(&mut self.data, &mut self.root);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+}
+
+impl<T, C: Comparator<T>> IntoIterator for SplayTree<T, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let order = in_order(&self);
+        IntoIter::new(self.data, order)
+    }
+}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a SplayTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, SplayTree<T, C>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root_value(tree: &SplayTree<i32>) -> Option<i32> {
+        tree.root.map(|r| *tree.data[r].get_value())
+    }
+
+    #[test]
+    fn insert_splays() {
+        let mut tree = SplayTree::new();
+        for x in &[50, 20, 80, 10, 30, 70, 90] {
+            tree.insert(*x);
+            assert_eq!(root_value(&tree), Some(*x));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), vec![10, 20, 30, 50, 70, 80, 90]);
+        assert_eq!(tree.rank(&70), 4);
+    }
+
+    #[test]
+    fn access() {
+        let mut tree = SplayTree::from_sorted_iter(0..100);
+        assert!(tree.access(&37));
+        assert_eq!(root_value(&tree), Some(37));
+        assert_eq!(tree.validate(), Ok(()));
+        // a miss still brings its neighbour up
+        assert!(!tree.access(&1000));
+        assert_eq!(root_value(&tree), Some(99));
+        assert!(!SplayTree::<i32>::new().access(&1));
+    }
+
+    #[test]
+    fn delete() {
+        let mut tree = SplayTree::new();
+        for x in 0..50 {
+            tree.insert(x);
+        }
+        // inserting in order leaves a path, which splaying folds back up
+        assert_eq!(tree.get_height(), 50);
+        assert!(tree.access(&0));
+        assert!(tree.get_height() < 30);
+        for x in (0..50).step_by(3) {
+            assert!(tree.delete(x));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert!(!tree.delete(3));
+        assert_eq!(tree.get_size(), 33);
+        assert_eq!(tree.data.len(), 50);
+        tree.shrink_to_fit();
+        assert_eq!(tree.data.len(), 33);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
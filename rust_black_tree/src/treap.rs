@@ -0,0 +1,478 @@
+use std::fmt::Debug;
+
+use super::comparator::{Comparator, Natural};
+use super::tree::BaseTree;
+use super::tree::Tree;
+use super::tree::{
+    compact, in_order, link_balanced, sorted_arena, validate_links, IntoIter, Iter,
+};
+use super::tree::InvariantError;
+use super::snapshot::{
+    read_snapshot, write_snapshot, SnapshotError, SnapshotKind, SnapshotNode, SnapshotValue,
+};
+
+use super::node::Node;
+use super::node::*;
+
+/// a nice convenient macro which allows a user to initialize a tree with
+/// a number of elements
+/// usage: treap!{1, 2, 3, 4, 5, 6, 7, 8, 9, 0};
+#[macro_export]
+macro_rules! treap {
+    ( $( $x:expr ),* ) => {
+        {
+            let mut temp_tree = Treap::new();
+            $(
+                temp_tree.insert($x);
+            )*
+            temp_tree
+        }
+    };
+}
+
+/// the seed of every treap that isn't given one, so runs are repeatable
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+#[derive(Debug)]
+pub struct TreapNode<T> {
+    pub value: Option<T>,
+    pub ptr: usize,
+    pub parent: Option<usize>,
+    pub lchild: Option<usize>,
+    pub rchild: Option<usize>,
+    /// random, and never lower than the priority of either child
+    pub priority: u64,
+    /// number of nodes in the subtree rooted here, including this one
+    pub size: usize,
+}
+
+impl<T> TreapNode<T> {
+    fn new(val: T, selfptr: usize, priority: u64) -> Self {
+        Self {
+            value: Some(val),
+            ptr: selfptr,
+            parent: None,
+            lchild: None,
+            rchild: None,
+            priority,
+            size: 1,
+        }
+    }
+}
+
+impl<T> Node<T> for TreapNode<T> {
+    fn to_self_string(&self) -> String
+    where
+        T: Debug,
+    {
+        format!(
+            "[P:{:?} V:{:?} Pri:{}]",
+            self.parent, self.get_value(), self.priority
+        )
+    }
+
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug,
+    {
+        let s = format!("{:?}", self.get_value());
+        let l = s.len();
+        (s, l)
+    }
+
+    fn export_style(&self, _data: &[Self]) -> NodeStyle
+    where
+        T: Debug,
+    {
+        NodeStyle {
+            label: format!("{:?}", self.get_value()),
+            fill: "lightyellow",
+            font: "black",
+            // the top bits are enough to see the heap order
+            attributes: vec![("priority", (self.priority >> 48).to_string())],
+        }
+    }
+
+    fn get_value(&self) -> &T {
+        self.value.as_ref().expect("freed node")
+    }
+    fn get_value_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("freed node")
+    }
+    fn into_value(self) -> T {
+        self.value.expect("freed node")
+    }
+    fn take_value(&mut self) -> T {
+        self.value.take().expect("freed node")
+    }
+    fn get_child(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Left => self.lchild,
+            Side::Right => self.rchild,
+        }
+    }
+
+    fn set_child(&mut self, child: usize, side: Side) {
+        self.set_child_opt(Some(child), side)
+    }
+
+    fn set_child_opt(&mut self, c: Option<usize>, side: Side) {
+        match side {
+            Side::Left => self.lchild = c,
+            Side::Right => self.rchild = c,
+        };
+    }
+    fn set_parent(&mut self, p: Option<usize>) {
+        self.parent = p;
+    }
+
+    fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn location(&self) -> usize {
+        self.ptr
+    }
+
+    fn set_location(&mut self, ptr: usize) {
+        self.ptr = ptr;
+    }
+
+    fn subtree_size(&self) -> usize {
+        self.size
+    }
+
+    fn set_subtree_size(&mut self, size: usize) {
+        self.size = size;
+    }
+}
+
+/**
+ * Arena based memory tree structure. Each node draws a random priority and
+ * the tree is kept a heap on those priorities, which makes its shape that of
+ * a tree built from a random insertion order, O(log n) deep with high
+ * probability. The priorities come from a seeded generator, so the same
+ * seed and operations always give the same tree
+*/
+#[derive(Debug)]
+pub struct Treap<T, C = Natural> {
+    root: Option<usize>,
+    size: usize,
+    data: Vec<TreapNode<T>>,
+    free: Vec<usize>,
+    cmp: C,
+    rng: u64,
+}
+
+impl<T> SnapshotNode<T> for TreapNode<T> {
+    const KIND: SnapshotKind = SnapshotKind::Treap;
+
+    fn blank(val: T, ptr: usize) -> Self {
+        TreapNode::new(val, ptr, 0)
+    }
+
+    fn write_extra(&self, out: &mut Vec<u8>) {
+        self.priority.write(out);
+    }
+
+    fn read_extra(&mut self, input: &mut &[u8]) -> Result<(), SnapshotError> {
+        self.priority = u64::read(input)?;
+        Ok(())
+    }
+}
+
+impl<T, C: Comparator<T>> Tree<T> for Treap<T, C> {
+    fn with_comparator(cmp: C) -> Self {
+        Self {
+            root: None,
+            data: Vec::new(),
+            size: 0,
+            free: Vec::new(),
+            cmp,
+            rng: DEFAULT_SEED,
+        }
+    }
+
+    fn validate(&self) -> Result<(), InvariantError> {
+        validate_links(self)?;
+        for n in in_order(self) {
+            if let Some(p) = self.get(n).parent {
+                if self.get(n).priority > self.get(p).priority {
+                    return Err(InvariantError::PriorityViolation { parent: p, child: n });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Self {
+        let mut data = sorted_arena(iter, &cmp, |val, loc| TreapNode::new(val, loc, 0));
+        let root = link_balanced(&mut data, &mut |_: &mut [TreapNode<T>], _, _| {});
+        let mut tree = Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp,
+            rng: DEFAULT_SEED,
+        };
+        // handing out fresh priorities from the highest down, in breadth
+        // first order, puts every parent above its children
+        let mut priorities: Vec<u64> = (0..tree.size).map(|_| tree.next_priority()).collect();
+        priorities.sort_unstable_by(|a, b| b.cmp(a));
+        let mut queue: Vec<usize> = tree.root.into_iter().collect();
+        let mut i = 0;
+        while i < queue.len() {
+            let n = queue[i];
+            tree.data[n].priority = priorities[i];
+            queue.extend(tree.data[n].lchild);
+            queue.extend(tree.data[n].rchild);
+            i += 1;
+        }
+        tree
+    }
+}
+
+impl<T, C: Comparator<T>> BaseTree<T> for Treap<T, C> {
+    type MNode = TreapNode<T>;
+    type Cmp = C;
+    fn get(&self, val: usize) -> &Self::MNode {
+        &self.data[val]
+    }
+
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode {
+        &mut self.data[val]
+    }
+
+    fn get_data(&self) -> &[Self::MNode] {
+        &self.data
+    }
+
+    fn get_root(&self) -> Option<usize> {
+        self.root
+    }
+
+    fn set_root(&mut self, new_root: Option<usize>) {
+        self.root = new_root
+    }
+
+    fn crement_size(&mut self, amount: isize) {
+        self.size = (self.size as isize + amount) as usize;
+    }
+
+    // the new node starts out as a leaf and is rotated up for as long as it
+    // outranks its parent
+    fn rebalance_ins(&mut self, n: usize) {
+        while let Some(p) = self.get(n).parent {
+            if self.get(n).priority <= self.get(p).priority {
+                break;
+            }
+            let side = self.get(n).side(&self.data);
+            self.rotate(!side, n);
+        }
+    }
+
+    fn rebalance_del(&mut self, n: usize, _child: usize) {
+        let child = self.get(n).lchild.or(self.get(n).rchild);
+        self.replace_node(n, child);
+    }
+
+    // rather than trading values with its successor, the node is rotated
+    // down below its higher priority child until it has at most one child
+    // left, which keeps the heap order without touching any priority
+    fn delete_replace(&mut self, n: usize) -> usize {
+        while let (Some(l), Some(r)) = (self.get(n).lchild, self.get(n).rchild) {
+            let up = if self.get(l).priority > self.get(r).priority {
+                l
+            } else {
+                r
+            };
+            let side = self.get(up).side(&self.data);
+            self.rotate(!side, up);
+        }
+        n
+    }
+
+    fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>) {
+        let parent = self.get(to_delete).parent;
+        if let Some(p) = parent {
+            let side = self.get(to_delete).side(&self.data);
+            self.get_mut(p).set_child_opt(to_attach, side);
+        } else {
+            self.root = to_attach;
+        }
+        if let Some(c) = to_attach {
+            self.get_mut(c).parent = parent;
+        }
+    }
+
+    fn get_size(&self) -> usize {
+        self.size
+    }
+
+    fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    fn create_node(&mut self, val: T) -> usize {
+        let priority = self.next_priority();
+        if let Some(n) = self.free.pop() {
+            self.data[n] = TreapNode::new(val, n, priority);
+            n
+        } else {
+            let loc = self.data.len();
+            self.data.push(TreapNode::new(val, loc, priority));
+            loc
+        }
+    }
+
+    fn delete_node(&mut self, index: usize) -> T {
+        self.free.push(index);
+        self.data[index].take_value()
+    }
+}
+
+impl<T: PartialOrd> Treap<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    /// an empty treap whose priorities are drawn starting from seed
+    pub fn with_seed(seed: u64) -> Self {
+        let mut tree = Self::new();
+        tree.reseed(seed);
+        tree
+    }
+
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_by(iter, Natural)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        let (data, root) = read_snapshot(bytes)?;
+        let tree = Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp: Natural,
+            rng: DEFAULT_SEED,
+        };
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd> Default for Treap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, C: Comparator<T>> Treap<T, C> {
+    /// a binary snapshot keeping the exact shape and priorities of the tree
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        write_snapshot(&self.data, self.root)
+    }
+
+    /// restarts the priority generator from seed. Nodes already in the tree
+    /// keep the priorities they have
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = seed;
+    }
+
+    // splitmix64, which is plenty random for priorities and works from any
+    // seed, zero included
+    fn next_priority(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// moves every live node to the front of the arena and drops the freed
+    /// slots
+    pub fn shrink_to_fit(&mut self) {
+        compact(&mut self.data, &mut self.root);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+}
+
+impl<T, C: Comparator<T>> IntoIterator for Treap<T, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let order = in_order(&self);
+        IntoIter::new(self.data, order)
+    }
+}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a Treap<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, Treap<T, C>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_order() {
+        let mut tree = Treap::new();
+        for x in 0..500 {
+            tree.insert(x);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        // sorted input would make a plain tree 500 deep
+        assert!(tree.get_height() < 40);
+        for x in (0..500).step_by(2) {
+            assert!(tree.delete(x));
+        }
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), (1..500).step_by(2).collect::<Vec<_>>());
+
+        let r = tree.root.unwrap();
+        let c = tree.data[r].lchild.unwrap();
+        tree.data[c].priority = u64::MAX;
+        assert_eq!(
+            tree.validate(),
+            Err(InvariantError::PriorityViolation { parent: r, child: c })
+        );
+    }
+
+    #[test]
+    fn seeded() {
+        let build = |seed| {
+            let mut tree = Treap::with_seed(seed);
+            for x in &[5, 2, 8, 1, 9, 3, 7] {
+                tree.insert(*x);
+            }
+            tree.to_string()
+        };
+        assert_eq!(build(1), build(1));
+        assert_ne!(build(1), build(2));
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let mut tree = Treap::from_sorted_iter(0..100);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.get_height(), 7);
+        tree.insert(1000);
+        tree.delete(50);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
@@ -1,12 +1,31 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use super::comparator::Comparator;
 use super::node::Node;
+use super::treeexport;
+use super::treestats::TreeStats;
 use super::node::*;
 
+/**
+ * The arena operations a tree has to provide. Each tree owns its nodes in a
+ * Vec and all mutation goes through &mut self, so no node ever aliases
+ * another and a tree can be sent to another thread whenever T can
+ */
 pub trait BaseTree<T> {
     type MNode: Node<T>;
+    /// what the values of the tree are ordered by
+    type Cmp: Comparator<T>;
     fn get(&self, val: usize) -> &Self::MNode;
-    fn get_mut(&self, val: usize) -> &mut Self::MNode;
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode;
+    /// the arena every node of the tree lives in
+    fn get_data(&self) -> &[Self::MNode];
 
-    fn delete_node(&mut self, index: usize);
+    /// frees the slot of a node that is out of the tree, handing back its
+    /// value so that it is dropped now rather than when the slot is reused
+    fn delete_node(&mut self, index: usize) -> T;
     fn create_node(&mut self, val: T) -> usize;
 
     fn rebalance_ins(&mut self, n: usize);
@@ -15,49 +34,357 @@ pub trait BaseTree<T> {
     fn delete_replace(&mut self, n: usize) -> usize;
     fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>);
 
-    fn attach_child(&self, p: usize, c: usize, side: Side);
+    fn attach_child(&mut self, p: usize, c: usize, side: Side) {
+        self.get_mut(p).set_child(c, side);
+        self.get_mut(c).set_parent(Some(p));
+    }
+
+    /// recomputes the stored subtree size of a node from its children
+    fn update_size(&mut self, n: usize) {
+        let data = self.get_data();
+        let size = |c: Option<usize>| c.map(|c| data[c].subtree_size()).unwrap_or(0);
+        let total = 1 + size(self.get(n).get_child(Side::Left))
+            + size(self.get(n).get_child(Side::Right));
+        self.get_mut(n).set_subtree_size(total);
+        self.update_summary(n);
+    }
+
+    /// adds delta to the stored size of n and every node above it
+    fn add_size_to_path(&mut self, n: usize, delta: isize) {
+        let mut next = Some(n);
+        while let Some(i) = next {
+            let size = self.get(i).subtree_size() as isize + delta;
+            self.get_mut(i).set_subtree_size(size as usize);
+            self.update_summary(i);
+            next = self.get(i).get_parent();
+        }
+    }
+
+    /// recomputes whatever summary of its subtree n keeps, from its value and
+    /// its children. Called wherever the sizes are fixed, so a tree that keeps
+    /// a summary only has to override this
+    fn update_summary(&mut self, _n: usize) {}
+
+    /// where the tree keeps its observer, for the trees that report Events
+    fn observer(&mut self) -> Option<&mut Observer<Self>> {
+        None
+    }
+
+    /// hands event to the observer, if the tree has one. The observer is
+    /// taken out while it runs so that it can look at the whole tree
+    fn emit(&mut self, event: Event) {
+        let observer = self.observer().and_then(|o| o.0.take());
+        if let Some(mut f) = observer {
+            f(&event, self);
+            if let Some(o) = self.observer() {
+                o.0 = Some(f);
+            }
+        }
+    }
 
     fn get_root(&self) -> Option<usize>;
     fn set_root(&mut self, new_root: Option<usize>);
 
     fn get_size(&self) -> usize;
     fn crement_size(&mut self, val: isize);
+
+    fn comparator(&self) -> &Self::Cmp;
+
+    /// orders two values the way the tree does
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.comparator().compare(a, b)
+    }
 }
 
-pub trait Tree<T: std::fmt::Debug>: BaseTree<T> {
-    fn new() -> Self;
+/**
+ * A broken invariant found by validate. Every variant names the arena index
+ * of the node the problem was found at, so it can be looked up in the tree
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    /// a red node has a red child
+    RedRedViolation { parent: usize, child: usize },
+    /// the root of a red black tree is red
+    RedRoot { node: usize },
+    /// the paths down either side of a node pass through a different number
+    /// of black nodes
+    BlackHeightMismatch { node: usize, left: usize, right: usize },
+    /// the stored balance factor disagrees with the subtree heights, or the
+    /// node is more than one level out of balance
+    BalanceFactorWrong { node: usize, stored: isize, actual: isize },
+    /// the node does not point back at the parent it hangs from
+    ParentLinkBroken {
+        node: usize,
+        expected: Option<usize>,
+        found: Option<usize>,
+    },
+    /// an in order walk reaches next right after node, yet next is not
+    /// greater than it
+    OrderViolation { node: usize, next: usize },
+    /// the stored subtree size disagrees with the number of nodes below
+    SizeWrong {
+        node: usize,
+        stored: usize,
+        actual: usize,
+    },
+    /// a treap node has a higher priority than its parent
+    PriorityViolation { parent: usize, child: usize },
+    /// the level of an AA tree node breaks one of the rules against the
+    /// levels of its children
+    LevelWrong { node: usize, level: usize },
+    /// a node sits deeper than a scapegoat tree of its size allows
+    TooDeep {
+        node: usize,
+        depth: usize,
+        limit: usize,
+    },
+    /// the summary a node keeps of its subtree, such as the largest interval
+    /// endpoint below it, disagrees with what its children hold
+    SummaryWrong { node: usize },
+    /// a B-tree node holds more keys than fit, or fewer than it has to
+    KeyCountWrong {
+        node: usize,
+        keys: usize,
+        min: usize,
+        max: usize,
+    },
+    /// a B-tree node that isn't a leaf has other than one child more than
+    /// it has keys
+    ChildCountWrong {
+        node: usize,
+        keys: usize,
+        children: usize,
+    },
+    /// a B-tree leaf sits at a different depth than the first leaf found
+    LeafDepthMismatch {
+        node: usize,
+        depth: usize,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantError::RedRedViolation { parent, child } => {
+                write!(f, "red node {} has red child {}", parent, child)
+            }
+            InvariantError::RedRoot { node } => write!(f, "root {} is red", node),
+            InvariantError::BlackHeightMismatch { node, left, right } => write!(
+                f,
+                "node {} has black height {} on the left and {} on the right",
+                node, left, right
+            ),
+            InvariantError::BalanceFactorWrong {
+                node,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "node {} stores balance factor {} but is {}",
+                node, stored, actual
+            ),
+            InvariantError::ParentLinkBroken {
+                node,
+                expected,
+                found,
+            } => write!(
+                f,
+                "node {} has parent {:?} but hangs from {:?}",
+                node, found, expected
+            ),
+            InvariantError::OrderViolation { node, next } => {
+                write!(f, "node {} is not less than the next node {}", node, next)
+            }
+            InvariantError::SizeWrong {
+                node,
+                stored,
+                actual,
+            } => write!(
+                f,
+                "node {} stores size {} but has {} nodes",
+                node, stored, actual
+            ),
+            InvariantError::PriorityViolation { parent, child } => write!(
+                f,
+                "node {} has a higher priority than its parent {}",
+                child, parent
+            ),
+            InvariantError::LevelWrong { node, level } => {
+                write!(f, "node {} can't be on level {}", node, level)
+            }
+            InvariantError::TooDeep { node, depth, limit } => write!(
+                f,
+                "node {} is at depth {} but the tree allows {}",
+                node, depth, limit
+            ),
+            InvariantError::SummaryWrong { node } => {
+                write!(f, "node {} has a stale summary of its subtree", node)
+            }
+            InvariantError::KeyCountWrong {
+                node,
+                keys,
+                min,
+                max,
+            } => write!(
+                f,
+                "node {} has {} keys but must have between {} and {}",
+                node, keys, min, max
+            ),
+            InvariantError::ChildCountWrong {
+                node,
+                keys,
+                children,
+            } => write!(
+                f,
+                "node {} has {} keys but {} children",
+                node, keys, children
+            ),
+            InvariantError::LeafDepthMismatch {
+                node,
+                depth,
+                expected,
+            } => write!(
+                f,
+                "leaf {} is at depth {} but other leaves are at {}",
+                node, depth, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+/**
+ * A single step a tree takes while it changes shape, for following along
+ * with a rebalance. Nodes are given by their arena index
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// a new node was linked in, before the tree rebalanced around it
+    Inserted(usize),
+    /// the value at the node is about to be deleted
+    Deleted(usize),
+    /// a red black node was painted a new color
+    Recolored(usize, Color),
+    /// the pivot was rotated up over its parent, which went down on side
+    Rotated(Side, usize),
+    /// an AVL node's balance factor changed to the given one
+    BalanceFactorChanged(usize, isize),
+    /// a red black delete reached delete_case_1 through delete_case_6
+    DeleteCase(u8),
+}
+
+/**
+ * The callback a tree reports its Events to, which also gets to see the tree
+ * as it stands right after each step
+ */
+pub struct Observer<B: ?Sized>(Option<Box<ObserverFn<B>>>);
+
+type ObserverFn<B> = dyn FnMut(&Event, &B) + Send + Sync;
+
+impl<B: ?Sized> Observer<B> {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: FnMut(&Event, &B) + Send + Sync + 'static,
+    {
+        Observer(Some(Box::new(f)))
+    }
+}
+
+impl<B: ?Sized> Default for Observer<B> {
+    fn default() -> Self {
+        Observer(None)
+    }
+}
+
+impl<B: ?Sized> fmt::Debug for Observer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Observer(Some(..))"),
+            None => write!(f, "Observer(None)"),
+        }
+    }
+}
+
+pub trait Tree<T>: BaseTree<T> {
+    /// an empty tree that orders its values with cmp
+    /// usage: RBTree::with_comparator(|a: &i32, b: &i32| b.cmp(a))
+    fn with_comparator(cmp: Self::Cmp) -> Self;
+
+    fn new() -> Self
+    where
+        Self: Sized,
+        Self::Cmp: Default,
+    {
+        Self::with_comparator(Self::Cmp::default())
+    }
+
+    /// checks every structural invariant of the tree, returning the first
+    /// one found broken
+    fn validate(&self) -> Result<(), InvariantError>;
+
+    /// builds a perfectly balanced tree in O(n) out of values given in
+    /// ascending order. Repeated values are only kept once
+    /// usage: RBTree::from_sorted_iter(0..1000)
+    fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self
+    where
+        Self: Sized,
+        Self::Cmp: Default,
+    {
+        Self::from_sorted_iter_by(iter, Self::Cmp::default())
+    }
+
+    /// from_sorted_iter for values ascending in the order of cmp
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: Self::Cmp) -> Self;
 
     fn is_empty(&self) -> bool {
-        return self.get_root().is_none()
+        self.get_root().is_none()
     }
 
     fn contains(&self, val: &T) -> bool {
+        if self.is_empty() {
+            return false;
+        }
         let n = self.find(val);
-        self.get(n).is(val)
+        self.compare(self.get(n).get_value(), val) == Ordering::Equal
     }
 
     fn insert(&mut self, val: T) {
-        if let Some(_root) = self.get_root() {
+        self.insert_and_find(val);
+    }
+
+    /// inserts the value and returns the index of the node holding it. If the
+    /// value was already in the tree the existing node is returned instead
+    fn insert_and_find(&mut self, val: T) -> usize {
+        let res = if let Some(_root) = self.get_root() {
             let n = self.find(&val);
-            let node = self.get(n);
-            if node.is(&val) {
+            let ord = self.compare(self.get(n).get_value(), &val);
+            if ord == Ordering::Equal {
                 // value already in tree
                 self.crement_size(-1);
+                n
             } else {
-                let side = if node.lesser(&val) {
+                let side = if ord == Ordering::Less {
                     Side::Right
                 } else {
                     Side::Left
                 };
                 let node = self.create_node(val);
                 self.attach_child(n, node, side);
+                self.add_size_to_path(n, 1);
+                self.emit(Event::Inserted(node));
                 self.rebalance_ins(node);
+                node
             }
         } else {
-            let r = Some(self.create_node(val));
-            self.set_root(r);
-        }
+            let r = self.create_node(val);
+            self.set_root(Some(r));
+            self.emit(Event::Inserted(r));
+            r
+        };
         self.crement_size(1);
+        res
     }
 
     fn delete(&mut self, val: T) -> bool {
@@ -65,14 +392,33 @@ pub trait Tree<T: std::fmt::Debug>: BaseTree<T> {
             false
         } else {
             let n = self.find(&val);
-            let del = self.delete_replace(n);
-            self.rebalance_del(del, n);
-            self.delete_node(del);
-            self.crement_size(-1);
+            self.delete_at(n);
             true
         }
     }
 
+    /// removes the node at the given index from the tree and returns the
+    /// value it held
+    fn delete_at(&mut self, n: usize) -> T {
+        self.emit(Event::Deleted(n));
+        let del = self.delete_replace(n);
+        // del still hangs in the tree while it is rebalanced, so its own size
+        // drops too and any rotation above it counts one node fewer
+        self.add_size_to_path(del, -1);
+        self.rebalance_del(del, n);
+        // del is out of the tree but still points at its last parent, and
+        // every summary from there up still counts the value it held
+        let mut next = self.get(del).get_parent();
+        while let Some(p) = next {
+            self.update_summary(p);
+            next = self.get(p).get_parent();
+        }
+        // after delete_replace, del holds the value that was at n
+        let val = self.delete_node(del);
+        self.crement_size(-1);
+        val
+    }
+
     fn rotate(&mut self, side: Side, n: usize) {
         let p = self.get(n).get_parent().expect("P rotate");
 
@@ -86,7 +432,7 @@ pub trait Tree<T: std::fmt::Debug>: BaseTree<T> {
         }
         if let Some(g) = self.get(p).get_parent() {
             self.get_mut(n).set_parent(Some(g));
-            let pside = if self.get(p).is_child(Side::Left) {
+            let pside = if self.get(p).is_child(Side::Left, self.get_data()) {
                 Side::Left
             } else {
                 Side::Right
@@ -97,25 +443,305 @@ pub trait Tree<T: std::fmt::Debug>: BaseTree<T> {
             self.get_mut(n).set_parent(None);
         }
         self.attach_child(n, p, side);
+        self.update_size(p);
+        self.update_size(n);
+        self.emit(Event::Rotated(side, n));
     }
 
     fn find(&self, val: &T) -> usize {
         let mut n = self.get_root().expect("n find");
         loop {
             let node = self.get(n);
-            if node.lesser(val) && node.get_child(Side::Right).is_some() {
-                n = node.get_child(Side::Right).expect("find n right child");
-            } else if node.greater(val) && node.get_child(Side::Left).is_some() {
-                n = node.get_child(Side::Left).expect("find n left child");
+            let next = match self.compare(node.get_value(), val) {
+                Ordering::Less => node.get_child(Side::Right),
+                Ordering::Greater => node.get_child(Side::Left),
+                Ordering::Equal => None,
+            };
+            match next {
+                Some(c) => n = c,
+                None => return n,
+            }
+        }
+    }
+
+    /// iterate over the values of the tree in sorted order
+    fn iter(&self) -> Iter<'_, T, Self>
+    where
+        Self: Sized,
+    {
+        match self.get_root() {
+            Some(root) => Iter::new(
+                self,
+                Some(self.get(root).find_min(self.get_data())),
+                Some(self.get(root).find_max(self.get_data())),
+            ),
+            None => Iter::new(self, None, None),
+        }
+    }
+
+    /// iterate in sorted order over the values of the tree that fall inside of
+    /// the given range
+    /// usage: tree.range(3..10), tree.range(..=7)
+    fn range<R: RangeBounds<T>>(&self, range: R) -> Iter<'_, T, Self>
+    where
+        Self: Sized,
+    {
+        let first = self.lower_bound(range.start_bound());
+        let last = self.upper_bound(range.end_bound());
+        match (first, last) {
+            (Some(f), Some(l))
+                if self.compare(self.get(f).get_value(), self.get(l).get_value())
+                    != Ordering::Greater =>
+            {
+                Iter::new(self, Some(f), Some(l))
+            }
+            _ => Iter::new(self, None, None),
+        }
+    }
+
+    /// the smallest node that is still inside of the bound
+    fn lower_bound(&self, bound: Bound<&T>) -> Option<usize> {
+        let mut n = self.get_root();
+        let mut found = None;
+        while let Some(i) = n {
+            let node = self.get(i);
+            let inside = match bound {
+                Bound::Included(v) => self.compare(node.get_value(), v) != Ordering::Less,
+                Bound::Excluded(v) => self.compare(node.get_value(), v) == Ordering::Greater,
+                Bound::Unbounded => true,
+            };
+            if inside {
+                found = Some(i);
+                n = node.get_child(Side::Left);
             } else {
-                return n;
+                n = node.get_child(Side::Right);
             }
         }
+        found
+    }
+
+    /// the largest node that is still inside of the bound
+    fn upper_bound(&self, bound: Bound<&T>) -> Option<usize> {
+        let mut n = self.get_root();
+        let mut found = None;
+        while let Some(i) = n {
+            let node = self.get(i);
+            let inside = match bound {
+                Bound::Included(v) => self.compare(node.get_value(), v) != Ordering::Greater,
+                Bound::Excluded(v) => self.compare(node.get_value(), v) == Ordering::Less,
+                Bound::Unbounded => true,
+            };
+            if inside {
+                found = Some(i);
+                n = node.get_child(Side::Right);
+            } else {
+                n = node.get_child(Side::Left);
+            }
+        }
+        found
+    }
+
+    /// the smallest value in the tree
+    fn first(&self) -> Option<&T> {
+        self.lower_bound(Bound::Unbounded).map(|n| self.get(n).get_value())
+    }
+
+    /// the largest value in the tree
+    fn last(&self) -> Option<&T> {
+        self.upper_bound(Bound::Unbounded).map(|n| self.get(n).get_value())
+    }
+
+    /// the largest value no greater than val
+    fn floor(&self, val: &T) -> Option<&T> {
+        self.upper_bound(Bound::Included(val)).map(|n| self.get(n).get_value())
+    }
+
+    /// the smallest value no less than val
+    fn ceiling(&self, val: &T) -> Option<&T> {
+        self.lower_bound(Bound::Included(val)).map(|n| self.get(n).get_value())
+    }
+
+    /// the largest value strictly below val, whether or not val is in the tree
+    fn predecessor(&self, val: &T) -> Option<&T> {
+        self.upper_bound(Bound::Excluded(val)).map(|n| self.get(n).get_value())
+    }
+
+    /// the smallest value strictly above val, whether or not val is in the tree
+    /// usage: tree.successor(&now) for the next deadline after now
+    fn successor(&self, val: &T) -> Option<&T> {
+        self.lower_bound(Bound::Excluded(val)).map(|n| self.get(n).get_value())
+    }
+
+    /// removes and returns the smallest value
+    fn pop_first(&mut self) -> Option<T> {
+        let n = self.lower_bound(Bound::Unbounded)?;
+        Some(self.delete_at(n))
+    }
+
+    /// removes and returns the largest value
+    fn pop_last(&mut self) -> Option<T> {
+        let n = self.upper_bound(Bound::Unbounded)?;
+        Some(self.delete_at(n))
+    }
+
+    /// finds the node holding a value that compares equal, where cmp says how
+    /// a value in the tree is ordered relative to the one being looked for
+    fn find_by<F: Fn(&T) -> Ordering>(&self, cmp: F) -> Option<usize> {
+        let mut n = self.get_root();
+        while let Some(i) = n {
+            let node = self.get(i);
+            n = match cmp(node.get_value()) {
+                Ordering::Less => node.get_child(Side::Right),
+                Ordering::Greater => node.get_child(Side::Left),
+                Ordering::Equal => return Some(i),
+            };
+        }
+        None
+    }
+
+    /// the number of values in the tree that are smaller than val
+    fn rank(&self, val: &T) -> usize {
+        self.count_below(val, false)
+    }
+
+    /// counts the values smaller than val, or no greater than it when
+    /// inclusive is set
+    fn count_below(&self, val: &T, inclusive: bool) -> usize {
+        let data = self.get_data();
+        let size = |c: Option<usize>| c.map(|c| data[c].subtree_size()).unwrap_or(0);
+        let mut n = self.get_root();
+        let mut count = 0;
+        while let Some(i) = n {
+            let node = self.get(i);
+            let ord = self.compare(node.get_value(), val);
+            if ord == Ordering::Less || (inclusive && ord == Ordering::Equal) {
+                count += size(node.get_child(Side::Left)) + 1;
+                n = node.get_child(Side::Right);
+            } else {
+                n = node.get_child(Side::Left);
+            }
+        }
+        count
+    }
+
+    /// the k-th smallest value in the tree, counting from 0, so that
+    /// select(rank(v)) finds v again
+    fn select(&self, k: usize) -> Option<&T> {
+        let data = self.get_data();
+        let size = |c: Option<usize>| c.map(|c| data[c].subtree_size()).unwrap_or(0);
+        let mut n = self.get_root();
+        let mut k = k;
+        while let Some(i) = n {
+            let node = self.get(i);
+            let left = size(node.get_child(Side::Left));
+            n = match k.cmp(&left) {
+                Ordering::Less => node.get_child(Side::Left),
+                Ordering::Equal => return Some(node.get_value()),
+                Ordering::Greater => {
+                    k -= left + 1;
+                    node.get_child(Side::Right)
+                }
+            };
+        }
+        None
+    }
+
+    /// the number of values inside of the range, without visiting them
+    /// usage: tree.count_range(10..20)
+    fn count_range<R: RangeBounds<T>>(&self, range: R) -> usize {
+        let below_start = match range.start_bound() {
+            Bound::Included(v) => self.count_below(v, false),
+            Bound::Excluded(v) => self.count_below(v, true),
+            Bound::Unbounded => 0,
+        };
+        let below_end = match range.end_bound() {
+            Bound::Included(v) => self.count_below(v, true),
+            Bound::Excluded(v) => self.count_below(v, false),
+            Bound::Unbounded => self.get_size(),
+        };
+        below_end.saturating_sub(below_start)
+    }
+
+    /**
+     * Splits the tree into the values below val and the values from val up.
+     * This default flattens the tree and rebuilds both halves from sorted
+     * order, which is O(n) however lopsided the split is. RBTree and AVLTree
+     * override it with a split built on Join: O(log n) inside the arena, plus
+     * moving the smaller half into an arena of its own
+     */
+    fn split(self, val: &T) -> (Self, Self)
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        let cmp = self.comparator().clone();
+        let (below, rest): (Vec<T>, Vec<T>) = self
+            .into_iter()
+            .partition(|v| cmp.compare(v, val) == Ordering::Less);
+        (
+            Self::from_sorted_iter_by(below, cmp.clone()),
+            Self::from_sorted_iter_by(rest, cmp),
+        )
+    }
+
+    /// joins two trees where every value of self is below every value of
+    /// other; this default rebuilds in O(n + m), RBTree and AVLTree join in
+    /// O(log n) after moving the smaller tree into the larger one's arena
+    fn join(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        if let (Some(max), Some(min)) = (self.last(), other.first()) {
+            assert!(
+                self.compare(max, min) == Ordering::Less,
+                "join needs every value of the left tree below the right tree"
+            );
+        }
+        let cmp = self.comparator().clone();
+        Self::from_sorted_iter_by(self.into_iter().chain(other), cmp)
+    }
+
+    /// the values found in either tree
+    /// this default merges both trees in O(n + m); RBTree and AVLTree take
+    /// O(m log(n / m + 1)) for m <= n by splitting and joining instead
+    fn union(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        let cmp = self.comparator().clone();
+        let merged = merge_sorted(self, other, (true, true, true), &cmp);
+        Self::from_sorted_iter_by(merged, cmp)
+    }
+
+    /// the values found in both trees, in O(n + m) unless overridden like
+    /// union
+    fn intersection(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        let cmp = self.comparator().clone();
+        let merged = merge_sorted(self, other, (false, true, false), &cmp);
+        Self::from_sorted_iter_by(merged, cmp)
+    }
+
+    /// the values of self that are not in other, in O(n + m) unless
+    /// overridden like union
+    fn difference(self, other: Self) -> Self
+    where
+        Self: Sized + IntoIterator<Item = T>,
+        Self::Cmp: Clone,
+    {
+        let cmp = self.comparator().clone();
+        let merged = merge_sorted(self, other, (true, false, false), &cmp);
+        Self::from_sorted_iter_by(merged, cmp)
     }
 
     fn get_height(&self) -> usize {
         if let Some(root) = self.get_root() {
-            self.get(root).get_height()
+            self.get(root).get_height(self.get_data())
         } else {
             0
         }
@@ -123,25 +749,350 @@ pub trait Tree<T: std::fmt::Debug>: BaseTree<T> {
 
     fn get_leaf_count(&self) -> usize {
         if let Some(root) = self.get_root() {
-            self.get(root).get_leaf_count()
+            self.get(root).get_leaf_count(self.get_data())
         } else {
             0
         }
     }
 
-    fn to_string(&self) -> String {
+    /// node count, height, depths and balancing data in one walk over the
+    /// tree
+    fn stats(&self) -> TreeStats
+    where
+        Self: Sized,
+    {
+        TreeStats::of(self)
+    }
+
+    fn to_string(&self) -> String
+    where
+        T: fmt::Debug,
+    {
         if let Some(root) = self.get_root() {
-            self.get(root).to_string()
+            self.get(root).to_string(self.get_data())
         } else {
             String::from("(Empty tree)")
         }
     }
 
-    fn to_pretty_string(&self) -> String {
+    /// the tree as a Graphviz graph
+    /// usage: dot -Tpng tree.dot -o tree.png
+    fn to_dot(&self) -> String
+    where
+        Self: Sized,
+        T: fmt::Debug,
+    {
+        treeexport::to_dot(self)
+    }
+
+    /// a standalone SVG drawing of the tree, for trees too big for a terminal
+    fn to_svg(&self) -> String
+    where
+        Self: Sized,
+        T: fmt::Debug,
+    {
+        treeexport::to_svg(self)
+    }
+
+    fn to_pretty_string(&self) -> String
+    where
+        T: fmt::Debug,
+    {
         if let Some(root) = self.get_root() {
-            self.get(root).to_pretty_string(1)
+            self.get(root).to_pretty_string(1, self.get_data())
         } else {
             String::from("(Empty tree)")
         }
     }
 }
+
+/**
+ * In order iterator over a tree. Keeps a cursor on each end of the
+ * iteration and walks them towards each other over the parent/child links,
+ * so iterating is done in place without copying anything out of the arena
+ */
+pub struct Iter<'a, T, B: BaseTree<T>> {
+    tree: &'a B,
+    front: Option<usize>,
+    back: Option<usize>,
+    phantom: PhantomData<&'a T>,
+}
+
+impl<'a, T, B: BaseTree<T>> Iter<'a, T, B> {
+    fn new(tree: &'a B, front: Option<usize>, back: Option<usize>) -> Self {
+        Self {
+            tree,
+            front,
+            back,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: 'a, B: BaseTree<T>> Iterator for Iter<'a, T, B> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.front?;
+        let node = self.tree.get(n);
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.front = node.get_successor(self.tree.get_data());
+        }
+        Some(node.get_value())
+    }
+}
+
+impl<'a, T: 'a, B: BaseTree<T>> DoubleEndedIterator for Iter<'a, T, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let n = self.back?;
+        let node = self.tree.get(n);
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.back = node.get_predecessor(self.tree.get_data());
+        }
+        Some(node.get_value())
+    }
+}
+
+/**
+ * Owning in order iterator over a tree, produced by calling into_iter on the
+ * tree itself
+ */
+pub struct IntoIter<T> {
+    values: std::vec::IntoIter<T>,
+}
+
+impl<T> IntoIter<T> {
+    /// takes the values out of an arena given the order to visit it in
+    pub(crate) fn new<N: Node<T>>(mut data: Vec<N>, order: Vec<usize>) -> Self {
+        // freed slots hold no value, so only the visited nodes are taken from
+        let values: Vec<T> = order.into_iter().map(|i| data[i].take_value()).collect();
+        Self {
+            values: values.into_iter(),
+        }
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.values.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.values.next_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+/// the arena indices of every node reachable from the root, in sorted order
+pub(crate) fn in_order<T, B: BaseTree<T>>(tree: &B) -> Vec<usize> {
+    let mut order = Vec::with_capacity(tree.get_size());
+    let mut n = tree.get_root().map(|r| tree.get(r).find_min(tree.get_data()));
+    while let Some(i) = n {
+        order.push(i);
+        n = tree.get(i).get_successor(tree.get_data());
+    }
+    order
+}
+
+/// trades the values held by two nodes, leaving the shape of the tree alone
+pub(crate) fn swap_values<T, N: Node<T>>(data: &mut [N], a: usize, b: usize) {
+    let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+    let (left, right) = data.split_at_mut(hi);
+    std::mem::swap(left[lo].get_value_mut(), right[0].get_value_mut());
+}
+
+/**
+ * Rebuilds the arena with only the nodes reachable from the root, numbered in
+ * breadth first order so the top of the tree sits together at the front.
+ * Every link is renumbered to match, and the root is updated in place
+ */
+pub(crate) fn compact<T, N: Node<T>>(data: &mut Vec<N>, root: &mut Option<usize>) {
+    let mut order = Vec::new();
+    if let Some(r) = *root {
+        order.push(r);
+    }
+    let mut i = 0;
+    while i < order.len() {
+        let node = &data[order[i]];
+        order.extend(node.get_child(Side::Left));
+        order.extend(node.get_child(Side::Right));
+        i += 1;
+    }
+
+    let mut new_index = vec![0; data.len()];
+    for (new, &old) in order.iter().enumerate() {
+        new_index[old] = new;
+    }
+    let mut slots: Vec<Option<N>> = std::mem::take(data).into_iter().map(Some).collect();
+    for &old in &order {
+        let mut node = slots[old].take().expect("node reachable twice");
+        node.set_location(new_index[old]);
+        node.set_parent(node.get_parent().map(|p| new_index[p]));
+        for &side in &[Side::Left, Side::Right] {
+            node.set_child_opt(node.get_child(side).map(|c| new_index[c]), side);
+        }
+        data.push(node);
+    }
+    data.shrink_to_fit();
+    *root = root.map(|r| new_index[r]);
+}
+
+/**
+ * Walks two ascending sequences side by side. keep says whether to keep the
+ * values found only in a, the ones in both, and the ones only in b
+ */
+fn merge_sorted<T, C: Comparator<T>>(
+    a: impl IntoIterator<Item = T>,
+    b: impl IntoIterator<Item = T>,
+    keep: (bool, bool, bool),
+    cmp: &C,
+) -> Vec<T> {
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    let mut out = Vec::new();
+    loop {
+        let ord = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) => cmp.compare(x, y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return out,
+        };
+        match ord {
+            Ordering::Less => out.extend(a.next().filter(|_| keep.0)),
+            Ordering::Equal => {
+                b.next();
+                out.extend(a.next().filter(|_| keep.1));
+            }
+            Ordering::Greater => out.extend(b.next().filter(|_| keep.2)),
+        }
+    }
+}
+
+/// collects ascending values into an arena of unlinked nodes, each one at its
+/// own index, dropping any value equal to the one before it
+pub(crate) fn sorted_arena<T, N: Node<T>>(
+    iter: impl IntoIterator<Item = T>,
+    cmp: &impl Comparator<T>,
+    make: impl Fn(T, usize) -> N,
+) -> Vec<N> {
+    let mut data: Vec<N> = Vec::new();
+    for val in iter {
+        if let Some(last) = data.last() {
+            match cmp.compare(last.get_value(), &val) {
+                Ordering::Equal => continue,
+                Ordering::Greater => panic!("from_sorted_iter needs ascending values"),
+                Ordering::Less => (),
+            }
+        }
+        let loc = data.len();
+        data.push(make(val, loc));
+    }
+    data
+}
+
+/**
+ * Links a sorted arena into a perfectly balanced tree by making the middle of
+ * every slice the parent of its two halves, and returns the root. finish is
+ * handed each node and its depth once both of its subtrees are linked, so the
+ * tree can fill in its own balancing data
+ */
+pub(crate) fn link_balanced<T, N, F>(data: &mut [N], finish: &mut F) -> Option<usize>
+where
+    N: Node<T>,
+    F: FnMut(&mut [N], usize, usize),
+{
+    fn link<T, N, F>(
+        data: &mut [N],
+        lo: usize,
+        hi: usize,
+        depth: usize,
+        finish: &mut F,
+    ) -> Option<usize>
+    where
+        N: Node<T>,
+        F: FnMut(&mut [N], usize, usize),
+    {
+        if lo >= hi {
+            return None;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let left = link(data, lo, mid, depth + 1, finish);
+        let right = link(data, mid + 1, hi, depth + 1, finish);
+        for &(side, child) in &[(Side::Left, left), (Side::Right, right)] {
+            data[mid].set_child_opt(child, side);
+            if let Some(c) = child {
+                data[c].set_parent(Some(mid));
+            }
+        }
+        data[mid].set_subtree_size(hi - lo);
+        finish(data, mid, depth);
+        Some(mid)
+    }
+    let len = data.len();
+    link(data, 0, len, 0, finish)
+}
+
+/**
+ * The checks shared by every tree: each child points back at its parent, an
+ * in order walk is strictly increasing, and each stored subtree size is right.
+ * Only child links are followed, so broken parent links can't send it astray
+ */
+pub(crate) fn validate_links<T, B: BaseTree<T>>(tree: &B) -> Result<(), InvariantError> {
+    fn check<T, B: BaseTree<T>>(
+        tree: &B,
+        n: usize,
+        parent: Option<usize>,
+        prev: &mut Option<usize>,
+    ) -> Result<usize, InvariantError> {
+        let node = tree.get(n);
+        if node.get_parent() != parent {
+            return Err(InvariantError::ParentLinkBroken {
+                node: n,
+                expected: parent,
+                found: node.get_parent(),
+            });
+        }
+        let left = match node.get_child(Side::Left) {
+            Some(c) => check(tree, c, Some(n), prev)?,
+            None => 0,
+        };
+        if let Some(p) = *prev {
+            if tree.compare(tree.get(p).get_value(), node.get_value()) != Ordering::Less {
+                return Err(InvariantError::OrderViolation { node: p, next: n });
+            }
+        }
+        *prev = Some(n);
+        let right = match node.get_child(Side::Right) {
+            Some(c) => check(tree, c, Some(n), prev)?,
+            None => 0,
+        };
+        let actual = left + right + 1;
+        if node.subtree_size() != actual {
+            return Err(InvariantError::SizeWrong {
+                node: n,
+                stored: node.subtree_size(),
+                actual,
+            });
+        }
+        Ok(actual)
+    }
+    match tree.get_root() {
+        Some(r) => check(tree, r, None, &mut None).map(|_| ()),
+        None => Ok(()),
+    }
+}
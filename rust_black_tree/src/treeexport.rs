@@ -0,0 +1,223 @@
+use std::cmp::max;
+use std::fmt::Debug;
+
+use super::node::{Node, NodeStyle, Side};
+use super::tree::{in_order, BaseTree};
+
+/**
+ * Writes the tree as a Graphviz digraph. Each node carries its fill colour
+ * and balancing data as attributes. A node with a single child gets an
+ * invisible placeholder on its empty side, so dot keeps left children on the
+ * left
+ */
+pub fn to_dot<T: Debug, B: BaseTree<T>>(tree: &B) -> String {
+    let data = tree.get_data();
+    let mut out = String::from("digraph tree {\n    node [shape=circle, style=filled];\n");
+    let mut stack: Vec<usize> = tree.get_root().into_iter().collect();
+    while let Some(n) = stack.pop() {
+        let node = &data[n];
+        let style = node.export_style(data);
+        out += &format!(
+            "    n{} [label=\"{}\", fillcolor=\"{}\", fontcolor=\"{}\"",
+            n,
+            dot_escape(&style.label),
+            style.fill,
+            style.font
+        );
+        for (key, val) in &style.attributes {
+            out += &format!(", {}=\"{}\"", key, dot_escape(val));
+        }
+        if !style.attributes.is_empty() {
+            out += &format!(", xlabel=\"{}\"", dot_escape(&notes(&style)));
+        }
+        out += "];\n";
+
+        let children = [node.get_child(Side::Left), node.get_child(Side::Right)];
+        if children.iter().any(Option::is_some) {
+            for (child, tag) in children.iter().zip(&["l", "r"]) {
+                match child {
+                    Some(c) => out += &format!("    n{} -> n{};\n", n, c),
+                    None => {
+                        out += &format!("    nil{}{} [shape=point, style=invis];\n", n, tag);
+                        out += &format!("    n{} -> nil{}{} [style=invis];\n", n, n, tag);
+                    }
+                }
+            }
+        }
+        // pushed right first so the left subtree is written out first
+        stack.extend(children.iter().rev().flatten());
+    }
+    out + "}\n"
+}
+
+/**
+ * Draws the tree as a standalone SVG document. Every node gets its own
+ * column in sorted order and its own row by depth, so nothing overlaps no
+ * matter how wide the tree grows
+ */
+pub fn to_svg<T: Debug, B: BaseTree<T>>(tree: &B) -> String {
+    const LEVEL: usize = 60;
+    const PAD: usize = 20;
+    const RY: usize = 14;
+    let data = tree.get_data();
+    let order = in_order(tree);
+    if order.is_empty() {
+        return String::from(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"120\" height=\"40\">\
+             <text x=\"10\" y=\"25\" font-family=\"monospace\">(Empty tree)</text></svg>\n",
+        );
+    }
+
+    // (column, row) of every node, indexed by its place in the arena
+    let mut pos = vec![(0, 0); data.len()];
+    for (col, &n) in order.iter().enumerate() {
+        pos[n].0 = col;
+    }
+    let mut rows = 0;
+    let mut stack: Vec<(usize, usize)> = tree.get_root().map(|r| (r, 0)).into_iter().collect();
+    while let Some((n, row)) = stack.pop() {
+        pos[n].1 = row;
+        rows = max(rows, row + 1);
+        for c in [Side::Left, Side::Right].iter().filter_map(|s| data[n].get_child(*s)) {
+            stack.push((c, row + 1));
+        }
+    }
+
+    let styles: Vec<NodeStyle> = order.iter().map(|&n| data[n].export_style(data)).collect();
+    let label_len = styles.iter().map(|s| s.label.chars().count()).max().unwrap_or(0);
+    let notes_len = styles.iter().map(|s| notes(s).chars().count()).max().unwrap_or(0);
+    let rx = max(RY, label_len * 4 + 6);
+    let step = max(2 * rx, notes_len * 6) + 10;
+    let x = |n: usize| PAD + step * pos[n].0 + step / 2;
+    let y = |n: usize| PAD + RY + LEVEL * pos[n].1;
+
+    let width = step * order.len() + 2 * PAD;
+    let height = LEVEL * (rows - 1) + 2 * (PAD + RY) + 12;
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"monospace\">\n",
+        w = width,
+        h = height
+    );
+
+    out += "<g stroke=\"#555\" stroke-width=\"1.5\">\n";
+    for &n in &order {
+        if let Some(p) = data[n].get_parent() {
+            out += &format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>\n",
+                x(p),
+                y(p),
+                x(n),
+                y(n)
+            );
+        }
+    }
+    out += "</g>\n";
+
+    for (&n, style) in order.iter().zip(&styles) {
+        let notes = xml_escape(&notes(style));
+        out += &format!(
+            "<g><title>{}</title>\
+             <ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" fill=\"{}\" stroke=\"black\"/>\
+             <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"12\" fill=\"{}\">{}</text>",
+            notes,
+            x(n),
+            y(n),
+            rx,
+            RY,
+            style.fill,
+            x(n),
+            y(n) + 4,
+            style.font,
+            xml_escape(&style.label)
+        );
+        if !notes.is_empty() {
+            out += &format!(
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"9\">{}</text>",
+                x(n),
+                y(n) + RY + 11,
+                notes
+            );
+        }
+        out += "</g>\n";
+    }
+    out + "</svg>\n"
+}
+
+// the attributes of a node in key=value form, as shown beside it
+fn notes(style: &NodeStyle) -> String {
+    style
+        .attributes
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::avltree::AVLTree;
+    use super::super::rbtree::RBTree;
+    use super::super::tree::Tree;
+    use super::super::unbalancetree::BSTree;
+
+    #[test]
+    fn dot_rb() {
+        let mut t = RBTree::new();
+        for x in &[2, 1, 3, 4] {
+            t.insert(*x);
+        }
+        let dot = t.to_dot();
+        assert!(dot.starts_with("digraph tree {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("[label=\"2\", fillcolor=\"black\", fontcolor=\"white\"];"));
+        assert!(dot.contains("[label=\"4\", fillcolor=\"red\", fontcolor=\"white\"];"));
+        let edges = dot.lines().filter(|l| l.contains(" -> n") && !l.contains("nil"));
+        assert_eq!(edges.count(), 3);
+        // 3 only has a right child, so its left side is held by a placeholder
+        assert_eq!(dot.matches("style=invis];").count(), 2);
+    }
+
+    #[test]
+    fn dot_attributes() {
+        let t = AVLTree::from_sorted_iter(0..3);
+        let dot = t.to_dot();
+        assert!(dot.contains("bf=\"0\", height=\"2\", xlabel=\"bf=0 height=2\""));
+
+        let mut t = BSTree::new();
+        t.insert(String::from("a\"b"));
+        t.insert(String::from("z"));
+        let dot = t.to_dot();
+        assert!(dot.contains("label=\"\\\"a\\\\\\\"b\\\"\""));
+        assert!(dot.contains("depth=\"1\""));
+    }
+
+    #[test]
+    fn svg() {
+        let t = RBTree::<i32>::new();
+        assert!(t.to_svg().contains("(Empty tree)"));
+
+        let t = AVLTree::from_sorted_iter(0..100);
+        let svg = t.to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<ellipse").count(), 100);
+        assert_eq!(svg.matches("<line").count(), 99);
+        assert!(svg.contains("<title>bf=0 height=1</title>"));
+
+        let mut t = BSTree::new();
+        t.insert("<&>");
+        assert!(t.to_svg().contains(">&quot;&lt;&amp;&gt;&quot;</text>"));
+    }
+}
@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+
+use super::avltree::AVLTree;
+use super::rbtree::RBTree;
+use super::tree::Tree;
+
+use super::node::Node;
+
+/// A key/value map backed by a red black tree
+pub type RBTreeMap<K, V> = TreeMap<K, V, RBTree<MapEntry<K, V>>>;
+
+/// A key/value map backed by an AVL tree
+pub type AVLTreeMap<K, V> = TreeMap<K, V, AVLTree<MapEntry<K, V>>>;
+
+/**
+ * The value stored in each node of a map. Entries are ordered by their key
+ * alone, so the value just rides along in the node
+ */
+pub struct MapEntry<K, V> {
+    pub key: K,
+    value: V,
+}
+
+impl<K, V> MapEntry<K, V> {
+    fn new(key: K, value: V) -> Self {
+        Self { key, value }
+    }
+
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    pub fn value_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+}
+
+impl<K: Ord, V> PartialEq for MapEntry<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K: Ord, V> PartialOrd for MapEntry<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.key.cmp(&other.key))
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for MapEntry<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {:?}", self.key, self.value)
+    }
+}
+
+/**
+ * Ordered map on top of any of the trees. Lookups compare keys through K: Ord,
+ * and the underlying tree stays available through tree() so the shape of the
+ * map can be inspected or printed
+ */
+pub struct TreeMap<K, V, B> {
+    tree: B,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, B> TreeMap<K, V, B>
+where
+    K: Ord,
+    B: Tree<MapEntry<K, V>>,
+{
+    pub fn new() -> Self
+    where
+        B::Cmp: Default,
+    {
+        Self {
+            tree: B::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// the tree the map is stored in
+    pub fn tree(&self) -> &B {
+        &self.tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.get_size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn find_key(&self, key: &K) -> Option<usize> {
+        self.tree.find_by(|e| e.key.cmp(key))
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find_key(key).is_some()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find_key(key)
+            .map(|n| self.tree.get(n).get_value().value())
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        match self.find_key(key) {
+            Some(n) => Some(self.tree.get_mut(n).get_value_mut().value_mut()),
+            None => None,
+        }
+    }
+
+    /// inserts a value under the key, returning the value that used to be
+    /// there if the key was already in the map
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut e) => Some(e.insert(value)),
+            Entry::Vacant(e) => {
+                e.insert(value);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.find_key(key).map(|n| self.remove_at(n))
+    }
+
+    fn remove_at(&mut self, n: usize) -> V {
+        self.tree.delete_at(n).value
+    }
+
+    /// gets the entry for a key so it can be inspected or filled in place
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, B> {
+        match self.find_key(&key) {
+            Some(index) => Entry::Occupied(OccupiedEntry { map: self, index }),
+            None => Entry::Vacant(VacantEntry { map: self, key }),
+        }
+    }
+
+    /// iterates over the key/value pairs in order of their keys
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+        self.tree.iter().map(|e| (&e.key, e.value()))
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> {
+        self.tree.iter().map(|e| &e.key)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> {
+        self.tree.iter().map(|e| e.value())
+    }
+}
+
+impl<K, V, B> Default for TreeMap<K, V, B>
+where
+    K: Ord,
+    B: Tree<MapEntry<K, V>>,
+    B::Cmp: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, B> FromIterator<(K, V)> for TreeMap<K, V, B>
+where
+    K: Ord,
+    B: Tree<MapEntry<K, V>>,
+    B::Cmp: Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+/// A view into a single key of a map, which may or may not hold a value
+pub enum Entry<'a, K, V, B> {
+    Vacant(VacantEntry<'a, K, V, B>),
+    Occupied(OccupiedEntry<'a, K, V, B>),
+}
+
+pub struct VacantEntry<'a, K, V, B> {
+    map: &'a mut TreeMap<K, V, B>,
+    key: K,
+}
+
+pub struct OccupiedEntry<'a, K, V, B> {
+    map: &'a mut TreeMap<K, V, B>,
+    index: usize,
+}
+
+impl<'a, K, V, B> Entry<'a, K, V, B>
+where
+    K: Ord,
+    B: Tree<MapEntry<K, V>>,
+{
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(e) => e.key(),
+            Entry::Occupied(e) => e.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Vacant(e) => e.insert(default()),
+            Entry::Occupied(e) => e.into_mut(),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(e.get_mut());
+                Entry::Occupied(e)
+            }
+            vacant => vacant,
+        }
+    }
+}
+
+impl<'a, K, V, B> VacantEntry<'a, K, V, B>
+where
+    K: Ord,
+    B: Tree<MapEntry<K, V>>,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        let n = self
+            .map
+            .tree
+            .insert_and_find(MapEntry::new(self.key, value));
+        self.map.tree.get_mut(n).get_value_mut().value_mut()
+    }
+}
+
+impl<'a, K, V, B> OccupiedEntry<'a, K, V, B>
+where
+    K: Ord,
+    B: Tree<MapEntry<K, V>>,
+{
+    pub fn key(&self) -> &K {
+        &self.map.tree.get(self.index).get_value().key
+    }
+
+    pub fn get(&self) -> &V {
+        self.map.tree.get(self.index).get_value().value()
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.map
+            .tree
+            .get_mut(self.index)
+            .get_value_mut()
+            .value_mut()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.map
+            .tree
+            .get_mut(self.index)
+            .get_value_mut()
+            .value_mut()
+    }
+
+    /// replaces the value of the entry, returning the old one
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.map.remove_at(self.index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_get() {
+        let mut map = RBTreeMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert(3, "three"), None);
+        assert_eq!(map.insert(1, "one"), None);
+        assert_eq!(map.insert(2, "two"), None);
+        assert_eq!(map.insert(2, "deux"), Some("two"));
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.get(&1), Some(&"one"));
+        assert_eq!(map.get(&2), Some(&"deux"));
+        assert_eq!(map.get(&4), None);
+        assert!(map.contains_key(&3));
+        assert!(!map.contains_key(&0));
+
+        if let Some(v) = map.get_mut(&3) {
+            *v = "trois";
+        }
+        let pairs: Vec<(&i32, &&str)> = map.iter().collect();
+        assert_eq!(pairs, vec![(&1, &"one"), (&2, &"deux"), (&3, &"trois")]);
+    }
+
+    #[test]
+    fn remove() {
+        let mut map: AVLTreeMap<i32, String> = (0..20).map(|x| (x, x.to_string())).collect();
+        assert_eq!(map.len(), 20);
+        assert_eq!(map.remove(&7), Some(String::from("7")));
+        assert_eq!(map.remove(&7), None);
+        assert_eq!(map.remove(&0), Some(String::from("0")));
+        assert_eq!(map.len(), 18);
+        assert_eq!(map.get(&8), Some(&String::from("8")));
+        let keys: Vec<i32> = map.keys().cloned().collect();
+        assert_eq!(keys, (1..20).filter(|x| *x != 7).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn entry() {
+        let mut map = RBTreeMap::new();
+        for word in "the quick brown fox jumps over the lazy dog the end".split(' ') {
+            *map.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(map.get(&"the"), Some(&3));
+        assert_eq!(map.get(&"fox"), Some(&1));
+
+        map.entry("fox").and_modify(|v| *v += 10).or_insert(0);
+        map.entry("cat").and_modify(|v| *v += 10).or_insert(5);
+        assert_eq!(map.get(&"fox"), Some(&11));
+        assert_eq!(map.get(&"cat"), Some(&5));
+
+        match map.entry("dog") {
+            Entry::Occupied(e) => {
+                assert_eq!(e.key(), &"dog");
+                assert_eq!(e.remove(), 1);
+            }
+            Entry::Vacant(_) => panic!("dog should be in the map"),
+        }
+        assert!(!map.contains_key(&"dog"));
+        assert_eq!(*map.entry("zebra").or_default(), 0);
+        let values: Vec<i32> = map.values().cloned().collect();
+        assert_eq!(values.len(), map.len());
+    }
+}
@@ -0,0 +1,74 @@
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+use super::avltree::AVLTree;
+use super::comparator::Comparator;
+use super::rbtree::RBTree;
+use super::tree::{BaseTree, Tree};
+use super::unbalancetree::BSTree;
+
+/**
+ * Trees serialize as the sorted sequence of their values, so what one kind
+ * of tree saves any other kind can load. Sorted input is rebuilt into a
+ * balanced tree in one pass, anything else is inserted a value at a time
+ */
+macro_rules! impl_serde {
+    ($($tree:ident),*) => {$(
+        impl<T, C> Serialize for $tree<T, C>
+        where
+            T: Serialize,
+            C: Comparator<T>,
+        {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut seq = serializer.serialize_seq(Some(self.get_size()))?;
+                for val in self.iter() {
+                    seq.serialize_element(val)?;
+                }
+                seq.end()
+            }
+        }
+
+        impl<'de, T> Deserialize<'de> for $tree<T>
+        where
+            T: Deserialize<'de> + PartialOrd,
+        {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let values = Vec::<T>::deserialize(deserializer)?;
+                if values.windows(2).all(|w| w[0] <= w[1]) {
+                    return Ok(Self::from_sorted_iter(values));
+                }
+                let mut tree = Self::new();
+                for val in values {
+                    tree.insert(val);
+                }
+                Ok(tree)
+            }
+        }
+    )*};
+}
+
+impl_serde!(RBTree, AVLTree, BSTree);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let mut tree = RBTree::new();
+        for x in &[5, 3, 8, 1, 4] {
+            tree.insert(*x);
+        }
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(json, "[1,3,4,5,8]");
+
+        let back: AVLTree<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.validate(), Ok(()));
+        assert_eq!(back.iter().cloned().collect::<Vec<_>>(), vec![1, 3, 4, 5, 8]);
+
+        let unsorted: BSTree<i32> = serde_json::from_str("[3, 1, 2, 1]").unwrap();
+        assert_eq!(unsorted.get_size(), 3);
+        assert_eq!(unsorted.to_string(), "([P:None V:3] ([P:Some(0) V:1] () ([P:Some(1) V:2] () ())) ())");
+        assert!(serde_json::from_str::<RBTree<i32>>("{}").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::node::{Color, Node, Side};
+use super::tree::BaseTree;
+
+/**
+ * A summary of how a tree is shaped, worked out in a single walk over it.
+ * Depths count the links down from the root, so the root is at depth 0 and
+ * height is one more than the deepest depth, as get_height has it
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TreeStats {
+    pub nodes: usize,
+    pub height: usize,
+    pub leaves: usize,
+    /// the depth of the leaf closest to the root
+    pub min_leaf_depth: usize,
+    pub average_depth: f64,
+    /// the depth of the middle node when they are sorted by depth, taking
+    /// the shallower of the two when there is an even number of nodes
+    pub median_depth: usize,
+    /// depth_histogram[d] is the number of nodes at depth d
+    pub depth_histogram: Vec<usize>,
+    /// black nodes on the way from the root down to an empty leaf, counting
+    /// the empty leaf, for trees that color their nodes
+    pub black_height: Option<usize>,
+    /// how many nodes have each balance factor, for trees that keep one
+    pub balance_factors: BTreeMap<isize, usize>,
+}
+
+impl TreeStats {
+    /// the stats of any of the binary trees
+    pub fn of<T, B: BaseTree<T>>(tree: &B) -> Self {
+        let data = tree.get_data();
+        let mut stats = TreeStats::default();
+        // each node along with its depth and the black nodes above it
+        let mut stack: Vec<(usize, usize, usize)> =
+            tree.get_root().map(|r| (r, 0, 0)).into_iter().collect();
+        while let Some((n, depth, blacks)) = stack.pop() {
+            let node = &data[n];
+            let blacks = blacks + (node.get_color() == Some(Color::Black)) as usize;
+            let children = [node.get_child(Side::Left), node.get_child(Side::Right)];
+            if node.get_color().is_some()
+                && children.contains(&None)
+                && stats.black_height.is_none()
+            {
+                stats.black_height = Some(blacks + 1);
+            }
+            if let Some(bf) = node.get_balance_factor() {
+                *stats.balance_factors.entry(bf).or_insert(0) += 1;
+            }
+            stats.record(depth, children == [None, None]);
+            stack.extend(children.iter().flatten().map(|&c| (c, depth + 1, blacks)));
+        }
+        stats.finish()
+    }
+
+    /// counts one node at the given depth
+    pub(crate) fn record(&mut self, depth: usize, leaf: bool) {
+        if self.depth_histogram.len() <= depth {
+            self.depth_histogram.resize(depth + 1, 0);
+        }
+        self.depth_histogram[depth] += 1;
+        if leaf {
+            if self.leaves == 0 || depth < self.min_leaf_depth {
+                self.min_leaf_depth = depth;
+            }
+            self.leaves += 1;
+        }
+    }
+
+    /// works out the totals once every node has been recorded
+    pub(crate) fn finish(mut self) -> Self {
+        self.nodes = self.depth_histogram.iter().sum();
+        self.height = self.depth_histogram.len();
+        if self.nodes == 0 {
+            return self;
+        }
+        let total: usize = self
+            .depth_histogram
+            .iter()
+            .enumerate()
+            .map(|(d, &count)| d * count)
+            .sum();
+        self.average_depth = total as f64 / self.nodes as f64;
+        let middle = (self.nodes - 1) / 2;
+        let mut seen = 0;
+        for (d, &count) in self.depth_histogram.iter().enumerate() {
+            seen += count;
+            if seen > middle {
+                self.median_depth = d;
+                break;
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for TreeStats {
+    // div_ceil would need Rust 1.73
+    #[allow(clippy::manual_div_ceil)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const BAR: usize = 40;
+        writeln!(f, "nodes: {}", self.nodes)?;
+        writeln!(f, "height: {}", self.height)?;
+        if self.nodes == 0 {
+            return Ok(());
+        }
+        writeln!(
+            f,
+            "leaves: {}, the shallowest at depth {}",
+            self.leaves, self.min_leaf_depth
+        )?;
+        writeln!(
+            f,
+            "depth: average {:.2}, median {}",
+            self.average_depth, self.median_depth
+        )?;
+        let widest = self.depth_histogram.iter().max().copied().unwrap_or(1);
+        for (d, &count) in self.depth_histogram.iter().enumerate() {
+            let bar = (count * BAR + widest - 1) / widest;
+            writeln!(f, "  {:>3} | {:<6} {}", d, count, "#".repeat(bar))?;
+        }
+        if let Some(bh) = self.black_height {
+            writeln!(f, "black height: {}", bh)?;
+        }
+        if !self.balance_factors.is_empty() {
+            let counts: Vec<String> = self
+                .balance_factors
+                .iter()
+                .map(|(bf, n)| format!("{}: {}", bf, n))
+                .collect();
+            writeln!(f, "balance factors: {}", counts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avltree::AVLTree;
+    use crate::rbtree::RBTree;
+    use crate::tree::Tree;
+    use crate::unbalancetree::BSTree;
+
+    #[test]
+    fn unbalanced() {
+        let mut tree = BSTree::new();
+        for x in &[4, 2, 6, 1, 3, 5, 7, 8] {
+            tree.insert(*x);
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.nodes, 8);
+        assert_eq!(stats.height, tree.get_height());
+        assert_eq!(stats.leaves, tree.get_leaf_count());
+        assert_eq!(stats.depth_histogram, vec![1, 2, 4, 1]);
+        assert_eq!(stats.min_leaf_depth, 2);
+        assert_eq!(stats.median_depth, 2);
+        assert!((stats.average_depth - 13.0 / 8.0).abs() < 1e-9);
+        assert_eq!(stats.black_height, None);
+        assert!(stats.balance_factors.is_empty());
+        assert_eq!(BSTree::<i32>::new().stats(), TreeStats::default());
+    }
+
+    #[test]
+    fn balancing_data() {
+        let mut rb = RBTree::new();
+        let mut avl = AVLTree::new();
+        for x in 0..100 {
+            rb.insert(x);
+            avl.insert(x);
+        }
+        let stats = rb.stats();
+        assert_eq!(stats.nodes, 100);
+        assert_eq!(stats.height, rb.get_height());
+        // a red black tree is never more than twice as deep in one place
+        // as it is in another
+        assert!(stats.height <= 2 * (stats.min_leaf_depth + 1));
+        assert!(stats.black_height.unwrap() >= stats.height / 2);
+
+        let stats = avl.stats();
+        assert_eq!(stats.black_height, None);
+        assert_eq!(stats.balance_factors.values().sum::<usize>(), 100);
+        assert!(stats.balance_factors.keys().all(|bf| bf.abs() <= 1));
+        assert!(stats.to_string().contains("balance factors: "));
+    }
+}
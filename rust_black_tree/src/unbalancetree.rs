@@ -1,8 +1,17 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::fmt::Debug;
 
+use super::comparator::{Comparator, Natural};
+use super::cursor::Cursors;
 use super::tree::BaseTree;
 use super::tree::Tree;
+use super::tree::{
+    compact, in_order, link_balanced, sorted_arena, swap_values, validate_links, IntoIter, Iter,
+};
+use super::tree::InvariantError;
+use super::tree::{Event, Observer};
+use super::snapshot::{
+    read_snapshot, write_snapshot, SnapshotError, SnapshotKind, SnapshotNode, SnapshotValue,
+};
 
 use super::node::Node;
 use super::node::*;
@@ -25,65 +34,69 @@ macro_rules! bst {
 
 #[derive(Debug)]
 pub struct RegularNode<T> {
-    pub value: T,
+    pub value: Option<T>,
     pub ptr: usize,
     pub parent: Option<usize>,
     pub lchild: Option<usize>,
     pub rchild: Option<usize>,
-    data: Rc<RefCell<Vec<RegularNode<T>>>>,
+    /// number of nodes in the subtree rooted here, including this one
+    pub size: usize,
 }
 
 impl<T> RegularNode<T> {
-    fn new(val: T, selfptr: usize, data: Rc<RefCell<Vec<RegularNode<T>>>>) -> Self {
+    fn new(val: T, selfptr: usize) -> Self {
         Self {
-            value: val,
+            value: Some(val),
             ptr: selfptr,
             parent: None,
             lchild: None,
             rchild: None,
-            data: data,
+            size: 1,
         }
     }
 }
 
-impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for RegularNode<T> {
-    fn to_self_string(&self) -> String {
-        format!("[P:{:?} V:{:?}]", self.parent, self.value)
+impl<T> Node<T> for RegularNode<T> {
+    fn to_self_string(&self) -> String
+    where
+        T: Debug,
+    {
+        format!("[P:{:?} V:{:?}]", self.parent, self.get_value())
     }
 
-    fn to_self_string_display(&self) -> (String, usize) {
-        let s = format!("{:?}", self.value);
+    fn to_self_string_display(&self) -> (String, usize)
+    where
+        T: Debug,
+    {
+        let s = format!("{:?}", self.get_value());
         let l = s.len();
         (s, l)
     }
 
-    fn is(&self, val: &T) -> bool {
-        &self.value == val
-    }
-    fn greater(&self, val: &T) -> bool {
-        &self.value > val
-    }
-    fn lesser(&self, val: &T) -> bool {
-        &self.value < val
+    fn export_style(&self, data: &[Self]) -> NodeStyle
+    where
+        T: Debug,
+    {
+        NodeStyle {
+            label: format!("{:?}", self.get_value()),
+            fill: "white",
+            font: "black",
+            attributes: vec![("depth", self.get_depth(data).to_string())],
+        }
     }
 
     fn get_value(&self) -> &T {
-        &self.value
+        self.value.as_ref().expect("freed node")
     }
-    /**
-     * In order to return a reference to a value of a vector contained within a
-     * refcell, a raw pointer is used. The unsafe code could be avoided by
-     * replacing each call to self.get(n) with &self.data.borrow()[n] and each call
-     * to self.get_mut(n) with &mut self.data.borrow()[n]
-     */
-    fn get(&self, ptr: usize) -> &RegularNode<T> {
-        unsafe { &(*self.data.as_ptr())[ptr] }
+    fn get_value_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("freed node")
     }
-
-    fn get_mut(&self, ptr: usize) -> &mut RegularNode<T> {
-        unsafe { &mut (*self.data.as_ptr())[ptr] }
+    fn into_value(self) -> T {
+        self.value.expect("freed node")
+    }
+    fn take_value(&mut self) -> T {
+        self.value.take().expect("freed node")
     }
-
     fn get_child(&self, side: Side) -> Option<usize> {
         match side {
             Side::Left => self.lchild,
@@ -100,9 +113,6 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for RegularNode<T> {
             Side::Left => self.lchild = c,
             Side::Right => self.rchild = c,
         };
-        if let Some(child) = c {
-            self.get_mut(child).parent = Some(self.location());
-        }
     }
     fn set_parent(&mut self, p: Option<usize>) {
         self.parent = p;
@@ -115,57 +125,93 @@ impl<T: std::fmt::Debug + std::cmp::PartialOrd> Node<T> for RegularNode<T> {
     fn location(&self) -> usize {
         self.ptr
     }
+
+    fn set_location(&mut self, ptr: usize) {
+        self.ptr = ptr;
+    }
+
+    fn subtree_size(&self) -> usize {
+        self.size
+    }
+
+    fn set_subtree_size(&mut self, size: usize) {
+        self.size = size;
+    }
 }
 
 /**
  * Arena based memory tree structure
 */
 #[derive(Debug)]
-pub struct BSTree<T> {
+pub struct BSTree<T, C = Natural> {
     root: Option<usize>,
     size: usize,
-    data: Rc<RefCell<Vec<RegularNode<T>>>>,
+    data: Vec<RegularNode<T>>,
     free: Vec<usize>,
+    cmp: C,
+    observer: Observer<Self>,
 }
 
-impl<T> Tree<T> for BSTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
-    fn new() -> Self {
+impl<T> SnapshotNode<T> for RegularNode<T> {
+    const KIND: SnapshotKind = SnapshotKind::Unbalanced;
+
+    fn blank(val: T, ptr: usize) -> Self {
+        RegularNode::new(val, ptr)
+    }
+
+    // a plain node keeps nothing beyond its value and links
+    fn write_extra(&self, _out: &mut Vec<u8>) {}
+
+    fn read_extra(&mut self, _input: &mut &[u8]) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+impl<T, C: Comparator<T>> Tree<T> for BSTree<T, C> {
+    fn with_comparator(cmp: C) -> Self {
         Self {
             root: None,
-            data: Rc::new(RefCell::new(Vec::new())),
+            data: Vec::new(),
             size: 0,
             free: Vec::new(),
+            cmp,
+            observer: Observer::default(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), InvariantError> {
+        validate_links(self)
+    }
+
+    fn from_sorted_iter_by<I: IntoIterator<Item = T>>(iter: I, cmp: C) -> Self {
+        let mut data = sorted_arena(iter, &cmp, RegularNode::new);
+        let root = link_balanced(&mut data, &mut |_: &mut [RegularNode<T>], _, _| {});
+        Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp,
+            observer: Observer::default(),
         }
     }
 }
 
-impl<T> BaseTree<T> for BSTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
+impl<T, C: Comparator<T>> Cursors<T> for BSTree<T, C> {}
+
+impl<T, C: Comparator<T>> BaseTree<T> for BSTree<T, C> {
     type MNode = RegularNode<T>;
-    /**
-     * In order to return a reference to a value of a vector contained within a refcell, a raw
-     * pointer is used. The unsafe code could be avoided by replacing each call to self.get(n) with
-     * &self.data.borrow()[n] and each call to self.get_mut(n) with &mut self.data.borrow()[n]. This
-     * allows us to do the same thing with less keystrokes. It does make the program not
-     * thread-safe, but a this data structure is a pretty terrible choice for a multi-threaded data
-     * structure anyways, since re-balancing can require that most of the tree be locked to one
-     * thread during an insertion or deletion
-     */
+    type Cmp = C;
     fn get(&self, val: usize) -> &Self::MNode {
-        unsafe { &(*self.data.as_ptr())[val] }
+        &self.data[val]
+    }
+
+    fn get_mut(&mut self, val: usize) -> &mut Self::MNode {
+        &mut self.data[val]
     }
 
-    fn get_mut(&self, val: usize) -> &mut Self::MNode {
-        unsafe { &mut (*self.data.as_ptr())[val] }
+    fn get_data(&self) -> &[Self::MNode] {
+        &self.data
     }
 
     fn get_root(&self) -> Option<usize> {
@@ -180,10 +226,6 @@ where
         self.size = (self.size as isize + amount) as usize;
     }
 
-    fn attach_child(&self, p: usize, c: usize, side: Side) {
-        self.get_mut(p).set_child(c, side)
-    }
-
     fn rebalance_ins(&mut self, _n: usize) {}
 
     fn rebalance_del(&mut self, _n: usize, _child: usize) {}
@@ -191,82 +233,154 @@ where
     fn delete_replace(&mut self, n: usize) -> usize {
         let node = self.get(n);
         match (node.lchild, node.rchild) {
-            (Some(lc), Some(rc)) => {
-                let p = node.parent;
-                let successor = self.get(rc).find_min();
+            (Some(_lc), Some(rc)) => {
+                // the successor has no left child, so it can be unlinked
+                // directly once it has traded values with n
+                let successor = self.get(rc).find_min(&self.data);
                 self.delete_replace(successor);
-                self.data.borrow_mut().swap(successor, n);
-
-                self.get_mut(n).lchild = Some(lc);
-                self.get_mut(n).rchild = Some(rc);
-                self.get_mut(n).parent = p;
-                self.get_mut(n).ptr = n;
+                swap_values(&mut self.data, successor, n);
                 return successor;
             }
-            (None, Some(_rc)) => self.replace_node(n, self.get(n).rchild),
-            (Some(_lc), None) => self.replace_node(n, self.get(n).lchild),
+            (None, Some(rc)) => self.replace_node(n, Some(rc)),
+            (Some(lc), None) => self.replace_node(n, Some(lc)),
             (None, None) => self.replace_node(n, None),
         };
         n
     }
 
     fn replace_node(&mut self, to_delete: usize, to_attach: Option<usize>) {
-        let node = self.get(to_delete);
-        if let Some(p) = node.parent {
-            if node.is_child(Side::Left) {
-                self.get_mut(p).lchild = to_attach;
-            } else {
-                self.get_mut(p).rchild = to_attach;
-            }
+        let parent = self.get(to_delete).parent;
+        if let Some(p) = parent {
+            let side = self.get(to_delete).side(&self.data);
+            self.get_mut(p).set_child_opt(to_attach, side);
         } else {
             self.root = to_attach;
         }
+        if let Some(c) = to_attach {
+            self.get_mut(c).parent = parent;
+        }
     }
 
     fn get_size(&self) -> usize {
-        return self.size;
+        self.size
+    }
+
+    fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
+    fn observer(&mut self) -> Option<&mut Observer<Self>> {
+        Some(&mut self.observer)
     }
 
     fn create_node(&mut self, val: T) -> usize {
-        // update this so it reuses deleted slots
-        if self.free.len() > 0 {
-            let n = self.free.pop().expect("pop should not fail if len > 0");
-            let mut d = self.get_mut(n);
-            d.ptr = n;
-            d.lchild = None;
-            d.rchild = None;
-            d.parent = None;
+        if let Some(n) = self.free.pop() {
+            self.data[n] = RegularNode::new(val, n);
             n
         } else {
-            let loc = self.data.borrow().len();
-            self.data
-                .borrow_mut()
-                .push(RegularNode::new(val, loc, self.data.clone()));
+            let loc = self.data.len();
+            self.data.push(RegularNode::new(val, loc));
             loc
         }
     }
 
-    fn delete_node(&mut self, index: usize) {
+    fn delete_node(&mut self, index: usize) -> T {
         self.free.push(index);
+        self.data[index].take_value()
+    }
+}
+
+impl<T: PartialOrd> BSTree<T> {
+    pub fn new() -> Self {
+        Self::with_comparator(Natural)
+    }
+
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_sorted_iter_by(iter, Natural)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: SnapshotValue,
+    {
+        let (data, root) = read_snapshot(bytes)?;
+        let tree = Self {
+            root,
+            size: data.len(),
+            data,
+            free: Vec::new(),
+            cmp: Natural,
+            observer: Observer::default(),
+        };
+        tree.validate().map_err(|_| SnapshotError::BadShape)?;
+        Ok(tree)
+    }
+}
+
+impl<T: PartialOrd> Default for BSTree<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<T> BSTree<T>
-where
-    T: PartialOrd,
-    T: PartialEq,
-    T: std::fmt::Debug,
-{
+impl<T, C: Comparator<T>> BSTree<T, C> {
+    /// calls f with each insert and delete, and the tree right after it
+    pub fn set_observer<F>(&mut self, f: F)
+    where
+        F: FnMut(&Event, &Self) + Send + Sync + 'static,
+    {
+        self.observer = Observer::new(f);
+    }
+
+    /// stops reporting events
+    pub fn clear_observer(&mut self) {
+        self.observer = Observer::default();
+    }
+
+    /// a binary snapshot keeping the exact shape of the tree
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: SnapshotValue,
+    {
+        write_snapshot(&self.data, self.root)
+    }
+
+    /// drops the slots left behind by deleted nodes
+    pub fn shrink_to_fit(&mut self) {
+        compact(&mut self.data, &mut self.root);
+        self.free.clear();
+        self.free.shrink_to_fit();
+    }
+
     #[allow(dead_code)]
     fn get_size_recursive(&self) -> usize {
         if let Some(root) = self.root {
-            self.get(root).get_size()
+            self.get(root).get_size(&self.data)
         } else {
             0
         }
     }
 }
 
+impl<T, C: Comparator<T>> IntoIterator for BSTree<T, C> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let order = in_order(&self);
+        IntoIter::new(self.data, order)
+    }
+}
+
+impl<'a, T, C: Comparator<T>> IntoIterator for &'a BSTree<T, C> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, BSTree<T, C>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -348,16 +462,16 @@ mod tests {
 
         tree.delete(0);
         double_size_test(&tree, 14);
-        assert_eq!(tree.data.borrow().len(), 15);
+        assert_eq!(tree.data.len(), 15);
         tree.insert(0);
-        assert_eq!(tree.data.borrow().len(), 15);
+        assert_eq!(tree.data.len(), 15);
         double_size_test(&tree, 15);
 
         tree.delete(50);
         double_size_test(&tree, 14);
-        assert_eq!(tree.data.borrow().len(), 15);
+        assert_eq!(tree.data.len(), 15);
         tree.insert(50);
-        assert_eq!(tree.data.borrow().len(), 15);
+        assert_eq!(tree.data.len(), 15);
         double_size_test(&tree, 15);
     }
 
@@ -370,4 +484,115 @@ mod tests {
         tree.delete(85);
         assert_eq!(tree.to_string(), "([P:None V:50] ([P:Some(0) V:25] ([P:Some(1) V:15] ([P:Some(3) V:0] () ()) ([P:Some(3) V:20] () ())) ([P:Some(1) V:35] ([P:Some(4) V:30] () ()) ([P:Some(4) V:40] () ()))) ([P:Some(0) V:75] ([P:Some(2) V:65] ([P:Some(5) V:60] () ()) ([P:Some(5) V:70] () ())) ()))");
     }
+
+    #[test]
+    fn test_iter() {
+        let tree = make_fake_tree_node_no_balance();
+        let v: Vec<i32> = tree.iter().cloned().collect();
+        assert_eq!(v, vec![0, 15, 20, 25, 30, 35, 40, 50, 60, 65, 70, 75, 80, 85, 100]);
+        let v: Vec<i32> = tree.iter().rev().cloned().collect();
+        assert_eq!(v, vec![100, 85, 80, 75, 70, 65, 60, 50, 40, 35, 30, 25, 20, 15, 0]);
+
+        let mut it = tree.iter();
+        assert_eq!(it.next(), Some(&0));
+        assert_eq!(it.next_back(), Some(&100));
+        assert_eq!(it.next(), Some(&15));
+        assert_eq!(it.count(), 12);
+
+        assert_eq!(BSTree::<i32>::new().iter().next(), None);
+    }
+
+    #[test]
+    fn test_range() {
+        let tree = make_fake_tree_node_no_balance();
+        let v: Vec<&i32> = tree.range(20..65).collect();
+        assert_eq!(v, vec![&20, &25, &30, &35, &40, &50, &60]);
+        let v: Vec<&i32> = tree.range(21..=65).rev().collect();
+        assert_eq!(v, vec![&65, &60, &50, &40, &35, &30, &25]);
+        let v: Vec<&i32> = tree.range(..15).collect();
+        assert_eq!(v, vec![&0]);
+        let v: Vec<&i32> = tree.range(86..).collect();
+        assert_eq!(v, vec![&100]);
+        assert_eq!(tree.range(41..50).next(), None);
+        assert_eq!(tree.range(50..50).next(), None);
+        assert_eq!(tree.range(101..).next(), None);
+        assert_eq!(tree.range(..).count(), 15);
+    }
+
+    #[test]
+    fn test_order_statistics() {
+        let mut tree = make_fake_tree_node_no_balance();
+        assert_eq!(tree.rank(&0), 0);
+        assert_eq!(tree.rank(&50), 7);
+        assert_eq!(tree.rank(&51), 8);
+        assert_eq!(tree.rank(&1000), 15);
+        assert_eq!(tree.select(0), Some(&0));
+        assert_eq!(tree.select(7), Some(&50));
+        assert_eq!(tree.select(14), Some(&100));
+        assert_eq!(tree.select(15), None);
+        assert_eq!(tree.count_range(20..65), 7);
+        assert_eq!(tree.count_range(21..=65), 7);
+        assert_eq!(tree.count_range(..), 15);
+        assert_eq!(tree.count_range(50..50), 0);
+
+        tree.delete(50);
+        tree.delete(0);
+        assert_eq!(tree.get(tree.root.unwrap()).size, 13);
+        assert_eq!(tree.select(0), Some(&15));
+        assert_eq!(tree.rank(&60), 6);
+        for (k, v) in tree.iter().enumerate() {
+            assert_eq!(tree.select(k), Some(v));
+            assert_eq!(tree.rank(v), k);
+        }
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let tree = make_fake_tree_node_no_balance();
+        let mut total = 0;
+        for x in &tree {
+            total += x;
+        }
+        assert_eq!(total, 750);
+        let v: Vec<i32> = tree.into_iter().collect();
+        assert_eq!(v, vec![0, 15, 20, 25, 30, 35, 40, 50, 60, 65, 70, 75, 80, 85, 100]);
+    }
+
+    #[test]
+    fn observer() {
+        use std::sync::{Arc, Mutex};
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut tree = BSTree::new();
+        let seen = log.clone();
+        tree.set_observer(move |event, tree: &BSTree<i32>| {
+            seen.lock().unwrap().push((*event, tree.to_string()));
+        });
+        tree.insert(2);
+        tree.insert(1);
+        tree.delete(2);
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[1].0, Event::Inserted(1));
+        // the tree is handed over after the node has been linked in
+        assert!(log[1].1.contains("V:1"));
+        assert_eq!(log[2].0, Event::Deleted(0));
+    }
+
+    #[test]
+    fn send_to_thread() {
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<BSTree<i32>>();
+
+        let mut tree = BSTree::new();
+        for x in 0..50 {
+            tree.insert(x);
+        }
+        let handle = std::thread::spawn(move || {
+            tree.delete(25);
+            tree
+        });
+        let tree = handle.join().unwrap();
+        assert!(!tree.contains(&25));
+        assert_eq!(tree.iter().count(), 49);
+    }
 }
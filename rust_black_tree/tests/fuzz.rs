@@ -0,0 +1,310 @@
+/**
+ * Differential fuzzing of the trees against std's BTreeSet. Random sequences
+ * of operations run on a tree and a BTreeSet side by side, and after every
+ * step the results, the contents and the structural invariants of the tree
+ * are checked.
+ *
+ * A failing sequence is shrunk to as few operations as still fail, and saved
+ * under tests/regressions so it can be checked in. Every file in there is
+ * replayed against all of the trees by replay_regressions.
+ *
+ * The trees are driven through OrderedSet, so BTree runs alongside the
+ * binary trees.
+ *
+ * FUZZ_CASES and FUZZ_SEED override the number of sequences and the seed of
+ * the first one, for longer runs than the default
+ */
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use rust_black_trees::orderedset::OrderedSet;
+use rust_black_trees::{avltree::AVLTree, btree::BTree, rbtree::RBTree, unbalancetree::BSTree};
+use rust_black_trees::{aatree::AATree, scapegoattree::ScapegoatTree, splaytree::SplayTree, treap::Treap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Insert(i32),
+    Delete(i32),
+    Contains(i32),
+    Successor(i32),
+    Floor(i32),
+    PopFirst,
+    PopLast,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Insert(v) => write!(f, "insert {}", v),
+            Op::Delete(v) => write!(f, "delete {}", v),
+            Op::Contains(v) => write!(f, "contains {}", v),
+            Op::Successor(v) => write!(f, "successor {}", v),
+            Op::Floor(v) => write!(f, "floor {}", v),
+            Op::PopFirst => write!(f, "pop_first"),
+            Op::PopLast => write!(f, "pop_last"),
+        }
+    }
+}
+
+fn parse_op(line: &str) -> Option<Op> {
+    let mut words = line.split_whitespace();
+    let name = words.next()?;
+    match name {
+        "pop_first" => return Some(Op::PopFirst),
+        "pop_last" => return Some(Op::PopLast),
+        _ => (),
+    }
+    let val = words.next()?.parse().ok()?;
+    match name {
+        "insert" => Some(Op::Insert(val)),
+        "delete" => Some(Op::Delete(val)),
+        "contains" => Some(Op::Contains(val)),
+        "successor" => Some(Op::Successor(val)),
+        "floor" => Some(Op::Floor(val)),
+        _ => None,
+    }
+}
+
+// a small value range so inserts and deletes keep running into each other
+fn random_ops(seed: u64) -> Vec<Op> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let range = rng.gen_range(4, 200);
+    let len = rng.gen_range(1, 400);
+    (0..len)
+        .map(|_| {
+            let val = rng.gen_range(0, range);
+            match rng.gen_range(0, 20) {
+                0..=9 => Op::Insert(val),
+                10..=14 => Op::Delete(val),
+                15 => Op::Contains(val),
+                16 => Op::Successor(val),
+                17 => Op::Floor(val),
+                18 => Op::PopFirst,
+                _ => Op::PopLast,
+            }
+        })
+        .collect()
+}
+
+/// runs the operations, returning a description of the first step at which
+/// the tree and the BTreeSet disagree or the tree breaks an invariant
+fn run<B: OrderedSet<i32> + Default>(ops: &[Op]) -> Result<(), String> {
+    let mut tree = B::default();
+    let mut set = BTreeSet::new();
+    for (step, op) in ops.iter().enumerate() {
+        let fail = |msg: String| Err(format!("step {} ({}): {}", step, op, msg));
+        match *op {
+            Op::Insert(v) => {
+                tree.insert(v);
+                set.insert(v);
+            }
+            Op::Delete(v) => {
+                let (got, want) = (tree.delete(v), set.remove(&v));
+                if got != want {
+                    return fail(format!("delete returned {}, expected {}", got, want));
+                }
+            }
+            Op::Contains(v) => {
+                let (got, want) = (tree.contains(&v), set.contains(&v));
+                if got != want {
+                    return fail(format!("contains returned {}, expected {}", got, want));
+                }
+            }
+            Op::Successor(v) => {
+                let (got, want) = (tree.successor(&v), set.range(v + 1..).next());
+                if got != want {
+                    return fail(format!("successor returned {:?}, expected {:?}", got, want));
+                }
+            }
+            Op::Floor(v) => {
+                let (got, want) = (tree.floor(&v), set.range(..=v).next_back());
+                if got != want {
+                    return fail(format!("floor returned {:?}, expected {:?}", got, want));
+                }
+            }
+            Op::PopFirst => {
+                let (got, want) = (tree.pop_first(), set.pop_first());
+                if got != want {
+                    return fail(format!("pop_first returned {:?}, expected {:?}", got, want));
+                }
+            }
+            Op::PopLast => {
+                let (got, want) = (tree.pop_last(), set.pop_last());
+                if got != want {
+                    return fail(format!("pop_last returned {:?}, expected {:?}", got, want));
+                }
+            }
+        }
+        if let Err(e) = tree.validate() {
+            return fail(e.to_string());
+        }
+        if tree.get_size() != set.len() {
+            return fail(format!("size {}, expected {}", tree.get_size(), set.len()));
+        }
+        if !tree.iter().eq(set.iter()) {
+            return fail(format!(
+                "contents {:?}, expected {:?}",
+                tree.iter().collect::<Vec<_>>(),
+                set
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// like run, but a panic inside of the tree counts as a failure too
+fn run_caught<B: OrderedSet<i32> + Default>(ops: &[Op]) -> Result<(), String> {
+    std::panic::catch_unwind(|| run::<B>(ops)).unwrap_or_else(|e| {
+        let msg = e
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| e.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(format!("panicked: {}", msg))
+    })
+}
+
+/**
+ * Drops chunks of operations for as long as the sequence keeps failing,
+ * halving the chunk size whenever no chunk of the current size can go
+ */
+fn shrink<B: OrderedSet<i32> + Default>(mut ops: Vec<Op>) -> Vec<Op> {
+    let mut chunk = ops.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        let mut removed = false;
+        while start < ops.len() {
+            let end = std::cmp::min(start + chunk, ops.len());
+            let mut fewer = ops[..start].to_vec();
+            fewer.extend_from_slice(&ops[end..]);
+            if run_caught::<B>(&fewer).is_err() {
+                ops = fewer;
+                removed = true;
+            } else {
+                start += chunk;
+            }
+        }
+        if !removed {
+            chunk /= 2;
+        }
+    }
+    ops
+}
+
+fn regressions_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("regressions")
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn fuzz<B: OrderedSet<i32> + Default>(name: &str) {
+    let first = env_or("FUZZ_SEED", 0);
+    let cases = env_or("FUZZ_CASES", 200);
+    for seed in first..first + cases {
+        let ops = random_ops(seed);
+        if run_caught::<B>(&ops).is_err() {
+            let ops = shrink::<B>(ops);
+            let err = run_caught::<B>(&ops).unwrap_err();
+            let path = regressions_dir().join(format!("{}-{}.txt", name, seed));
+            let mut replay = format!("# {} seed {}\n# {}\n", name, seed, err);
+            for op in &ops {
+                replay += &format!("{}\n", op);
+            }
+            fs::create_dir_all(regressions_dir()).expect("create regressions dir");
+            fs::write(&path, replay).expect("write replay file");
+            panic!(
+                "{} failed with seed {} at {}, replay saved to {}",
+                name,
+                seed,
+                err,
+                path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn fuzz_rbtree() {
+    fuzz::<RBTree<i32>>("rb");
+}
+
+#[test]
+fn fuzz_avltree() {
+    fuzz::<AVLTree<i32>>("avl");
+}
+
+#[test]
+fn fuzz_bstree() {
+    fuzz::<BSTree<i32>>("bst");
+}
+
+#[test]
+fn fuzz_splaytree() {
+    fuzz::<SplayTree<i32>>("splay");
+}
+
+#[test]
+fn fuzz_treap() {
+    fuzz::<Treap<i32>>("treap");
+}
+
+#[test]
+fn fuzz_aatree() {
+    fuzz::<AATree<i32>>("aa");
+}
+
+#[test]
+fn fuzz_scapegoattree() {
+    fuzz::<ScapegoatTree<i32>>("sg");
+}
+
+#[test]
+fn fuzz_btree() {
+    // a small fanout splits and merges nodes even in short sequences
+    fuzz::<BTree<i32, 3>>("btree3");
+    fuzz::<BTree<i32>>("btree");
+}
+
+#[test]
+fn replay_regressions() {
+    let entries = match fs::read_dir(regressions_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries {
+        let path = entry.expect("regression entry").path();
+        let text = fs::read_to_string(&path).expect("read replay file");
+        let ops: Vec<Op> = text
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.trim().is_empty())
+            .map(|l| parse_op(l).unwrap_or_else(|| panic!("bad line {:?} in {:?}", l, path)))
+            .collect();
+        for res in &[
+            run_caught::<RBTree<i32>>(&ops),
+            run_caught::<AVLTree<i32>>(&ops),
+            run_caught::<BSTree<i32>>(&ops),
+            run_caught::<SplayTree<i32>>(&ops),
+            run_caught::<Treap<i32>>(&ops),
+            run_caught::<AATree<i32>>(&ops),
+            run_caught::<ScapegoatTree<i32>>(&ops),
+            run_caught::<BTree<i32, 3>>(&ops),
+            run_caught::<BTree<i32>>(&ops),
+        ] {
+            if let Err(e) = res {
+                panic!("{} fails again: {}", path.display(), e);
+            }
+        }
+    }
+}